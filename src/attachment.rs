@@ -0,0 +1,268 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use anyhow::Context;
+
+/// A file embedded in a script's [Fonts] section or attached to an MKV
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Decodes the files in a [Fonts] or [Graphics] section. Each file starts with
+/// a `fontname: ` or `filename: ` line followed by lines of the SSA flavor of
+/// uuencoding, where every 6 bits are stored as a character offset by 33.
+pub fn decode_embedded(lines: &[String]) -> anyhow::Result<Vec<Attachment>> {
+    let mut attachments = Vec::new();
+    let mut current: Option<(String, String)> = None;
+
+    for line in lines {
+        let header = line
+            .strip_prefix("fontname:")
+            .or_else(|| line.strip_prefix("filename:"));
+        if let Some(name) = header {
+            if let Some((name, encoded)) = current.take() {
+                attachments.push(Attachment {
+                    data: uudecode(&encoded).context(format!("invalid data for {name}"))?,
+                    name,
+                });
+            }
+            current = Some((name.trim().to_string(), String::new()));
+        } else if let Some((_, encoded)) = current.as_mut() {
+            encoded.push_str(line);
+        }
+    }
+
+    if let Some((name, encoded)) = current {
+        attachments.push(Attachment {
+            data: uudecode(&encoded).context(format!("invalid data for {name}"))?,
+            name,
+        });
+    }
+
+    Ok(attachments)
+}
+
+/// Encodes a file for a [Fonts] section, the inverse of [`decode_embedded`]
+pub fn encode_embedded(attachment: &Attachment) -> Vec<String> {
+    let encoded = uuencode(&attachment.data);
+    let mut lines = vec![format!("fontname: {}", attachment.name)];
+    lines.extend(
+        encoded
+            .as_bytes()
+            .chunks(80)
+            .map(|x| String::from_utf8_lossy(x).into_owned()),
+    );
+    lines
+}
+
+fn uudecode(encoded: &str) -> anyhow::Result<Vec<u8>> {
+    let values = encoded
+        .bytes()
+        .map(|x| {
+            x.checked_sub(33)
+                .filter(|x| *x < 64)
+                .context(format!("invalid character: {}", x as char))
+        })
+        .collect::<anyhow::Result<Vec<u8>>>()?;
+
+    let mut data = Vec::with_capacity(values.len() * 3 / 4);
+    for chunk in values.chunks(4) {
+        let mut group = [0u8; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let bytes = [
+            (group[0] << 2) | (group[1] >> 4),
+            (group[1] << 4) | (group[2] >> 2),
+            (group[2] << 6) | group[3],
+        ];
+        // a trailing group of n characters carries n - 1 bytes
+        data.extend_from_slice(&bytes[..chunk.len().saturating_sub(1)]);
+    }
+
+    Ok(data)
+}
+
+fn uuencode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len() * 4 / 3 + 4);
+    for chunk in data.chunks(3) {
+        let mut group = [0u8; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let values = [
+            group[0] >> 2,
+            ((group[0] & 0x03) << 4) | (group[1] >> 4),
+            ((group[1] & 0x0f) << 2) | (group[2] >> 6),
+            group[2] & 0x3f,
+        ];
+        for value in &values[..=chunk.len()] {
+            encoded.push(char::from(value + 33));
+        }
+    }
+    encoded
+}
+
+const EBML_SEGMENT: u32 = 0x1853_8067;
+const EBML_ATTACHMENTS: u32 = 0x1941_A469;
+const EBML_ATTACHED_FILE: u32 = 0x61A7;
+const EBML_FILE_NAME: u32 = 0x466E;
+const EBML_FILE_DATA: u32 = 0x465C;
+
+/// Reads all attachments from a Matroska file, seeking past everything else
+pub fn mkv_attachments(path: &Path) -> anyhow::Result<Vec<Attachment>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut attachments = Vec::new();
+
+    while let Some(id) = read_element_id(&mut reader)? {
+        let size = read_element_size(&mut reader)?;
+        match (id, size) {
+            // descend into the segment, whether its size is known or not
+            (EBML_SEGMENT | EBML_ATTACHMENTS, _) => {}
+            (EBML_ATTACHED_FILE, Some(size)) => {
+                attachments.push(read_attached_file(&mut reader, size)?);
+            }
+            (_, Some(size)) => {
+                reader.seek_relative(i64::try_from(size)?)?;
+            }
+            // children of unknown sized elements other than the segment can't
+            // be skipped reliably, so stop with what has been found so far
+            (_, None) => break,
+        }
+    }
+
+    Ok(attachments)
+}
+
+fn read_attached_file(reader: &mut BufReader<File>, size: u64) -> anyhow::Result<Attachment> {
+    let end = reader.stream_position()? + size;
+    let mut name = String::new();
+    let mut data = Vec::new();
+
+    while reader.stream_position()? < end {
+        let id = read_element_id(reader)?.context("unexpected end of attachment")?;
+        let size = read_element_size(reader)?.context("attachment field has unknown size")?;
+        match id {
+            EBML_FILE_NAME => {
+                let mut buf = vec![0; usize::try_from(size)?];
+                reader.read_exact(&mut buf)?;
+                name = String::from_utf8_lossy(&buf)
+                    .trim_end_matches('\0')
+                    .to_string();
+            }
+            EBML_FILE_DATA => {
+                data = vec![0; usize::try_from(size)?];
+                reader.read_exact(&mut data)?;
+            }
+            _ => reader.seek_relative(i64::try_from(size)?)?,
+        }
+    }
+
+    reader.seek(SeekFrom::Start(end))?;
+    Ok(Attachment { name, data })
+}
+
+/// Reads an EBML element ID, keeping its length marker bits as Matroska IDs
+/// are conventionally written that way. Returns `None` at end of file.
+fn read_element_id(reader: &mut impl Read) -> anyhow::Result<Option<u32>> {
+    let mut first = [0u8; 1];
+    if reader.read(&mut first)? == 0 {
+        return Ok(None);
+    }
+    let len = first[0].leading_zeros() + 1;
+    anyhow::ensure!(len <= 4, "invalid EBML element ID");
+
+    let mut id = u32::from(first[0]);
+    for _ in 1..len {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        id = (id << 8) | u32::from(byte[0]);
+    }
+    Ok(Some(id))
+}
+
+/// Reads an EBML element size. Returns `None` for the reserved unknown size.
+fn read_element_size(reader: &mut impl Read) -> anyhow::Result<Option<u64>> {
+    let mut first = [0u8; 1];
+    reader.read_exact(&mut first)?;
+    let len = first[0].leading_zeros() + 1;
+    anyhow::ensure!(len <= 8, "invalid EBML element size");
+
+    let mut size = u64::from(first[0]) & (0xff >> len);
+    let mut unknown = size == (0xff >> len);
+    for _ in 1..len {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        size = (size << 8) | u64::from(byte[0]);
+        unknown &= byte[0] == 0xff;
+    }
+    Ok((!unknown).then_some(size))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(b"")]
+    #[case(b"a")]
+    #[case(b"ab")]
+    #[case(b"abc")]
+    #[case(b"\x00\xff\x10\x80\x7f")]
+    fn test_uuencode_roundtrip(#[case] data: &[u8]) {
+        let encoded = uuencode(data);
+        assert!(encoded.bytes().all(|x| (33..97).contains(&x)));
+        assert_eq!(uudecode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn test_embedded_roundtrip() {
+        let attachment = Attachment {
+            name: "font_0.ttf".to_string(),
+            data: (0..=255).cycle().take(1000).collect(),
+        };
+        let lines = encode_embedded(&attachment);
+        assert!(lines[1..].iter().all(|x| x.len() <= 80));
+        assert_eq!(decode_embedded(&lines).unwrap(), vec![attachment]);
+    }
+
+    #[test]
+    fn test_mkv_attachments() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/fonts");
+        let attachments = mkv_attachments(&dir.join("SubassTest.mkv")).unwrap();
+        let names: Vec<&str> = attachments.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, ["SubassTest.ttf", "SubassTestCFF.otf"]);
+        for x in &attachments {
+            assert_eq!(x.data, std::fs::read(dir.join(&x.name)).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_mkv_attachments_truncated() {
+        // the attached file claims more data than the file has
+        let path =
+            std::env::temp_dir().join(format!("subass-truncated-{}.mkv", std::process::id()));
+        let data = std::fs::read(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/fonts/SubassTest.mkv"),
+        )
+        .unwrap();
+        std::fs::write(&path, &data[..200]).unwrap();
+        let result = mkv_attachments(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[rstest]
+    #[case(&[0x81], Some(1))]
+    #[case(&[0x40, 0x02], Some(2))]
+    #[case(&[0xff], None)]
+    #[case(&[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], None)]
+    fn test_read_element_size(#[case] bytes: &[u8], #[case] should: Option<u64>) {
+        let result = read_element_size(&mut &bytes[..]).unwrap();
+        assert_eq!(result, should);
+    }
+}
@@ -0,0 +1,482 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use strum::IntoEnumIterator;
+
+use crate::classify::Classifier;
+use crate::classify::EventKind;
+use crate::common::Boolean;
+use crate::event::EventStrict;
+use crate::event::EventType;
+use crate::lint::Location;
+use crate::style::StyleField;
+use crate::tag;
+use crate::tag::TextPart;
+use crate::time::AssTime;
+use crate::AssScript;
+
+/// Defects [`AssScript::autofix`] can repair
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    strum::Display,
+    strum::EnumString,
+    strum::EnumIter,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum AutoFix {
+    /// Swap Start and End when End is earlier
+    EndBeforeStart,
+    /// Remove events that are never shown, except comments. Not applied by
+    /// default, as events are often hidden on purpose.
+    ZeroDuration,
+    /// Replace numbers other than -1 and 0 in boolean style fields with the
+    /// value renderers read them as
+    InvalidBoolean,
+    /// Remove `{}`
+    EmptyBlocks,
+    /// Replace `\n` with what it renders as: a line break with `WrapStyle: 2`,
+    /// a space otherwise
+    LineBreaks,
+    /// Remove spaces at the end of lines
+    TrailingSpaces,
+    /// Merge an event into the previous one when they only differ in timing
+    /// and follow each other without a gap
+    MergeDuplicates,
+    /// Extend dialogue up to the next line when the gap is below `max_gap`,
+    /// so subtitles don't flicker
+    SnapGaps,
+    /// Extend dialogue shorter than `min_duration`, without overlapping the
+    /// next line
+    MinDuration,
+}
+
+#[derive(Debug, Clone)]
+pub struct FixOptions {
+    pub fixes: BTreeSet<AutoFix>,
+    pub max_gap: AssTime,
+    pub min_duration: AssTime,
+}
+
+impl Default for FixOptions {
+    fn default() -> Self {
+        Self {
+            fixes: AutoFix::iter()
+                .filter(|x| *x != AutoFix::ZeroDuration)
+                .collect(),
+            max_gap: AssTime::from_millis(300),
+            min_duration: AssTime::from_millis(500),
+        }
+    }
+}
+
+/// A change made by a fix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub fix: AutoFix,
+    /// Index of the event in the script before fixing
+    pub location: Location,
+    pub message: String,
+}
+
+/// What an invalid boolean is read as, if it is a number: renderers treat
+/// any non-zero number as true
+pub(crate) fn repair_boolean(value: &Boolean) -> Option<Boolean> {
+    let Boolean::Unknown(x) = value else {
+        return None;
+    };
+    let x = x.trim().parse::<i64>().ok()?;
+    Some(if x == 0 {
+        Boolean::False
+    } else {
+        Boolean::True
+    })
+}
+
+fn remove_empty_blocks(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for part in tag::split_text(text) {
+        match part {
+            TextPart::Override("") => {}
+            TextPart::Override(x) => {
+                out.push('{');
+                out.push_str(x);
+                out.push('}');
+            }
+            TextPart::Text(x) => out.push_str(x),
+        }
+    }
+    out
+}
+
+/// Rewrites the plain text parts of `text` with `f`, which is also told
+/// whether the part is the last visible one
+fn map_text(text: &str, mut f: impl FnMut(&str, bool) -> String) -> String {
+    let parts = tag::split_text(text);
+    let last = parts.iter().rposition(|x| matches!(x, TextPart::Text(_)));
+    let mut out = String::with_capacity(text.len());
+    for (i, part) in parts.into_iter().enumerate() {
+        match part {
+            TextPart::Override(x) => {
+                out.push('{');
+                out.push_str(x);
+                out.push('}');
+            }
+            TextPart::Text(x) => out.push_str(&f(x, Some(i) == last)),
+        }
+    }
+    out
+}
+
+fn fix_line_breaks(text: &str, hard: bool) -> String {
+    let replacement = if hard { "\\N" } else { " " };
+    map_text(text, |x, _| x.replace("\\n", replacement))
+}
+
+fn trim_trailing_spaces(text: &str) -> String {
+    map_text(text, |x, last| {
+        let mut lines: Vec<&str> = x.split("\\N").collect();
+        let count = lines.len();
+        for (i, line) in lines.iter_mut().enumerate() {
+            if last || i + 1 < count {
+                *line = line.trim_end();
+            }
+        }
+        lines.join("\\N")
+    })
+}
+
+impl Change {
+    fn event(fix: AutoFix, index: usize, message: String) -> Self {
+        Self {
+            fix,
+            location: Location::Event(index),
+            message,
+        }
+    }
+}
+
+/// A fix that rewrites event text
+type TextFix<'a> = (AutoFix, &'a dyn Fn(&str) -> String);
+
+impl AssScript {
+    /// Repairs the defects enabled in `options`, returning what changed.
+    /// Timing fixes other than [`AutoFix::EndBeforeStart`] and
+    /// [`AutoFix::ZeroDuration`] only touch dialogue, since signs are usually
+    /// timed to the frame on purpose.
+    pub fn autofix(&mut self, options: &FixOptions) -> anyhow::Result<Vec<Change>> {
+        let hard_breaks = self.script_info("WrapStyle").map(str::trim) == Some("2");
+        let text_fixes: [TextFix; 3] = [
+            (AutoFix::EmptyBlocks, &remove_empty_blocks),
+            (AutoFix::LineBreaks, &|x| fix_line_breaks(x, hard_breaks)),
+            (AutoFix::TrailingSpaces, &trim_trailing_spaces),
+        ];
+        let mut changes = Vec::new();
+        let mut removed = vec![false; self.events.entries.len()];
+
+        if options.fixes.contains(&AutoFix::InvalidBoolean) {
+            fix_booleans(self, &mut changes);
+        }
+        for (i, event) in self.events.entries.iter_mut().enumerate() {
+            for (fix, f) in &text_fixes {
+                if !options.fixes.contains(fix) {
+                    continue;
+                }
+                let text = f(&event.text);
+                if text != event.text {
+                    changes.push(Change::event(*fix, i, format!("text changed to {text}")));
+                    event.text = text;
+                }
+            }
+
+            let Some((start, end)) = times(event) else {
+                continue;
+            };
+            if options.fixes.contains(&AutoFix::EndBeforeStart) && end < start {
+                event.set_start_time(end);
+                event.set_end_time(start);
+                changes.push(Change::event(
+                    AutoFix::EndBeforeStart,
+                    i,
+                    format!("times swapped to {end} - {start}"),
+                ));
+            }
+            if options.fixes.contains(&AutoFix::ZeroDuration)
+                && start == end
+                && event.event_type != EventType::Comment
+            {
+                removed[i] = true;
+                changes.push(Change::event(
+                    AutoFix::ZeroDuration,
+                    i,
+                    "removed, never shown".to_string(),
+                ));
+            }
+        }
+
+        if options.fixes.contains(&AutoFix::MergeDuplicates) {
+            merge_duplicates(&mut self.events.entries, &mut removed, &mut changes);
+        }
+        fix_durations(self, &removed, options, &mut changes);
+
+        let mut removed = removed.into_iter();
+        self.events
+            .entries
+            .retain(|_| !removed.next().unwrap_or_default());
+        changes.sort_by_key(|x| x.location);
+        Ok(changes)
+    }
+}
+
+fn fix_booleans(script: &mut AssScript, changes: &mut Vec<Change>) {
+    for (i, style) in script.styles.entries.iter_mut().enumerate() {
+        let booleans = [
+            (StyleField::Bold, &mut style.bold),
+            (StyleField::Italic, &mut style.italic),
+            (StyleField::Underline, &mut style.underline),
+            (StyleField::StrikeOut, &mut style.strike_out),
+        ];
+        for (field, value) in booleans {
+            if let Some(x) = repair_boolean(value) {
+                changes.push(Change {
+                    fix: AutoFix::InvalidBoolean,
+                    location: Location::Style(i),
+                    message: format!("{field} changed from {value} to {x}"),
+                });
+                *value = x;
+            }
+        }
+    }
+}
+
+/// Start and end of an event, `None` if either can't be parsed. Timing
+/// fixes skip such events, the `invalid-time` lint reports them.
+fn times(event: &EventStrict) -> Option<(AssTime, AssTime)> {
+    Some((event.start_time().ok()?, event.end_time().ok()?))
+}
+
+fn merge_duplicates(events: &mut [EventStrict], removed: &mut [bool], changes: &mut Vec<Change>) {
+    let mut previous: Option<usize> = None;
+    for i in 0..events.len() {
+        if removed[i] {
+            continue;
+        }
+        let Some((start, end)) =
+            times(&events[i]).filter(|_| events[i].event_type != EventType::Comment)
+        else {
+            previous = None;
+            continue;
+        };
+        if let Some(p) = previous {
+            let (kept, event) = (&events[p], &events[i]);
+            let kept_end = times(kept).map_or(end, |x| x.1);
+            if same_content(kept, event) && start <= kept_end {
+                events[p].set_end_time(end.max(kept_end));
+                removed[i] = true;
+                changes.push(Change::event(
+                    AutoFix::MergeDuplicates,
+                    i,
+                    format!("merged into event {}", p + 1),
+                ));
+                continue;
+            }
+        }
+        previous = Some(i);
+    }
+}
+
+/// Applies [`AutoFix::MinDuration`] and [`AutoFix::SnapGaps`] to the lines
+/// of each style and layer
+fn fix_durations(
+    script: &mut AssScript,
+    removed: &[bool],
+    options: &FixOptions,
+    changes: &mut Vec<Change>,
+) {
+    let (min_duration, snap_gaps) = (
+        options.fixes.contains(&AutoFix::MinDuration),
+        options.fixes.contains(&AutoFix::SnapGaps),
+    );
+    if !min_duration && !snap_gaps {
+        return;
+    }
+
+    let classifier = Classifier::new(script);
+    let mut tracks: HashMap<(&str, &str), Vec<(AssTime, usize)>> = HashMap::new();
+    for (i, event) in script.events.entries.iter().enumerate() {
+        if removed[i] || classifier.classify(event) != EventKind::Dialogue {
+            continue;
+        }
+        if let Some((start, _)) = times(event) {
+            tracks
+                .entry((&event.style, &event.layer))
+                .or_default()
+                .push((start, i));
+        }
+    }
+    let tracks: Vec<Vec<usize>> = tracks
+        .into_values()
+        .map(|mut x| {
+            x.sort_unstable();
+            x.into_iter().map(|(_, i)| i).collect()
+        })
+        .collect();
+
+    for track in tracks {
+        for (n, &i) in track.iter().enumerate() {
+            let next_start = track
+                .get(n + 1)
+                .and_then(|&x| times(&script.events.entries[x]))
+                .map(|x| x.0);
+            let event = &mut script.events.entries[i];
+            let Some((start, end)) = times(event) else {
+                continue;
+            };
+            let mut new_end = end;
+            // zero-length events are hidden on purpose or by mistake, neither
+            // is helped by showing them briefly
+            if min_duration && end > start && end - start < options.min_duration {
+                let mut target = start + options.min_duration;
+                if let Some(x) = next_start.filter(|&x| x >= end) {
+                    target = target.min(x);
+                }
+                if target > end {
+                    changes.push(Change::event(
+                        AutoFix::MinDuration,
+                        i,
+                        format!("end moved from {end} to {target}"),
+                    ));
+                    new_end = target;
+                }
+            }
+            if let Some(x) = next_start.filter(|_| snap_gaps) {
+                let gap = x - new_end;
+                if gap > AssTime::ZERO && gap <= options.max_gap {
+                    changes.push(Change::event(
+                        AutoFix::SnapGaps,
+                        i,
+                        format!("end moved from {new_end} to {x}"),
+                    ));
+                    new_end = x;
+                }
+            }
+            if new_end != end {
+                event.set_end_time(new_end);
+            }
+        }
+    }
+}
+
+/// Whether two events only differ in timing
+fn same_content(a: &EventStrict, b: &EventStrict) -> bool {
+    let mut a = a.clone();
+    a.start.clone_from(&b.start);
+    a.end.clone_from(&b.end);
+    a == *b
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing;
+    use crate::testing::script;
+
+    fn times(script: &AssScript) -> Vec<(i64, i64)> {
+        script
+            .events
+            .entries
+            .iter()
+            .map(|x| (x.start_time().unwrap().0, x.end_time().unwrap().0))
+            .collect()
+    }
+
+    #[rstest]
+    #[case(AutoFix::EmptyBlocks, r"{}Hello{\i1}{}", r"Hello{\i1}")]
+    #[case(AutoFix::LineBreaks, r"Hello\nworld", "Hello world")]
+    #[case(AutoFix::TrailingSpaces, r"Hello \Nworld  {\i0}", r"Hello\Nworld{\i0}")]
+    #[case(
+        AutoFix::TrailingSpaces,
+        r"{\i1}Hello {\i0}world",
+        r"{\i1}Hello {\i0}world"
+    )]
+    fn test_text_fixes(#[case] fix: AutoFix, #[case] text: &str, #[case] should: &str) {
+        let mut script = script(&[(0, 2000, text)]);
+        let options = FixOptions {
+            fixes: BTreeSet::from([fix]),
+            ..Default::default()
+        };
+        let changes = script.autofix(&options).unwrap();
+        assert_eq!(script.events.entries[0].text, should);
+        assert_eq!(changes.len(), usize::from(text != should));
+    }
+
+    #[test]
+    fn test_hard_line_breaks() {
+        let mut script = script(&[(0, 2000, r"Hello\nworld")]);
+        testing::set_info(&mut script, "WrapStyle", "2");
+        script.autofix(&FixOptions::default()).unwrap();
+        assert_eq!(script.events.entries[0].text, r"Hello\Nworld");
+    }
+
+    #[test]
+    fn test_invalid_times() {
+        let mut script = script(&[
+            (1000, 0, "backwards"),
+            (2000, 3000, "bogus"),
+            (3000, 3000, "x"),
+        ]);
+        script.events.entries[1].start = "bogus".to_string();
+        let mut options = FixOptions::default();
+        options.fixes.insert(AutoFix::ZeroDuration);
+        let changes = script.autofix(&options).unwrap();
+        // the unparsable event is left for the invalid-time lint
+        assert_eq!(script.events.entries.len(), 2);
+        assert_eq!(script.events.entries[0].start, "0:00:00.00");
+        assert_eq!(script.events.entries[1].start, "bogus");
+        assert_eq!(changes.len(), 2);
+    }
+
+    #[test]
+    fn test_timing_fixes() {
+        let mut script = script(&[
+            (1000, 0, "backwards"),
+            (2000, 3000, "repeated"),
+            (3000, 4000, "repeated"),
+            (4100, 4200, "short"),
+            (4300, 6000, "gap"),
+            (9000, 9100, "short at the end"),
+            (5000, 5000, r"{\pos(1,1)}sign"),
+        ]);
+        let changes = script.autofix(&FixOptions::default()).unwrap();
+        assert_eq!(
+            times(&script),
+            vec![
+                (0, 1000),
+                (2000, 4100),
+                (4100, 4300),
+                (4300, 6000),
+                (9000, 9500),
+                (5000, 5000)
+            ]
+        );
+        let fixes: Vec<AutoFix> = changes.iter().map(|x| x.fix).collect();
+        assert_eq!(
+            fixes,
+            vec![
+                AutoFix::EndBeforeStart,
+                AutoFix::SnapGaps,
+                AutoFix::MergeDuplicates,
+                AutoFix::MinDuration,
+                AutoFix::MinDuration
+            ]
+        );
+    }
+}
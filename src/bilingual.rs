@@ -0,0 +1,372 @@
+use std::collections::BTreeMap;
+
+use crate::classify::Classifier;
+use crate::classify::EventKind;
+use crate::event::EventStrict;
+use crate::index::EventIndex;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::time::AssTime;
+use crate::AssScript;
+
+/// How the text of paired events is combined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layout {
+    /// Lines joined with `\N`, both in the primary event's style
+    Join,
+    /// Lines joined with `\N`, switching to the given style with `\r` for the
+    /// secondary line
+    Inline(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PairOptions {
+    pub layout: Layout,
+    /// Put the secondary line above the primary one
+    pub secondary_first: bool,
+    /// Share of the shorter event two events must overlap by to be paired
+    pub min_overlap: f64,
+    /// Appended to the names of secondary styles that differ from a primary
+    /// style of the same name, such as `Default-en`
+    pub style_suffix: String,
+}
+
+impl Default for PairOptions {
+    fn default() -> Self {
+        Self {
+            layout: Layout::Join,
+            secondary_first: false,
+            min_overlap: 0.5,
+            style_suffix: "-secondary".to_string(),
+        }
+    }
+}
+
+/// Events that can be stacked with a translation: visible dialogue, not signs
+/// or songs
+fn pairable(event: &EventStrict, classifier: &Classifier) -> bool {
+    classifier.classify(event) == EventKind::Dialogue
+        && !tag::plain_text(&event.text).trim().is_empty()
+}
+
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    parents[i] = root;
+    root
+}
+
+/// Combines each primary event with the secondary events it overlaps into a
+/// single event. When a line is split differently in the two scripts, every
+/// event of the group ends up in one combined event spanning all of them.
+/// Unpaired events are kept as they are, secondary ones after the primary
+/// ones, and secondary styles missing from the primary script are added.
+/// Secondary styles that differ from a primary style of the same name are
+/// added with [`PairOptions::style_suffix`], and the secondary events and
+/// `\r` tags using them are updated to match.
+pub fn pair(
+    primary: &AssScript,
+    secondary: &AssScript,
+    options: &PairOptions,
+) -> anyhow::Result<AssScript> {
+    let mut script = primary.clone();
+    let renamed = add_styles(&mut script, secondary, &options.style_suffix)?;
+    let secondary = &rename_styles(secondary, &renamed);
+    if let Layout::Inline(style) = &options.layout {
+        anyhow::ensure!(
+            script.styles.find(style).is_some(),
+            "style {style} not found"
+        );
+    }
+
+    // primary events first, then secondary ones, as (index, start, end)
+    let mut candidates = Vec::new();
+    let classifier = Classifier::new(primary);
+    for (i, event) in primary.events.entries.iter().enumerate() {
+        if pairable(event, &classifier) {
+            candidates.push((i, event.start_time()?, event.end_time()?));
+        }
+    }
+    let secondary_from = candidates.len();
+    let classifier = Classifier::new(secondary);
+    let mut secondary_events = Vec::new();
+    for (i, event) in secondary.events.entries.iter().enumerate() {
+        if pairable(event, &classifier) {
+            candidates.push((i, event.start_time()?, event.end_time()?));
+            secondary_events.push(event.clone());
+        }
+    }
+
+    // group events transitively connected by sufficient overlap
+    let index = EventIndex::new(&secondary_events)?;
+    let mut parents: Vec<usize> = (0..candidates.len()).collect();
+    for a in 0..secondary_from {
+        let (_, start_a, end_a) = candidates[a];
+        for span in index.overlapping(start_a, end_a) {
+            let b = secondary_from + span.index;
+            let (_, start_b, end_b) = candidates[b];
+            let overlap = end_a.min(end_b) - start_a.max(start_b);
+            let shorter = (end_a - start_a).min(end_b - start_b);
+            // durations are far below the range where the casts lose precision
+            #[allow(clippy::cast_precision_loss)]
+            let sufficient =
+                overlap.millis() as f64 >= options.min_overlap * shorter.millis() as f64;
+            if overlap > AssTime::ZERO && sufficient {
+                let (root_a, root_b) = (find(&mut parents, a), find(&mut parents, b));
+                parents[root_b] = root_a;
+            }
+        }
+    }
+
+    let mut groups: Vec<(Vec<usize>, Vec<usize>)> =
+        vec![(Vec::new(), Vec::new()); candidates.len()];
+    for (i, &(index, ..)) in candidates.iter().enumerate() {
+        let root = find(&mut parents, i);
+        if i < secondary_from {
+            groups[root].0.push(index);
+        } else {
+            groups[root].1.push(index);
+        }
+    }
+
+    // the combined event replaces the first primary event of its group
+    let mut combined: Vec<Option<EventStrict>> = vec![None; primary.events.entries.len()];
+    let mut replaced = vec![false; primary.events.entries.len()];
+    let mut paired_secondary = vec![false; secondary.events.entries.len()];
+    for (primaries, secondaries) in groups {
+        if primaries.is_empty() || secondaries.is_empty() {
+            continue;
+        }
+        let first_primary = primaries[0];
+        for &i in &primaries {
+            replaced[i] = true;
+        }
+        for &i in &secondaries {
+            paired_secondary[i] = true;
+        }
+        let primaries: Vec<&EventStrict> = primaries
+            .iter()
+            .map(|&i| &primary.events.entries[i])
+            .collect();
+        let secondaries: Vec<&EventStrict> = secondaries
+            .iter()
+            .map(|&i| &secondary.events.entries[i])
+            .collect();
+        combined[first_primary] = Some(combine(&primaries, &secondaries, options)?);
+    }
+
+    script.events.entries = Vec::new();
+    for (i, event) in primary.events.entries.iter().enumerate() {
+        if let Some(x) = combined[i].take() {
+            script.events.entries.push(x);
+        } else if !replaced[i] {
+            script.events.entries.push(event.clone());
+        }
+    }
+    for (i, event) in secondary.events.entries.iter().enumerate() {
+        if !paired_secondary[i] {
+            script.events.entries.push(event.clone());
+        }
+    }
+    Ok(script)
+}
+
+/// Adds the secondary styles to the paired script, returning the new names of
+/// those that had to be renamed
+fn add_styles(
+    script: &mut AssScript,
+    secondary: &AssScript,
+    suffix: &str,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut renamed = BTreeMap::new();
+    for style in &secondary.styles.entries {
+        match script.styles.find(&style.name) {
+            None => script.styles.entries.push(style.clone()),
+            Some(x) if x == style => {}
+            Some(_) => {
+                let name = format!("{}{}", style.name, suffix);
+                anyhow::ensure!(
+                    script.styles.find(&name).is_none() && secondary.styles.find(&name).is_none(),
+                    "style {name} already exists, choose another suffix"
+                );
+                renamed.insert(style.name.clone(), name.clone());
+                script.styles.entries.push(StyleStrict {
+                    name,
+                    ..style.clone()
+                });
+            }
+        }
+    }
+    Ok(renamed)
+}
+
+/// A copy of the script whose events and `\r` tags use the new names of
+/// renamed styles
+fn rename_styles(script: &AssScript, names: &BTreeMap<String, String>) -> AssScript {
+    let mut script = script.clone();
+    if names.is_empty() {
+        return script;
+    }
+    for event in &mut script.events.entries {
+        if let Some(x) = names.get(&event.style) {
+            event.style.clone_from(x);
+        }
+        event.text = tag::rewrite_tags(&event.text, &mut |tag| {
+            if tag.name != "r" {
+                return None;
+            }
+            names.get(tag.args).map(|x| format!(r"\r{x}"))
+        });
+    }
+    script
+}
+
+fn combine(
+    primaries: &[&EventStrict],
+    secondaries: &[&EventStrict],
+    options: &PairOptions,
+) -> anyhow::Result<EventStrict> {
+    let join = |events: &[&EventStrict]| -> String {
+        let texts: Vec<&str> = events.iter().map(|x| x.text.trim()).collect();
+        texts.join(" ")
+    };
+    let primary_text = join(primaries);
+    let secondary_text = join(secondaries);
+
+    let text = match (&options.layout, options.secondary_first) {
+        (Layout::Join, false) => format!("{primary_text}\\N{secondary_text}"),
+        (Layout::Join, true) => format!("{secondary_text}\\N{primary_text}"),
+        (Layout::Inline(style), false) => {
+            format!("{primary_text}\\N{{\\r{style}}}{secondary_text}")
+        }
+        // switch back to the event style for the primary line
+        (Layout::Inline(style), true) => {
+            format!("{{\\r{style}}}{secondary_text}\\N{{\\r}}{primary_text}")
+        }
+    };
+
+    let mut event = primaries[0].clone();
+    let mut start = event.start_time()?;
+    let mut end = event.end_time()?;
+    for x in primaries.iter().chain(secondaries) {
+        start = start.min(x.start_time()?);
+        end = end.max(x.end_time()?);
+    }
+    event.set_start_time(start);
+    event.set_end_time(end);
+    event.text = text;
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::style::StyleStrict;
+    use crate::testing::script;
+
+    #[test]
+    fn test_pair() {
+        let zh = script(&[
+            (1000, 3000, "zh", "你好"),
+            (4000, 8000, "zh", "分開的一句"),
+            (20000, 21000, "zh", "只有中文"),
+        ]);
+        let en = script(&[
+            (1100, 2900, "en", "Hello"),
+            (4000, 6000, "en", "A split"),
+            (6000, 8100, "en", "line"),
+            (9000, 9500, "en", "English only"),
+            (1000, 3000, "en", r"{\pos(10,10)}Sign"),
+        ]);
+
+        let paired = pair(&zh, &en, &PairOptions::default()).unwrap();
+        let events: Vec<(&str, &str, &str)> = paired
+            .events
+            .entries
+            .iter()
+            .map(|x| (x.start.as_str(), x.end.as_str(), x.text.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("0:00:01.00", "0:00:03.00", r"你好\NHello"),
+                ("0:00:04.00", "0:00:08.10", r"分開的一句\NA split line"),
+                ("0:00:20.00", "0:00:21.00", "只有中文"),
+                ("0:00:09.00", "0:00:09.50", "English only"),
+                ("0:00:01.00", "0:00:03.00", r"{\pos(10,10)}Sign"),
+            ]
+        );
+        assert!(paired.styles.find("en").is_some());
+    }
+
+    #[rstest]
+    #[case(false, r"你好\N{\ren}Hello")]
+    #[case(true, r"{\ren}Hello\N{\r}你好")]
+    fn test_pair_inline(#[case] secondary_first: bool, #[case] should: &str) {
+        let zh = script(&[(1000, 3000, "zh", "你好")]);
+        let en = script(&[(1000, 3000, "en", "Hello")]);
+        let options = PairOptions {
+            layout: Layout::Inline("en".to_string()),
+            secondary_first,
+            ..Default::default()
+        };
+        let paired = pair(&zh, &en, &options).unwrap();
+        assert_eq!(paired.events.entries[0].text, should);
+    }
+
+    #[test]
+    fn test_pair_small_overlap() {
+        let zh = script(&[(1000, 3000, "zh", "你好")]);
+        let en = script(&[(2800, 5000, "en", "Hello")]);
+        let paired = pair(&zh, &en, &PairOptions::default()).unwrap();
+        assert_eq!(paired.events.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_pair_style_collision() {
+        let zh = script(&[(1000, 3000, "你好")]);
+        let mut en = script(&[(1000, 3000, "Hello"), (5000, 6000, r"{\rDefault}Bye")]);
+        en.styles.entries[0].fontsize = "40".to_string();
+        let options = PairOptions {
+            layout: Layout::Inline("Default-en".to_string()),
+            style_suffix: "-en".to_string(),
+            ..Default::default()
+        };
+
+        let paired = pair(&zh, &en, &options).unwrap();
+        let styles: Vec<(&str, &str)> = paired
+            .styles
+            .entries
+            .iter()
+            .map(|x| (x.name.as_str(), x.fontsize.as_str()))
+            .collect();
+        assert_eq!(styles, [("Default", "48"), ("Default-en", "40")]);
+        let events: Vec<(&str, &str)> = paired
+            .events
+            .entries
+            .iter()
+            .map(|x| (x.style.as_str(), x.text.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("Default", r"你好\N{\rDefault-en}Hello"),
+                ("Default-en", r"{\rDefault-en}Bye"),
+            ]
+        );
+
+        // identical styles are shared
+        let paired = pair(&zh, &zh, &PairOptions::default()).unwrap();
+        assert_eq!(paired.styles.entries.len(), 1);
+
+        en.styles.entries.push(StyleStrict {
+            name: "Default-en".to_string(),
+            ..Default::default()
+        });
+        assert!(pair(&zh, &en, &options).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::str::FromStr;
+
+use crate::common::Colour;
+use crate::event::EventStrict;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::time::AssTime;
+use crate::AssScript;
+use crate::DATA_SECTIONS;
+
+/// What [`AssScript::canonicalize`] may reorder, besides the fixed section
+/// order the writer always uses
+#[derive(Debug, Clone, Default)]
+pub struct FmtOptions {
+    /// Sort events by start time, then layer
+    pub sort_events: bool,
+    /// Sort styles by name
+    pub sort_styles: bool,
+}
+
+/// Shortest form of a number, so `20.00` becomes `20`. Anything that isn't a
+/// number is kept.
+fn number(value: &str) -> String {
+    match value.trim().parse::<f64>() {
+        // adding zero turns -0 into 0
+        Ok(x) if x.is_finite() => format!("{}", x + 0.0),
+        _ => value.to_string(),
+    }
+}
+
+/// `&HAABBGGRR` in upper case. Decimal colours of SSA scripts are kept.
+fn colour(value: &str) -> String {
+    let trimmed = value.trim();
+    if !trimmed.to_ascii_uppercase().starts_with("&H") {
+        return value.to_string();
+    }
+    Colour::from_str(trimmed).map_or_else(|_| value.to_string(), |x| x.to_string())
+}
+
+/// `H:MM:SS.cc`, unless that would change the time, so negative times and
+/// milliseconds are kept as written
+fn time(value: &str) -> String {
+    let Ok(time) = AssTime::from_str(value) else {
+        return value.to_string();
+    };
+    let formatted = time.to_string();
+    if AssTime::from_str(&formatted).ok() == Some(time) {
+        formatted
+    } else {
+        value.to_string()
+    }
+}
+
+/// Upper case hex in colour and alpha tags, with the closing `&`
+fn canonical_tags(text: &str) -> String {
+    tag::rewrite_tags(text, &mut |tag| {
+        if tag.parens
+            || !matches!(
+                tag.name,
+                "c" | "1c" | "2c" | "3c" | "4c" | "alpha" | "1a" | "2a" | "3a" | "4a"
+            )
+        {
+            return None;
+        }
+        let hex = tag.args.trim_end_matches('&');
+        let hex = hex.strip_prefix("&H").or_else(|| hex.strip_prefix("&h"))?;
+        u32::from_str_radix(hex, 16).ok()?;
+        Some(format!("\\{}&H{}&", tag.name, hex.to_ascii_uppercase()))
+    })
+}
+
+/// `Key: Value` with a single space after the colon
+fn info_line(line: &str) -> String {
+    match line.split_once(':') {
+        Some((key, value)) => format!("{}: {}", key.trim(), value.trim()),
+        None => line.to_string(),
+    }
+}
+
+fn canonical_style(style: &mut StyleStrict) {
+    for x in [
+        &mut style.primary_color,
+        &mut style.secondary_color,
+        &mut style.outline_color,
+        &mut style.back_color,
+    ] {
+        *x = colour(x);
+    }
+    for x in [
+        &mut style.fontsize,
+        &mut style.scale_x,
+        &mut style.scale_y,
+        &mut style.spacing,
+        &mut style.angle,
+        &mut style.outline,
+        &mut style.shadow,
+        &mut style.margin_l,
+        &mut style.margin_r,
+        &mut style.margin_v,
+        &mut style.encoding,
+    ] {
+        *x = number(x);
+    }
+}
+
+fn canonical_event(event: &mut EventStrict) {
+    event.start = time(&event.start);
+    event.end = time(&event.end);
+    for x in [
+        &mut event.layer,
+        &mut event.margin_l,
+        &mut event.margin_r,
+        &mut event.margin_v,
+    ] {
+        *x = number(x);
+    }
+    event.text = canonical_tags(&event.text);
+}
+
+impl AssScript {
+    /// Rewrites the script into a canonical form, so that scripts saved by
+    /// different tools only differ where their content does. Values that
+    /// can't be parsed are kept as they are. `;` comment lines aren't kept
+    /// when a script is read, so they are never part of the output.
+    pub fn canonicalize(&mut self, options: &FmtOptions) {
+        for (name, lines) in &mut self.other_sections {
+            if !DATA_SECTIONS.contains(&name.as_str()) {
+                for line in lines.iter_mut() {
+                    *line = info_line(line);
+                }
+            }
+        }
+        self.styles.context.normalize_format_line();
+        self.events.context.normalize_format_line();
+        self.styles.entries.iter_mut().for_each(canonical_style);
+        self.events.entries.iter_mut().for_each(canonical_event);
+
+        if options.sort_styles {
+            self.styles.entries.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        if options.sort_events {
+            self.events.entries.sort_by_cached_key(|x| {
+                (
+                    x.start_time().ok(),
+                    x.layer.parse::<i64>().ok(),
+                    x.end_time().ok(),
+                )
+            });
+        }
+    }
+}
+
+/// Formats the source of a script, keeping a leading byte order mark.
+/// Scripts with `;` comment lines are an error, as they would be dropped.
+pub fn format(contents: &str, options: &FmtOptions) -> anyhow::Result<String> {
+    let comments = AssScript::comment_lines(contents);
+    if let Some(first) = comments.first() {
+        anyhow::bail!(
+            "{} comment line(s) would be dropped, starting with {first:?}",
+            comments.len()
+        );
+    }
+    let mut script = AssScript::try_from_str(contents)?;
+    script.canonicalize(options);
+    let formatted = script.to_ass_string()?;
+    Ok(if contents.starts_with('\u{feff}') {
+        format!("\u{feff}{formatted}")
+    } else {
+        formatted
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    const SCRIPT: &str = r"[Script Info]
+ScriptType:v4.00+
+PlayResX:   1920
+
+[V4+ Styles]
+Format:Name,Fontname,Fontsize,PrimaryColour,SecondaryColour,OutlineColour,BackColour,Bold,Italic,Underline,StrikeOut,ScaleX,ScaleY,Spacing,Angle,BorderStyle,Outline,Shadow,Alignment,MarginL,MarginR,MarginV,Encoding
+Style: Sign,Arial,20.00,&h00ffffff,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2.50,0,8,10,10,10,1
+Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,0,2,10,10,10,1
+
+[Events]
+Format:Layer,Start,End,Style,Name,MarginL,MarginR,MarginV,Effect,Text
+Dialogue: 1,0:00:05.00,0:00:06.00,Default,,0000,0000,0000,,{\c&hff00ff&\1a&H80}b
+Dialogue: 0,00:00:05.0,0:00:06.00,Default,,0,0,0,,a
+";
+
+    const FORMATTED: &str = r"[Script Info]
+ScriptType: v4.00+
+PlayResX: 1920
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,0,2,10,10,10,1
+Style: Sign,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2.5,0,8,10,10,10,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,a
+Dialogue: 1,0:00:05.00,0:00:06.00,Default,,0,0,0,,{\c&HFF00FF&\1a&H80&}b
+";
+
+    #[test]
+    fn test_format() {
+        let options = FmtOptions {
+            sort_events: true,
+            sort_styles: true,
+        };
+        let formatted = format(SCRIPT, &options).unwrap();
+        assert_eq!(formatted, FORMATTED);
+        assert_eq!(format(&formatted, &options).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_bom() {
+        let options = FmtOptions::default();
+        let contents = format!("\u{feff}{FORMATTED}");
+        assert_eq!(format(&contents, &options).unwrap(), contents);
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let contents = FORMATTED.replace("[Events]\n", "[Events]\n; timed by hand\n");
+        let error = format(&contents, &FmtOptions::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "1 comment line(s) would be dropped, starting with \"; timed by hand\""
+        );
+    }
+
+    #[rstest]
+    #[case("20.00", "20")]
+    #[case("-0", "0")]
+    #[case("1.50", "1.5")]
+    #[case("abc", "abc")]
+    fn test_number(#[case] got: &str, #[case] should: &str) {
+        assert_eq!(number(got), should);
+    }
+
+    #[rstest]
+    #[case("00:00:05.0", "0:00:05.00")]
+    #[case("0:00:05.001", "0:00:05.001")]
+    #[case("-0:00:01.00", "-0:00:01.00")]
+    #[case("abc", "abc")]
+    fn test_time(#[case] got: &str, #[case] should: &str) {
+        assert_eq!(time(got), should);
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::event::EventStrict;
+use crate::event::EventType;
+use crate::style::Alignment;
+use crate::tag;
+use crate::AssScript;
+
+/// What an event is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum EventKind {
+    /// Spoken lines
+    Dialogue,
+    /// Typesetting of on-screen text
+    Sign,
+    /// Timed song lyrics with karaoke effects
+    Karaoke,
+    /// Song lyrics or their translation, such as the OP and ED
+    Song,
+    /// Comment events, which are never shown
+    Comment,
+}
+
+/// Style, actor and effect names of songs, such as `OP-EN`, `ED_romaji` or
+/// `Insert Song`
+static SONG_NAMES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(^|[^a-z])(op|ed)([^a-z]|$)|song|lyric|opening|ending|insert|karaoke|romaji")
+        .unwrap()
+});
+
+/// Style and actor names of typesetting, such as `TS`, `Sign-Top` or
+/// `WallEyecatchI`
+static SIGN_NAMES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(^|[^a-z])(ts|title|note|screen)([^a-z]|$)|sign|typeset|caption|eyecatch")
+        .unwrap()
+});
+
+/// Labels events using their tags, style and actor names, and the script
+/// around them. Each check is a heuristic, so releases with unusual naming
+/// can end up with some events mislabeled.
+#[derive(Debug, Clone, Default)]
+pub struct Classifier {
+    /// Styles aligned to the middle of the screen, where dialogue never is
+    middle_styles: HashSet<String>,
+    /// Times and text of events repeated on several layers, which is how
+    /// borders and glows of signs are built
+    layered: HashSet<(String, String, String)>,
+}
+
+impl Classifier {
+    pub fn new(script: &AssScript) -> Self {
+        let middle_styles = script
+            .styles
+            .entries
+            .iter()
+            .filter(|x| {
+                matches!(
+                    x.alignment,
+                    Alignment::MiddleLeft | Alignment::MiddleCenter | Alignment::MiddleRight
+                )
+            })
+            .map(|x| x.name.clone())
+            .collect();
+
+        let mut layers: HashMap<(String, String, String), HashSet<&str>> = HashMap::new();
+        for event in &script.events.entries {
+            if event.event_type == EventType::Dialogue {
+                let key = (
+                    event.start.clone(),
+                    event.end.clone(),
+                    tag::plain_text(&event.text),
+                );
+                layers.entry(key).or_default().insert(&event.layer);
+            }
+        }
+        let layered = layers
+            .into_iter()
+            .filter(|(_, layers)| layers.len() > 1)
+            .map(|(key, _)| key)
+            .collect();
+
+        Self {
+            middle_styles,
+            layered,
+        }
+    }
+
+    pub fn classify(&self, event: &EventStrict) -> EventKind {
+        if event.event_type == EventType::Comment {
+            return EventKind::Comment;
+        }
+
+        let (mut karaoke, mut sign) = (false, false);
+        tag::rewrite_tags(&event.text, &mut |tag| {
+            match tag.name {
+                "k" | "K" | "kf" | "ko" => karaoke = true,
+                "pos" | "move" | "org" | "clip" | "iclip" | "frx" | "fry" => sign = true,
+                "p" => sign |= tag.args.parse::<u32>().unwrap_or(0) > 0,
+                "an" => sign |= matches!(tag.args, "4" | "5" | "6"),
+                _ => {}
+            }
+            None
+        });
+        if karaoke {
+            return EventKind::Karaoke;
+        }
+        let names = [&event.style, &event.name, &event.effect];
+        if names.iter().any(|x| SONG_NAMES.is_match(x)) {
+            return EventKind::Song;
+        }
+        if sign
+            || SIGN_NAMES.is_match(&event.style)
+            || SIGN_NAMES.is_match(&event.name)
+            || self.middle_styles.contains(&event.style)
+        {
+            return EventKind::Sign;
+        }
+        let key = (
+            event.start.clone(),
+            event.end.clone(),
+            tag::plain_text(&event.text),
+        );
+        if self.layered.contains(&key) {
+            return EventKind::Sign;
+        }
+        EventKind::Dialogue
+    }
+}
+
+impl AssScript {
+    /// Labels every event, in order
+    pub fn classify_events(&self) -> Vec<EventKind> {
+        let classifier = Classifier::new(self);
+        self.events
+            .entries
+            .iter()
+            .map(|x| classifier.classify(x))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::style::StyleStrict;
+    use crate::time::AssTime;
+
+    #[rstest]
+    #[case("Default", "", "Hello", EventKind::Dialogue)]
+    #[case("Default", "", r"{\an8}Hello", EventKind::Dialogue)]
+    #[case("Default", "", r"{\pos(10,10)}EXIT", EventKind::Sign)]
+    #[case("Default", "", r"{\p1}m 0 0 l 10 0 10 10{\p0}", EventKind::Sign)]
+    #[case("Default", "", r"{\an5}Episode 1", EventKind::Sign)]
+    #[case("Middle", "", "Episode 1", EventKind::Sign)]
+    #[case("WallEyecatchI", "", "Attack on Titan", EventKind::Sign)]
+    #[case("Default", "TS", "EXIT", EventKind::Sign)]
+    #[case("OP-EN", "", "Lyrics", EventKind::Song)]
+    #[case("ed_romaji", "", r"{\pos(10,10)}Lyrics", EventKind::Song)]
+    #[case("Editor", "", "Hello", EventKind::Dialogue)]
+    #[case("Subtitle", "", "Hello", EventKind::Dialogue)]
+    #[case("Subtitles", "", "Hello", EventKind::Dialogue)]
+    #[case("Notes", "", "Hello", EventKind::Dialogue)]
+    #[case("Footnote", "", "Hello", EventKind::Dialogue)]
+    #[case("Onscreen", "", "Hello", EventKind::Dialogue)]
+    #[case("Title", "", "Episode 1", EventKind::Sign)]
+    #[case("Default", "note", "Hello", EventKind::Sign)]
+    #[case("On Screen", "", "Hello", EventKind::Sign)]
+    #[case("OP-JP", "", r"{\k20}ka{\k30}ra", EventKind::Karaoke)]
+    fn test_classify(
+        #[case] style: &str,
+        #[case] name: &str,
+        #[case] text: &str,
+        #[case] should: EventKind,
+    ) {
+        let mut script = AssScript::new();
+        script.styles.entries.push(StyleStrict {
+            name: "Middle".to_string(),
+            alignment: Alignment::MiddleCenter,
+            ..Default::default()
+        });
+        let mut event = EventStrict::new(AssTime(0), AssTime(1000), style, text);
+        event.name = name.to_string();
+        script.events.entries.push(event);
+        assert_eq!(script.classify_events(), vec![should]);
+    }
+
+    #[test]
+    fn test_classify_layers() {
+        let mut script = AssScript::new();
+        for layer in ["0", "1"] {
+            let mut event = EventStrict::new(AssTime(0), AssTime(1000), "Default", "EXIT");
+            event.layer = layer.to_string();
+            script.events.entries.push(event);
+        }
+        script.events.entries.push(EventStrict::new(
+            AssTime(0),
+            AssTime(1000),
+            "Default",
+            "Hello",
+        ));
+        let mut comment = EventStrict::new(AssTime(0), AssTime(1000), "Default", "Hello");
+        comment.event_type = EventType::Comment;
+        script.events.entries.push(comment);
+
+        assert_eq!(
+            script.classify_events(),
+            vec![
+                EventKind::Sign,
+                EventKind::Sign,
+                EventKind::Dialogue,
+                EventKind::Comment
+            ]
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use std::fmt;
+
+use crate::event::EventStrict;
+use crate::event::EventType;
+use crate::index::Span;
+use crate::lint;
+use crate::lint::Location;
+use crate::style::Alignment;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::tag::TextPart;
+use crate::time::AssTime;
+use crate::AssScript;
+
+/// How events that would cover each other are moved apart, from the
+/// `Collisions` entry in [Script Info]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, strum::Display, strum::EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum CollisionMode {
+    /// Later events move away from the edge they are aligned to
+    #[default]
+    Normal,
+    /// Later events move towards the edge they are aligned to
+    Reverse,
+}
+
+/// Width and height of an event's text, given the style and the width
+/// available before wrapping, `None` if it isn't wrapped
+pub type Measure<'a> = dyn Fn(&EventStrict, &StyleStrict, Option<f64>) -> (f64, f64) + 'a;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl Rect {
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.left < other.right
+            && other.left < self.right
+            && self.top < other.bottom
+            && other.top < self.bottom
+    }
+}
+
+/// Where an event is shown, in script pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub index: usize,
+    pub layer: i64,
+    pub start: AssTime,
+    pub end: AssTime,
+    pub rect: Rect,
+    /// How far collisions moved the event, up being negative
+    pub shift: f64,
+    alignment: u8,
+    positioned: bool,
+}
+
+impl Placement {
+    /// Positioned and middle aligned events don't take part in collisions
+    fn stacks(&self) -> bool {
+        !self.positioned && !(4..=6).contains(&self.alignment)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// Two events cover each other, because they are on different layers or
+    /// one of them isn't moved by collisions
+    Overlap {
+        first: usize,
+        second: usize,
+        at: AssTime,
+    },
+    /// An event was moved past the top or bottom of the screen
+    OffScreen { event: usize, at: AssTime },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Collisions {
+    /// Shown events, by start time
+    pub placements: Vec<Placement>,
+    pub problems: Vec<Problem>,
+}
+
+/// Estimates the size of an event from its font size and the number of
+/// characters, assuming characters are half as wide as they are high, and
+/// East Asian ones square. `\fs` tags that make the text larger are taken
+/// into account.
+pub fn estimate_size(
+    event: &EventStrict,
+    style: &StyleStrict,
+    max_width: Option<f64>,
+) -> (f64, f64) {
+    let number = |x: &str, default: f64| x.trim().parse::<f64>().unwrap_or(default);
+    let mut size = number(&style.fontsize, 20.0);
+    for part in tag::split_text(&event.text) {
+        if let TextPart::Override(block) = part {
+            for tag in tag::parse_tags(block) {
+                if tag.name == "fs" {
+                    size = size.max(number(tag.args, 0.0));
+                }
+            }
+        }
+    }
+    let scale_x = number(&style.scale_x, 100.0) / 100.0;
+    let scale_y = number(&style.scale_y, 100.0) / 100.0;
+    let spacing = number(&style.spacing, 0.0);
+
+    let mut width: f64 = 0.0;
+    let mut lines = 0.0;
+    for line in tag::plain_text(&event.text).split('\n') {
+        // columns and characters are far below the range where the casts
+        // lose precision
+        #[allow(clippy::cast_precision_loss)]
+        let line_width =
+            lint::width(line) as f64 * size * scale_x / 2.0 + line.chars().count() as f64 * spacing;
+        match max_width {
+            Some(max) if line_width > max && max > 0.0 => {
+                width = width.max(max);
+                lines += (line_width / max).ceil();
+            }
+            _ => {
+                width = width.max(line_width);
+                lines += 1.0;
+            }
+        }
+    }
+    (width, lines * size * scale_y)
+}
+
+/// The alignment and position override tags of an event, the first of each
+/// winning as renderers do. `\a` uses the SSA numbering.
+pub(crate) fn overrides(text: &str) -> (Option<u8>, Option<(f64, f64)>) {
+    let mut alignment = None;
+    let mut position = None;
+    for part in tag::split_text(text) {
+        let TextPart::Override(block) = part else {
+            continue;
+        };
+        for tag in tag::parse_tags(block) {
+            match tag.name {
+                "an" if alignment.is_none() => {
+                    alignment = tag.args.parse().ok().filter(|x| (1..=9).contains(x));
+                }
+                "a" if alignment.is_none() => {
+                    alignment = Alignment::from_ssa(tag.args).numpad();
+                }
+                "pos" | "move" if position.is_none() => {
+                    let params: Vec<f64> =
+                        tag.params().iter().filter_map(|x| x.parse().ok()).collect();
+                    if params.len() >= 2 {
+                        position = Some((params[0], params[1]));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    (alignment, position)
+}
+
+impl AssScript {
+    pub fn collision_mode(&self) -> CollisionMode {
+        self.script_info("Collisions")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Simulates how renderers stack events shown at the same time, with
+    /// sizes from [`estimate_size`]
+    pub fn collisions(&self) -> anyhow::Result<Collisions> {
+        let wrap = self.script_info("WrapStyle") != Some("2");
+        self.collisions_with(&|event, style, max_width| {
+            estimate_size(event, style, max_width.filter(|_| wrap))
+        })
+    }
+
+    /// Simulates how renderers stack events shown at the same time. Events
+    /// keep their place while they are shown, so each event is placed when
+    /// it starts, moving it past the events already shown on its layer.
+    /// Positioned and middle aligned events are never moved.
+    pub fn collisions_with(&self, measure: &Measure) -> anyhow::Result<Collisions> {
+        let mode = self.collision_mode();
+        let index = self.events.index()?;
+        let shown = |x: &&Span| {
+            let event = &self.events.entries[x.index];
+            event.event_type == EventType::Dialogue
+                && x.start < x.end
+                && !tag::plain_text(&event.text).trim().is_empty()
+        };
+        let layer = |i: usize| {
+            self.events.entries[i]
+                .layer
+                .trim()
+                .parse::<i64>()
+                .unwrap_or(0)
+        };
+
+        let mut collisions = Collisions::default();
+        // position in `collisions.placements` of every event placed so far
+        let mut placed: Vec<Option<usize>> = vec![None; self.events.entries.len()];
+        let spans: Vec<&Span> = index.iter().filter(shown).collect();
+        for group in spans.chunk_by(|a, b| a.start == b.start) {
+            // events starting together are placed by layer, then file order
+            let mut group = group.to_vec();
+            group.sort_by_key(|x| (layer(x.index), x.index));
+            for span in group {
+                let mut earlier: Vec<usize> = index
+                    .at(span.start)
+                    .into_iter()
+                    .filter_map(|x| placed[x.index])
+                    .collect();
+                earlier.sort_unstable();
+                let earlier: Vec<&Placement> = earlier
+                    .into_iter()
+                    .map(|i| &collisions.placements[i])
+                    .collect();
+
+                let mut placement = self.place(span.index, measure);
+                placement.layer = layer(span.index);
+                placement.start = span.start;
+                placement.end = span.end;
+                resolve(&mut placement, &earlier, mode);
+
+                for other in &earlier {
+                    if other.rect.overlaps(&placement.rect) {
+                        collisions.problems.push(Problem::Overlap {
+                            first: other.index,
+                            second: span.index,
+                            at: span.start,
+                        });
+                    }
+                }
+                let (_, height) = self.play_res();
+                if placement.rect.top < 0.0 || placement.rect.bottom > height {
+                    collisions.problems.push(Problem::OffScreen {
+                        event: span.index,
+                        at: span.start,
+                    });
+                }
+                placed[span.index] = Some(collisions.placements.len());
+                collisions.placements.push(placement);
+            }
+        }
+        Ok(collisions)
+    }
+
+    /// Where an event is shown before collisions move it
+    fn place(&self, index: usize, measure: &Measure) -> Placement {
+        let event = &self.events.entries[index];
+        let default_style = StyleStrict::default();
+        let style = self.styles.find(&event.style).unwrap_or(&default_style);
+        let (play_x, play_y) = self.play_res();
+        let (margin_l, margin_r, margin_v) = event.margins(Some(style));
+
+        let (alignment, position) = overrides(&event.text);
+        let alignment = alignment.or(style.alignment.numpad()).unwrap_or(2);
+        let (width, height) = measure(
+            event,
+            style,
+            position.is_none().then_some(play_x - margin_l - margin_r),
+        );
+
+        let (x, y) = position.unwrap_or_else(|| {
+            let x = match alignment % 3 {
+                1 => margin_l,
+                0 => play_x - margin_r,
+                _ => (margin_l + play_x - margin_r) / 2.0,
+            };
+            let y = match alignment {
+                1..=3 => play_y - margin_v,
+                7..=9 => margin_v,
+                _ => play_y / 2.0,
+            };
+            (x, y)
+        });
+        let left = match alignment % 3 {
+            1 => x,
+            0 => x - width,
+            _ => x - width / 2.0,
+        };
+        let top = match alignment {
+            1..=3 => y - height,
+            7..=9 => y,
+            _ => y - height / 2.0,
+        };
+        Placement {
+            index,
+            rect: Rect {
+                left,
+                top,
+                right: left + width,
+                bottom: top + height,
+            },
+            shift: 0.0,
+            layer: 0,
+            start: AssTime::ZERO,
+            end: AssTime::ZERO,
+            alignment,
+            positioned: position.is_some(),
+        }
+    }
+}
+
+/// Moves an event past the events shown on its layer that it covers
+fn resolve(placement: &mut Placement, shown: &[&Placement], mode: CollisionMode) {
+    if !placement.stacks() {
+        return;
+    }
+    let height = placement.rect.bottom - placement.rect.top;
+    let up = (placement.alignment <= 3) == (mode == CollisionMode::Normal);
+    let original = placement.rect.top;
+    while let Some(other) = shown
+        .iter()
+        .filter(|x| x.stacks() && x.layer == placement.layer)
+        .find(|x| x.rect.overlaps(&placement.rect))
+    {
+        let top = if up {
+            other.rect.top - height
+        } else {
+            other.rect.bottom
+        };
+        placement.rect.top = top;
+        placement.rect.bottom = top + height;
+    }
+    placement.shift = placement.rect.top - original;
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Overlap { first, second, at } => write!(
+                f,
+                "{at} {} overlaps {}",
+                Location::Event(*second),
+                Location::Event(*first)
+            ),
+            Problem::OffScreen { event, at } => {
+                write!(f, "{at} {} goes off screen", Location::Event(*event))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing;
+
+    /// A script whose `Default` style is 60 pixels high, 20 from the edges
+    fn script(events: &[(i64, i64, i64, &str)]) -> AssScript {
+        let mut script = testing::script(events);
+        script.styles.entries[0].fontsize = "60".to_string();
+        script.styles.entries[0].margin_v = "20".to_string();
+        script
+    }
+
+    #[test]
+    fn test_stacking() {
+        let script = script(&[
+            (0, 5000, 0, "Hello"),
+            (1000, 3000, 0, "World"),
+            (2000, 4000, 0, "Again"),
+            (3500, 4500, 0, "{\\an8}Top"),
+        ]);
+        let collisions = script.collisions().unwrap();
+        assert!(collisions.problems.is_empty());
+        let tops: Vec<(usize, f64, f64)> = collisions
+            .placements
+            .iter()
+            .map(|x| (x.index, x.rect.top, x.shift))
+            .collect();
+        assert_eq!(
+            tops,
+            vec![
+                (0, 1000.0, 0.0),
+                (1, 940.0, -60.0),
+                (2, 880.0, -120.0),
+                (3, 20.0, 0.0)
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::reverse(0, "Collisions: Reverse", vec![Problem::OffScreen { event: 1, at: AssTime(1000) }])]
+    #[case::layers(1, "Collisions: Normal", vec![Problem::Overlap { first: 0, second: 1, at: AssTime(1000) }])]
+    fn test_problems(#[case] layer: i64, #[case] info: &str, #[case] should: Vec<Problem>) {
+        let mut script = script(&[(0, 5000, 0, "Hello"), (1000, 3000, layer, "World")]);
+        script
+            .other_sections
+            .get_mut("Script Info")
+            .unwrap()
+            .push(info.to_string());
+        assert_eq!(script.collisions().unwrap().problems, should);
+    }
+
+    #[test]
+    fn test_positioned_overlap() {
+        let script = script(&[
+            (0, 5000, 0, "{\\an8}Top line"),
+            (0, 5000, 0, "{\\an8\\pos(960,30)}Note"),
+        ]);
+        assert_eq!(
+            script.collisions().unwrap().problems,
+            vec![Problem::Overlap {
+                first: 0,
+                second: 1,
+                at: AssTime(0)
+            }]
+        );
+    }
+
+    #[rstest]
+    #[case("abcd", None, (120.0, 60.0))]
+    #[case("abcd", Some(50.0), (50.0, 180.0))]
+    #[case("ab\\Ncd{\\fs80}", None, (80.0, 160.0))]
+    #[case("中文", None, (120.0, 60.0))]
+    fn test_estimate_size(
+        #[case] text: &str,
+        #[case] max_width: Option<f64>,
+        #[case] should: (f64, f64),
+    ) {
+        let style = StyleStrict {
+            fontsize: "60".to_string(),
+            ..Default::default()
+        };
+        let event = EventStrict::new(AssTime(0), AssTime(1000), "Default", text);
+        assert_eq!(estimate_size(&event, &style, max_width), should);
+    }
+}
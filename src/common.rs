@@ -1,3 +1,43 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Implements serde traits through `Display` and `FromStr`, so enums are
+/// represented by the same strings as in scripts and unknown values survive
+macro_rules! serde_via_str {
+    ($($name:ty),+) => {
+        $(
+            #[cfg(feature = "serde")]
+            impl serde::Serialize for $name {
+                fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            #[cfg(feature = "serde")]
+            impl<'de> serde::Deserialize<'de> for $name {
+                fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+                    s.parse().map_err(serde::de::Error::custom)
+                }
+            }
+        )+
+    };
+}
+pub(crate) use serde_via_str;
+
+/// Serializes a map with its keys sorted, so the output is deterministic
+#[cfg(feature = "serde")]
+pub(crate) fn serialize_sorted<S, V>(
+    map: &std::collections::HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: serde::Serialize,
+{
+    serializer.collect_map(map.iter().collect::<std::collections::BTreeMap<_, _>>())
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Eq, strum::Display, strum::EnumString)]
 pub enum Boolean {
     #[strum(default)]
@@ -9,6 +49,86 @@ pub enum Boolean {
     False,
 }
 
+serde_via_str!(Boolean);
+
+/// An ASS colour. Alpha is transparency, so 0 is opaque.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Colour {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Colour {
+    /// `#RRGGBB`, as used by HTML-like formats
+    pub fn to_html(self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
+
+    /// Parses `#RRGGBB` or `#RRGGBBAA` where alpha is opacity
+    pub fn from_html(s: &str) -> anyhow::Result<Self> {
+        let hex = s.trim().strip_prefix('#').unwrap_or(s.trim());
+        anyhow::ensure!(hex.len() == 6 || hex.len() == 8, "invalid colour: {s}");
+        let value = u32::from_str_radix(hex, 16)?;
+        let [b0, b1, b2, b3] = value.to_be_bytes();
+        Ok(if hex.len() == 6 {
+            Self {
+                r: b1,
+                g: b2,
+                b: b3,
+                a: 0,
+            }
+        } else {
+            Self {
+                r: b0,
+                g: b1,
+                b: b2,
+                a: 255 - b3,
+            }
+        })
+    }
+
+    /// `&HBBGGRR&`, the form used by colour override tags
+    pub fn to_tag_string(self) -> String {
+        format!("&H{:02X}{:02X}{:02X}&", self.b, self.g, self.r)
+    }
+}
+
+/// Parses `&HAABBGGRR`, with or without alpha and the trailing `&`, as well as
+/// the decimal values found in old SSA scripts
+impl FromStr for Colour {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().trim_end_matches('&');
+        let value = match s.strip_prefix("&H").or_else(|| s.strip_prefix("&h")) {
+            Some(hex) => u32::from_str_radix(hex, 16)?,
+            // SSA writes colours as signed decimals, only the low 32 bits count
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            None => s.parse::<i64>()? as u32,
+        };
+        let [alpha, blue, green, red] = value.to_be_bytes();
+        Ok(Self {
+            r: red,
+            g: green,
+            b: blue,
+            a: alpha,
+        })
+    }
+}
+
+/// Formats as `&HAABBGGRR`, the form used in [V4+ Styles]
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "&H{:02X}{:02X}{:02X}{:02X}",
+            self.a, self.b, self.g, self.r
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -34,4 +154,28 @@ mod tests {
         let result = got.to_string();
         assert_eq!(result, should);
     }
+
+    #[rstest]
+    #[case("&H00FFFFFF", Colour { r: 255, g: 255, b: 255, a: 0 })]
+    #[case("&H7F404040", Colour { r: 64, g: 64, b: 64, a: 127 })]
+    #[case("&H0000FF&", Colour { r: 255, g: 0, b: 0, a: 0 })]
+    #[case("&h02ffffff", Colour { r: 255, g: 255, b: 255, a: 2 })]
+    #[case("255", Colour { r: 255, g: 0, b: 0, a: 0 })]
+    #[case("-2147483640", Colour { r: 8, g: 0, b: 0, a: 128 })]
+    fn test_colour_from_str(#[case] got: &str, #[case] should: Colour) {
+        let result = Colour::from_str(got).unwrap();
+        assert_eq!(result, should);
+    }
+
+    #[test]
+    fn test_colour_to_string() {
+        let colour = Colour::from_str("&H7F404080").unwrap();
+        assert_eq!(colour.to_string(), "&H7F404080");
+        assert_eq!(colour.to_html(), "#804040");
+        assert_eq!(colour.to_tag_string(), "&H404080&");
+        assert_eq!(
+            Colour::from_html("#804040").unwrap().to_tag_string(),
+            "&H404080&"
+        );
+    }
 }
@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use crate::microdvd;
+use crate::srt;
+use crate::srt::TagMode;
+use crate::style::StyleStrict;
+use crate::time::FrameRate;
+use crate::ttml;
+use crate::vtt;
+use crate::AssScript;
+
+/// Subtitle formats scripts can be converted from and to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum Format {
+    #[strum(serialize = "ass", serialize = "ssa")]
+    Ass,
+    Srt,
+    Vtt,
+    /// Frame based, timed using [`ConvertOptions::framerate`]
+    #[strum(serialize = "microdvd", serialize = "sub")]
+    MicroDvd,
+    /// The serde representation of [`AssScript`]
+    #[cfg(feature = "serde")]
+    Json,
+    /// IMSC1 text profile, export only
+    #[strum(serialize = "ttml", serialize = "dfxp")]
+    Ttml,
+}
+
+impl Format {
+    /// Guesses the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+/// Settings for formats that can't represent everything a script can
+#[derive(Default, Debug, Clone)]
+pub struct ConvertOptions {
+    /// Style events are assigned to when reading formats without styles
+    pub style: StyleStrict,
+    pub tags: TagMode,
+    /// Frame rate of frame based formats, unless the file declares one
+    pub framerate: FrameRate,
+}
+
+pub fn read(contents: &str, format: Format, options: &ConvertOptions) -> anyhow::Result<AssScript> {
+    match format {
+        Format::Ass => AssScript::try_from_str(contents),
+        Format::Srt => srt::import(contents, &options.style),
+        Format::Vtt => vtt::import(contents, &options.style),
+        Format::MicroDvd => microdvd::import(contents, &options.style, options.framerate),
+        #[cfg(feature = "serde")]
+        Format::Json => Ok(serde_json::from_str(contents)?),
+        Format::Ttml => anyhow::bail!("reading TTML is not supported"),
+    }
+}
+
+pub fn write(
+    script: &AssScript,
+    format: Format,
+    options: &ConvertOptions,
+) -> anyhow::Result<String> {
+    match format {
+        Format::Ass => script.to_ass_string(),
+        Format::Srt => srt::export(script, options.tags),
+        Format::Vtt => vtt::export(script, options.tags),
+        Format::MicroDvd => microdvd::export(script, options.tags, options.framerate),
+        #[cfg(feature = "serde")]
+        Format::Json => Ok(serde_json::to_string_pretty(script)? + "\n"),
+        Format::Ttml => ttml::export(script, options.tags),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("a.ass", Some(Format::Ass))]
+    #[case("a.SSA", Some(Format::Ass))]
+    #[case("a.en.srt", Some(Format::Srt))]
+    #[case("a.vtt", Some(Format::Vtt))]
+    #[case("a.dfxp", Some(Format::Ttml))]
+    #[case("a.sub", Some(Format::MicroDvd))]
+    #[cfg_attr(feature = "serde", case("a.json", Some(Format::Json)))]
+    #[case("a.txt", None)]
+    fn test_format_from_path(#[case] got: &str, #[case] should: Option<Format>) {
+        assert_eq!(Format::from_path(Path::new(got)), should);
+    }
+}
@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::event::EventField;
+use crate::event::EventStrict;
+use crate::index::EventIndex;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::time::AssTime;
+use crate::AssScript;
+
+/// A value that differs between two versions, `None` where it is missing
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "change", rename_all = "kebab-case"))]
+pub enum StyleChange {
+    Added {
+        name: String,
+    },
+    Removed {
+        name: String,
+    },
+    Changed {
+        name: String,
+        fields: Vec<FieldChange>,
+    },
+}
+
+/// How a matched event was edited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[strum(serialize_all = "kebab-case")]
+pub enum EditKind {
+    Retimed,
+    TextChanged,
+    Restyled,
+    /// Any other field, such as the layer, actor or margins
+    Changed,
+}
+
+/// Events are referred to by their index in the old or new script
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "change", rename_all = "kebab-case"))]
+pub enum EventChange {
+    Added {
+        new: usize,
+        event: EventStrict,
+    },
+    Removed {
+        old: usize,
+        event: EventStrict,
+    },
+    Modified {
+        old: usize,
+        new: usize,
+        kinds: Vec<EditKind>,
+        fields: Vec<FieldChange>,
+        event: EventStrict,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ScriptDiff {
+    /// Changed [Script Info] entries
+    pub info: Vec<FieldChange>,
+    pub styles: Vec<StyleChange>,
+    /// In the order of the events' start times
+    pub events: Vec<EventChange>,
+}
+
+impl ScriptDiff {
+    pub fn is_empty(&self) -> bool {
+        self.info.is_empty() && self.styles.is_empty() && self.events.is_empty()
+    }
+}
+
+fn changed_fields<F: fmt::Display + PartialEq>(
+    old: &[(F, String)],
+    new: &[(F, String)],
+) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    for (field, value) in old {
+        let new_value = new.iter().find(|x| x.0 == *field).map(|x| &x.1);
+        if new_value != Some(value) {
+            changes.push(FieldChange {
+                field: field.to_string(),
+                old: Some(value.clone()),
+                new: new_value.cloned(),
+            });
+        }
+    }
+    for (field, value) in new {
+        if !old.iter().any(|x| x.0 == *field) {
+            changes.push(FieldChange {
+                field: field.to_string(),
+                old: None,
+                new: Some(value.clone()),
+            });
+        }
+    }
+    changes
+}
+
+pub(crate) fn info_entries(script: &AssScript) -> Vec<(String, String)> {
+    script
+        .other_sections
+        .get("Script Info")
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+fn diff_styles(old: &[StyleStrict], new: &[StyleStrict]) -> Vec<StyleChange> {
+    let mut changes = Vec::new();
+    for style in old {
+        match new.iter().find(|x| x.name == style.name) {
+            None => changes.push(StyleChange::Removed {
+                name: style.name.clone(),
+            }),
+            Some(x) => {
+                let fields = changed_fields(&style.fields(), &x.fields());
+                if !fields.is_empty() {
+                    changes.push(StyleChange::Changed {
+                        name: style.name.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    for style in new {
+        if !old.iter().any(|x| x.name == style.name) {
+            changes.push(StyleChange::Added {
+                name: style.name.clone(),
+            });
+        }
+    }
+    changes
+}
+
+/// Share of character bigrams two texts have in common, from 0 to 1
+fn similarity(a: &str, b: &str) -> f64 {
+    let bigrams = |s: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = s.to_lowercase().chars().collect();
+        chars.windows(2).map(|x| (x[0], x[1])).collect()
+    };
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return f64::from(u8::from(a == b));
+    }
+    let mut remaining = b.clone();
+    let mut common = 0;
+    for x in &a {
+        if let Some(i) = remaining.iter().position(|y| y == x) {
+            remaining.swap_remove(i);
+            common += 1;
+        }
+    }
+    // counts are far below the range where the casts lose precision
+    #[allow(clippy::cast_precision_loss)]
+    let similarity = 2.0 * f64::from(common) / (a.len() + b.len()) as f64;
+    similarity
+}
+
+/// All fields, to find events that are exactly the same
+fn event_key(event: &EventStrict) -> Vec<String> {
+    event
+        .fields()
+        .into_iter()
+        .map(|(field, value)| format!("{field}={value}"))
+        .chain([event.event_type.to_string()])
+        .collect()
+}
+
+/// Pairs each event of `old` with the event of `new` that is the same line,
+/// possibly edited. Identical events are paired first, then events with the
+/// same text nearest in time, then events at the same time or overlapping
+/// with similar text.
+pub fn match_events(
+    old: &[EventStrict],
+    new: &[EventStrict],
+) -> anyhow::Result<Vec<(usize, usize)>> {
+    let mut pairs = Vec::new();
+    let mut old_matched = vec![false; old.len()];
+    let mut new_matched = vec![false; new.len()];
+
+    let mut identical: HashMap<Vec<String>, VecDeque<usize>> = HashMap::new();
+    for (i, event) in old.iter().enumerate() {
+        identical.entry(event_key(event)).or_default().push_back(i);
+    }
+    for (j, event) in new.iter().enumerate() {
+        if let Some(i) = identical
+            .get_mut(&event_key(event))
+            .and_then(VecDeque::pop_front)
+        {
+            pairs.push((i, j));
+            old_matched[i] = true;
+            new_matched[j] = true;
+        }
+    }
+
+    let times = |events: &[EventStrict]| -> anyhow::Result<Vec<(AssTime, AssTime)>> {
+        events
+            .iter()
+            .map(|x| Ok((x.start_time()?, x.end_time()?)))
+            .collect()
+    };
+    let (old_times, new_times) = (times(old)?, times(new)?);
+
+    let mut same_text: HashMap<(&str, String), Vec<usize>> = HashMap::new();
+    for (i, event) in old.iter().enumerate() {
+        if !old_matched[i] {
+            same_text
+                .entry((&event.text, event.event_type.to_string()))
+                .or_default()
+                .push(i);
+        }
+    }
+    for (j, event) in new.iter().enumerate() {
+        if new_matched[j] {
+            continue;
+        }
+        let Some(candidates) = same_text.get_mut(&(&event.text, event.event_type.to_string()))
+        else {
+            continue;
+        };
+        let nearest = candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &i)| (old_times[i].0 - new_times[j].0).millis().abs());
+        if let Some((n, &i)) = nearest {
+            candidates.swap_remove(n);
+            pairs.push((i, j));
+            old_matched[i] = true;
+            new_matched[j] = true;
+        }
+    }
+
+    // plain text once per event, and an index to find the old events a new
+    // one overlaps. Events without duration overlap nothing, so those are
+    // found by their time.
+    let old_plain: Vec<String> = old.iter().map(|x| tag::plain_text(&x.text)).collect();
+    let index = EventIndex::new(old)?;
+    let mut instants: HashMap<(AssTime, AssTime), Vec<usize>> = HashMap::new();
+    for (i, &(start, end)) in old_times.iter().enumerate() {
+        if start >= end {
+            instants.entry((start, end)).or_default().push(i);
+        }
+    }
+    for (j, event) in new.iter().enumerate() {
+        if new_matched[j] {
+            continue;
+        }
+        let (start, end) = new_times[j];
+        let plain = tag::plain_text(&event.text);
+        let candidates: Vec<usize> = if start < end {
+            index
+                .overlapping(start, end)
+                .iter()
+                .map(|x| x.index)
+                .collect()
+        } else {
+            instants.get(&(start, end)).cloned().unwrap_or_default()
+        };
+        let mut best: Option<(f64, usize)> = None;
+        for i in candidates {
+            if old_matched[i] {
+                continue;
+            }
+            let same_time = old_times[i] == (start, end);
+            let score = similarity(&old_plain[i], &plain);
+            if (same_time || score >= 0.5)
+                && best.is_none_or(|(x, _)| score + f64::from(u8::from(same_time)) > x)
+            {
+                best = Some((score + f64::from(u8::from(same_time)), i));
+            }
+        }
+        if let Some((_, i)) = best {
+            pairs.push((i, j));
+            old_matched[i] = true;
+            new_matched[j] = true;
+        }
+    }
+
+    pairs.sort_unstable();
+    Ok(pairs)
+}
+
+/// Compares two versions of a script: styles by name, field by field, and
+/// events as matched by [`match_events`]
+pub fn diff(old: &AssScript, new: &AssScript) -> anyhow::Result<ScriptDiff> {
+    let (old_events, new_events) = (&old.events.entries, &new.events.entries);
+    let pairs = match_events(old_events, new_events)?;
+
+    // changes with the start time they are sorted by
+    let mut events: Vec<(AssTime, EventChange)> = Vec::new();
+    let mut old_matched = HashSet::new();
+    let mut new_matched = HashSet::new();
+    for (i, j) in pairs {
+        old_matched.insert(i);
+        new_matched.insert(j);
+        let fields = changed_fields(&old_events[i].fields(), &new_events[j].fields());
+        if fields.is_empty() && old_events[i].event_type == new_events[j].event_type {
+            continue;
+        }
+        let mut kinds = Vec::new();
+        for x in &fields {
+            let kind = match x.field.parse() {
+                Ok(EventField::Start | EventField::End) => EditKind::Retimed,
+                Ok(EventField::Text) => EditKind::TextChanged,
+                Ok(EventField::Style) => EditKind::Restyled,
+                _ => EditKind::Changed,
+            };
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        if kinds.is_empty() {
+            // only the type differs, such as a line that was commented out
+            kinds.push(EditKind::Changed);
+        }
+        events.push((
+            new_events[j].start_time()?,
+            EventChange::Modified {
+                old: i,
+                new: j,
+                kinds,
+                fields,
+                event: new_events[j].clone(),
+            },
+        ));
+    }
+    for (i, event) in old_events.iter().enumerate() {
+        if !old_matched.contains(&i) {
+            events.push((
+                event.start_time()?,
+                EventChange::Removed {
+                    old: i,
+                    event: event.clone(),
+                },
+            ));
+        }
+    }
+    for (j, event) in new_events.iter().enumerate() {
+        if !new_matched.contains(&j) {
+            events.push((
+                event.start_time()?,
+                EventChange::Added {
+                    new: j,
+                    event: event.clone(),
+                },
+            ));
+        }
+    }
+    events.sort_by_key(|x| x.0);
+
+    Ok(ScriptDiff {
+        info: changed_fields(&info_entries(old), &info_entries(new)),
+        styles: diff_styles(&old.styles.entries, &new.styles.entries),
+        events: events.into_iter().map(|x| x.1).collect(),
+    })
+}
+
+fn describe(event: &EventStrict) -> String {
+    format!(
+        "{} {} - {} {}: {}",
+        event.event_type, event.start, event.end, event.style, event.text
+    )
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |x: &Option<String>| x.as_deref().unwrap_or("(none)").to_string();
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.field,
+            value(&self.old),
+            value(&self.new)
+        )
+    }
+}
+
+/// A report with a line per change, `+` for additions, `-` for removals and
+/// `~` for edits
+impl fmt::Display for ScriptDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.info.is_empty() {
+            writeln!(f, "[Script Info]")?;
+            for x in &self.info {
+                writeln!(f, "~ {x}")?;
+            }
+        }
+        if !self.styles.is_empty() {
+            writeln!(f, "[V4+ Styles]")?;
+            for x in &self.styles {
+                match x {
+                    StyleChange::Added { name } => writeln!(f, "+ {name}")?,
+                    StyleChange::Removed { name } => writeln!(f, "- {name}")?,
+                    StyleChange::Changed { name, fields } => {
+                        writeln!(f, "~ {name}")?;
+                        for x in fields {
+                            writeln!(f, "    {x}")?;
+                        }
+                    }
+                }
+            }
+        }
+        if !self.events.is_empty() {
+            writeln!(f, "[Events]")?;
+            for x in &self.events {
+                match x {
+                    EventChange::Added { new, event } => {
+                        writeln!(f, "+ #{} {}", new + 1, describe(event))?;
+                    }
+                    EventChange::Removed { old, event } => {
+                        writeln!(f, "- #{} {}", old + 1, describe(event))?;
+                    }
+                    EventChange::Modified {
+                        new,
+                        kinds,
+                        fields,
+                        event,
+                        ..
+                    } => {
+                        let kinds: Vec<String> = kinds.iter().map(ToString::to_string).collect();
+                        writeln!(
+                            f,
+                            "~ #{} {} ({})",
+                            new + 1,
+                            describe(event),
+                            kinds.join(", ")
+                        )?;
+                        for x in fields {
+                            writeln!(f, "    {x}")?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::script;
+
+    fn summary(diff: &ScriptDiff) -> Vec<String> {
+        diff.events
+            .iter()
+            .map(|x| match x {
+                EventChange::Added { new, .. } => format!("+{new}"),
+                EventChange::Removed { old, .. } => format!("-{old}"),
+                EventChange::Modified {
+                    old, new, kinds, ..
+                } => {
+                    let kinds: Vec<String> = kinds.iter().map(ToString::to_string).collect();
+                    format!("{old}>{new} {}", kinds.join(","))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_events() {
+        let old = script(&[
+            (1000, 2000, "Default", "Unchanged"),
+            (3000, 4000, "Default", "Retimed line"),
+            (5000, 6000, "Default", "A typo hree"),
+            (7000, 8000, "Default", "Removed"),
+            (9000, 10000, "Default", "Restyled"),
+            (11000, 12000, "Default", "Rewritten"),
+        ]);
+        let new = script(&[
+            (1000, 2000, "Default", "Unchanged"),
+            (3100, 4100, "Default", "Retimed line"),
+            (5000, 6000, "Default", "A typo here"),
+            (7500, 8000, "Default", "Added"),
+            (9000, 10000, "Top", "Restyled"),
+            (11000, 12000, "Default", "Something else entirely"),
+        ]);
+        let diff = diff(&old, &new).unwrap();
+        assert_eq!(
+            summary(&diff),
+            vec![
+                "1>1 retimed",
+                "2>2 text-changed",
+                "-3",
+                "+3",
+                "4>4 restyled",
+                "5>5 text-changed"
+            ]
+        );
+        assert_eq!(
+            diff.styles,
+            vec![StyleChange::Added {
+                name: "Top".to_string()
+            }]
+        );
+        assert!(diff.info.is_empty());
+    }
+
+    #[test]
+    fn test_diff_long_and_instant_events() {
+        let old = script(&[
+            (0, 600_000, "Long sign"),
+            (1000, 2000, "Hello there"),
+            (5000, 5000, "Hidden"),
+        ]);
+        let new = script(&[
+            (0, 600_000, "Long sign!"),
+            (1000, 2000, "Hello there!"),
+            (5000, 5000, "Still hidden"),
+        ]);
+        let diff = diff(&old, &new).unwrap();
+        assert_eq!(
+            summary(&diff),
+            vec!["0>0 text-changed", "1>1 text-changed", "2>2 text-changed"]
+        );
+    }
+
+    #[test]
+    fn test_diff_styles() {
+        let old = script::<(i64, i64, &str)>(&[]);
+        let mut new = script::<(i64, i64, &str)>(&[]);
+        new.styles.entries[0].fontsize = "28".to_string();
+        new.styles.entries.push(StyleStrict {
+            name: "Top".to_string(),
+            ..Default::default()
+        });
+        let diff = diff(&old, &new).unwrap();
+        assert_eq!(
+            diff.styles,
+            vec![
+                StyleChange::Changed {
+                    name: "Default".to_string(),
+                    fields: vec![FieldChange {
+                        field: "Fontsize".to_string(),
+                        old: Some(StyleStrict::default().fontsize),
+                        new: Some("28".to_string()),
+                    }],
+                },
+                StyleChange::Added {
+                    name: "Top".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert!((similarity("night", "nacht") - 0.25).abs() < 1e-9);
+        assert!((similarity("a", "a") - 1.0).abs() < 1e-9);
+        assert!(similarity("A typo hree", "A typo here") > 0.5);
+    }
+}
@@ -3,7 +3,12 @@ use std::str::FromStr;
 
 use anyhow::Context;
 
+use crate::common::serde_via_str;
+use crate::style::StyleStrict;
+use crate::time::AssTime;
+
 #[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Events {
     pub context: EventContext,
     pub entries: Vec<EventStrict>,
@@ -15,6 +20,8 @@ pub struct Events {
 pub enum EventField {
     #[strum(default)]
     Unknown(String),
+    /// SSA predecessor of `Layer`, with values like `Marked=0`
+    Marked,
     Layer,
     Start,
     End,
@@ -28,8 +35,11 @@ pub enum EventField {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EventContext {
     format: Vec<EventField>,
+    /// The Format line as read, written back unchanged
+    line: Option<String>,
 }
 
 impl Default for EventContext {
@@ -47,18 +57,36 @@ impl Default for EventContext {
                 EventField::Effect,
                 EventField::Text,
             ],
+            line: None,
         }
     }
 }
 
 impl EventContext {
+    pub fn format_line(&self) -> String {
+        if let Some(line) = &self.line {
+            return line.clone();
+        }
+        let fields: Vec<String> = self.format.iter().map(EventField::to_string).collect();
+        format!("Format: {}", fields.join(", "))
+    }
+
+    /// Forgets the Format line as read, so it is written with the standard
+    /// `Field, Field` spacing
+    pub(crate) fn normalize_format_line(&mut self) {
+        self.line = None;
+    }
+
     pub fn from_format_line(line: &str) -> anyhow::Result<Self> {
         let (_, fields) = line.split_once(':').context("unable to split on ':'")?;
         let format = fields
             .split(',')
             .map(|x| EventField::from_str(x.trim()))
             .collect::<Result<_, strum::ParseError>>()?;
-        Ok(Self { format })
+        Ok(Self {
+            format,
+            line: Some(line.trim().to_string()),
+        })
     }
 
     pub fn event_from_line(&self, line: &str) -> anyhow::Result<Event> {
@@ -74,8 +102,8 @@ impl EventContext {
         if fields.len() != self.format.len() {
             anyhow::bail!(
                 "wrong number of fields, should: {}, actual: {}",
-                fields.len(),
-                self.format.len()
+                self.format.len(),
+                fields.len()
             );
         }
 
@@ -94,6 +122,7 @@ impl EventContext {
                 EventField::Unknown(x) => {
                     event.unknown_fields.insert(x.clone(), field);
                 }
+                EventField::Marked => event.marked = Some(field),
                 EventField::Layer => event.layer = Some(field),
                 EventField::Start => event.start = Some(field),
                 EventField::End => event.end = Some(field),
@@ -119,25 +148,9 @@ impl EventContext {
         let mut line = format!("{}: ", event.event_type);
 
         for field_type in &self.format {
-            let s = match field_type {
-                EventField::Unknown(x) => format!(
-                    "{}",
-                    event
-                        .unknown_fields
-                        .get(x)
-                        .context(format!("unknown fields did not contain field: {x}"))?
-                ),
-                EventField::Layer => format!("{}", event.layer),
-                EventField::Start => format!("{}", event.start),
-                EventField::End => format!("{}", event.end),
-                EventField::Style => format!("{}", event.style),
-                EventField::Name => format!("{}", event.name),
-                EventField::MarginL => format!("{}", event.margin_l),
-                EventField::MarginR => format!("{}", event.margin_r),
-                EventField::MarginV => format!("{}", event.margin_v),
-                EventField::Effect => format!("{}", event.effect),
-                EventField::Text => format!("{}", event.text),
-            };
+            let s = event.field(field_type).context(format!(
+                "unknown fields did not contain field: {field_type}"
+            ))?;
             line.push_str(&s);
             line.push(',');
         }
@@ -151,6 +164,7 @@ impl EventContext {
 pub struct Event {
     unknown_fields: HashMap<String, String>,
     event_type: EventType,
+    marked: Option<String>,
     layer: Option<String>,
     start: Option<String>,
     end: Option<String>,
@@ -164,38 +178,162 @@ pub struct Event {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EventStrict {
-    unknown_fields: HashMap<String, String>,
-    event_type: EventType,
-    layer: String,
-    start: String,
-    end: String,
-    style: String,
-    name: String,
-    margin_l: String,
-    margin_r: String,
-    margin_v: String,
-    effect: String,
-    text: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::common::serialize_sorted")
+    )]
+    pub unknown_fields: HashMap<String, String>,
+    pub event_type: EventType,
+    /// Only written when the Format line has a `Marked` field
+    pub marked: String,
+    pub layer: String,
+    pub start: String,
+    pub end: String,
+    pub style: String,
+    pub name: String,
+    pub margin_l: String,
+    pub margin_r: String,
+    pub margin_v: String,
+    pub effect: String,
+    pub text: String,
+}
+
+impl EventStrict {
+    /// A dialogue event with default layer and margins
+    pub fn new(start: AssTime, end: AssTime, style: &str, text: &str) -> Self {
+        Self {
+            unknown_fields: HashMap::new(),
+            event_type: EventType::Dialogue,
+            marked: "Marked=0".to_string(),
+            layer: "0".to_string(),
+            start: start.to_string(),
+            end: end.to_string(),
+            style: style.to_string(),
+            name: String::new(),
+            margin_l: "0".to_string(),
+            margin_r: "0".to_string(),
+            margin_v: "0".to_string(),
+            effect: String::new(),
+            text: text.to_string(),
+        }
+    }
+
+    /// Value of a field as written in the Format line, `None` for unknown
+    /// fields the event doesn't have
+    pub fn field(&self, field: &EventField) -> Option<String> {
+        Some(match field {
+            EventField::Unknown(x) => return self.unknown_fields.get(x).cloned(),
+            EventField::Marked => self.marked.clone(),
+            EventField::Layer => self.layer.clone(),
+            EventField::Start => self.start.clone(),
+            EventField::End => self.end.clone(),
+            EventField::Style => self.style.clone(),
+            EventField::Name => self.name.clone(),
+            EventField::MarginL => self.margin_l.clone(),
+            EventField::MarginR => self.margin_r.clone(),
+            EventField::MarginV => self.margin_v.clone(),
+            EventField::Effect => self.effect.clone(),
+            EventField::Text => self.text.clone(),
+        })
+    }
+
+    /// Every field of the event, the V4+ ones in their usual order followed
+    /// by unknown ones sorted by name
+    pub fn fields(&self) -> Vec<(EventField, String)> {
+        let mut unknown: Vec<_> = self.unknown_fields.keys().collect();
+        unknown.sort();
+        EventContext::default()
+            .format
+            .into_iter()
+            .chain(unknown.into_iter().map(|x| EventField::Unknown(x.clone())))
+            .filter_map(|x| self.field(&x).map(|value| (x, value)))
+            .collect()
+    }
+
+    /// Sets a field by its name in the Format line, such as `Text`. Unknown
+    /// fields can only be set if the event already has them.
+    pub fn set_field(&mut self, field: &EventField, value: &str) -> anyhow::Result<()> {
+        let value = value.to_string();
+        match field {
+            EventField::Unknown(x) => {
+                *self
+                    .unknown_fields
+                    .get_mut(x)
+                    .context(format!("event has no field {x}"))? = value;
+            }
+            EventField::Marked => self.marked = value,
+            EventField::Layer => self.layer = value,
+            EventField::Start => self.start = value,
+            EventField::End => self.end = value,
+            EventField::Style => self.style = value,
+            EventField::Name => self.name = value,
+            EventField::MarginL => self.margin_l = value,
+            EventField::MarginR => self.margin_r = value,
+            EventField::MarginV => self.margin_v = value,
+            EventField::Effect => self.effect = value,
+            EventField::Text => self.text = value,
+        }
+        Ok(())
+    }
+
+    pub fn start_time(&self) -> anyhow::Result<AssTime> {
+        AssTime::from_str(&self.start).context(format!("invalid Start: {}", self.start))
+    }
+
+    pub fn end_time(&self) -> anyhow::Result<AssTime> {
+        AssTime::from_str(&self.end).context(format!("invalid End: {}", self.end))
+    }
+
+    pub fn set_start_time(&mut self, time: AssTime) {
+        self.start = time.to_string();
+    }
+
+    pub fn set_end_time(&mut self, time: AssTime) {
+        self.end = time.to_string();
+    }
+
+    /// Left, right and vertical margins, where zero falls back to the style
+    pub fn margins(&self, style: Option<&StyleStrict>) -> (f64, f64, f64) {
+        let margin = |event: &str, style: Option<&String>| -> f64 {
+            match event.parse::<f64>() {
+                Ok(x) if x != 0.0 => x,
+                _ => style.and_then(|x| x.parse().ok()).unwrap_or_default(),
+            }
+        };
+        (
+            margin(&self.margin_l, style.map(|x| &x.margin_l)),
+            margin(&self.margin_r, style.map(|x| &x.margin_r)),
+            margin(&self.margin_v, style.map(|x| &x.margin_v)),
+        )
+    }
 }
 
+/// Only the timing and text are required, other fields missing from the
+/// Format line get the values libass assumes for them
 impl TryFrom<Event> for EventStrict {
     type Error = anyhow::Error;
 
     fn try_from(value: Event) -> Result<Self, Self::Error> {
+        let start = value.start.context("Start not found")?;
+        let end = value.end.context("End not found")?;
+        let text = value.text.context("Text not found")?;
+        let default = Self::new(AssTime::ZERO, AssTime::ZERO, "Default", "");
         Ok(Self {
             unknown_fields: value.unknown_fields,
             event_type: value.event_type,
-            layer: value.layer.context("Layer not found")?,
-            start: value.start.context("Start not found")?,
-            end: value.end.context("End not found")?,
-            style: value.style.context("Style not found")?,
-            name: value.name.context("Name not found")?,
-            margin_l: value.margin_l.context("MarginL not found")?,
-            margin_r: value.margin_r.context("MarginR not found")?,
-            margin_v: value.margin_v.context("MarginV not found")?,
-            effect: value.effect.context("Effect not found")?,
-            text: value.text.context("Text not found")?,
+            marked: value.marked.unwrap_or(default.marked),
+            layer: value.layer.unwrap_or(default.layer),
+            start,
+            end,
+            style: value.style.unwrap_or(default.style),
+            name: value.name.unwrap_or(default.name),
+            margin_l: value.margin_l.unwrap_or(default.margin_l),
+            margin_r: value.margin_r.unwrap_or(default.margin_r),
+            margin_v: value.margin_v.unwrap_or(default.margin_v),
+            effect: value.effect.unwrap_or(default.effect),
+            text,
         })
     }
 }
@@ -214,6 +352,8 @@ pub enum EventType {
     Command,
 }
 
+serde_via_str!(EventField, EventType);
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -228,6 +368,7 @@ mod tests {
     #[rstest]
     #[case("Name", EventField::Name)]
     #[case("MarginV", EventField::MarginV)]
+    #[case("Marked", EventField::Marked)]
     #[case("FooBar", EventField::Unknown("FooBar".to_string()))]
     fn test_style_field_from_str(#[case] got: &str, #[case] should: EventField) {
         let result = EventField::from_str(got).unwrap();
@@ -260,10 +401,20 @@ mod tests {
         DEFAULT_EVENT_FORMAT,
         r"Comment: 0,0:04:12.94,0:04:12.98,op-en,,0,0,0,,==========OP=========="
     )]
+    #[case::ssa(
+        "Format: Marked, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text",
+        r"Dialogue: Marked=0,0:00:01.00,0:00:02.00,Default,,0000,0000,0000,,Hi, there"
+    )]
+    #[case::minimal("Format: Start, End, Text", r"Dialogue: 0:00:01.00,0:00:02.00,Hi")]
+    #[case::reordered(
+        "Format: Text, Start, End, Style",
+        r"Dialogue: Hi,0:00:01.00,0:00:02.00,Default"
+    )]
     fn test_style_lossless(#[case] format: &str, #[case] line_before: &str) {
         let context = EventContext::from_format_line(format).unwrap();
+        assert_eq!(context.format_line(), format);
         let parsed = context.event_strict_from_line(line_before).unwrap();
         let line_after = context.line_from_event_strict(&parsed).unwrap();
-        assert_eq!(line_after, line_before)
+        assert_eq!(line_after, line_before);
     }
 }
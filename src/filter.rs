@@ -0,0 +1,454 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Context;
+use regex::Regex;
+
+use crate::classify::Classifier;
+use crate::classify::EventKind;
+use crate::event::EventStrict;
+use crate::event::Events;
+use crate::tag;
+use crate::time::AssTime;
+use crate::AssScript;
+
+/// A glob such as `Default*`, or a regular expression written as `/…/`.
+/// Globs match the whole value, regular expressions any part of it.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn is_match(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let regex = match s.strip_prefix('/').and_then(|x| x.strip_suffix('/')) {
+            Some(x) if s.len() > 1 => Regex::new(x)?,
+            _ => {
+                let mut pattern = String::from("^");
+                for c in s.chars() {
+                    match c {
+                        '*' => pattern.push_str(".*"),
+                        '?' => pattern.push('.'),
+                        c => pattern.push_str(&regex::escape(&c.to_string())),
+                    }
+                }
+                pattern.push('$');
+                Regex::new(&pattern)?
+            }
+        };
+        Ok(Self {
+            source: s.to_string(),
+            regex,
+        })
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// A query over events. Terms are written `key:value`, separated by spaces
+/// to require all of them, or `or` to require any. `not` or `-` negates a
+/// term, and parentheses group them:
+///
+/// - `type:comment`
+/// - `style:Default*`, `name:Levi`, `effect:/^Banner/`
+/// - `layer:1`, `layer:1..3`, `layer:2..`
+/// - `time:0:01:00..0:02:00`, events overlapping the range
+/// - `text:"*- JP*"`, matching the raw text with tags
+/// - `plain:/^\s*$/`, matching the displayed text
+/// - `tag:pos`, events using an override tag
+/// - `kind:sign`, events labeled by [`Classifier`]
+#[derive(Debug, Clone)]
+pub enum Filter {
+    All,
+    Type(String),
+    Style(Pattern),
+    Name(Pattern),
+    Effect(Pattern),
+    Text(Pattern),
+    Plain(Pattern),
+    Layer(Option<i64>, Option<i64>),
+    Time(Option<AssTime>, Option<AssTime>),
+    Tag(String),
+    Kind(EventKind),
+    Not(Box<Filter>),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+}
+
+impl Filter {
+    /// Whether the filter matches, classifying the event on its own for
+    /// `kind:` terms
+    pub fn matches(&self, event: &EventStrict) -> bool {
+        self.matches_in(event, &Classifier::default())
+    }
+
+    /// Whether the filter matches, classifying the event with `classifier`
+    /// for `kind:` terms
+    pub fn matches_in(&self, event: &EventStrict, classifier: &Classifier) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::Type(x) => event.event_type.to_string().eq_ignore_ascii_case(x),
+            Filter::Style(x) => x.is_match(&event.style),
+            Filter::Name(x) => x.is_match(&event.name),
+            Filter::Effect(x) => x.is_match(&event.effect),
+            Filter::Text(x) => x.is_match(&event.text),
+            Filter::Plain(x) => x.is_match(&tag::plain_text(&event.text)),
+            Filter::Layer(min, max) => event.layer.parse::<i64>().is_ok_and(|layer| {
+                min.is_none_or(|x| layer >= x) && max.is_none_or(|x| layer <= x)
+            }),
+            Filter::Time(from, to) => {
+                let (Ok(start), Ok(end)) = (event.start_time(), event.end_time()) else {
+                    return false;
+                };
+                from.is_none_or(|x| end > x) && to.is_none_or(|x| start < x)
+            }
+            Filter::Tag(name) => {
+                let mut found = false;
+                tag::rewrite_tags(&event.text, &mut |tag| {
+                    found |= tag.name == name;
+                    None
+                });
+                found
+            }
+            Filter::Kind(x) => classifier.classify(event) == *x,
+            Filter::Not(x) => !x.matches_in(event, classifier),
+            Filter::And(x) => x.iter().all(|x| x.matches_in(event, classifier)),
+            Filter::Or(x) => x.iter().any(|x| x.matches_in(event, classifier)),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Ok(Filter::All);
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let filter = parser.or()?;
+        anyhow::ensure!(
+            parser.position == parser.tokens.len(),
+            "unexpected {:?} in filter: {s}",
+            parser.tokens[parser.position]
+        );
+        Ok(filter)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Or,
+    Not,
+    Term(String, String),
+}
+
+fn tokenize(s: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '-' | '!' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = chars.next_if(|c| *c != ':' && !c.is_whitespace() && *c != ')')
+                {
+                    key.push(c);
+                }
+                match key.to_ascii_lowercase().as_str() {
+                    "or" => tokens.push(Token::Or),
+                    "not" => tokens.push(Token::Not),
+                    "and" => {}
+                    _ => {
+                        anyhow::ensure!(
+                            chars.next() == Some(':'),
+                            "expected key:value, found {key}"
+                        );
+                        tokens.push(Token::Term(key, value(&mut chars)?));
+                    }
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads a value, which is quoted, a `/regex/` or runs until whitespace
+fn value(chars: &mut std::iter::Peekable<std::str::Chars>) -> anyhow::Result<String> {
+    let mut value = String::new();
+    match chars.peek() {
+        Some('"') => {
+            chars.next();
+            loop {
+                match chars.next().context("unterminated quote in filter")? {
+                    '"' => break,
+                    '\\' if chars.next_if_eq(&'"').is_some() => value.push('"'),
+                    c => value.push(c),
+                }
+            }
+        }
+        Some('/') => {
+            value.push('/');
+            chars.next();
+            loop {
+                let c = chars.next().context("unterminated regex in filter")?;
+                value.push(c);
+                match c {
+                    '/' => break,
+                    '\\' => value.push(chars.next().context("unterminated regex in filter")?),
+                    _ => {}
+                }
+            }
+        }
+        _ => {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ')') {
+                value.push(c);
+            }
+        }
+    }
+    Ok(value)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn or(&mut self) -> anyhow::Result<Filter> {
+        let mut terms = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            terms.push(self.and()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            Filter::Or(terms)
+        })
+    }
+
+    fn and(&mut self) -> anyhow::Result<Filter> {
+        let mut terms = Vec::new();
+        while matches!(
+            self.peek(),
+            Some(Token::Open | Token::Not | Token::Term(..))
+        ) {
+            terms.push(self.unary()?);
+        }
+        anyhow::ensure!(!terms.is_empty(), "expected a filter term");
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            Filter::And(terms)
+        })
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Filter> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Not) => Ok(Filter::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let filter = self.or()?;
+                anyhow::ensure!(self.peek() == Some(&Token::Close), "missing ) in filter");
+                self.position += 1;
+                Ok(filter)
+            }
+            Some(Token::Term(key, value)) => term(&key, &value),
+            x => anyhow::bail!("unexpected {x:?} in filter"),
+        }
+    }
+}
+
+fn term(key: &str, value: &str) -> anyhow::Result<Filter> {
+    let range = |value: &str| -> (String, Option<String>) {
+        match value.split_once("..") {
+            Some((from, to)) => (from.to_string(), Some(to.to_string())),
+            None => (value.to_string(), None),
+        }
+    };
+    let bound = |x: &str| -> anyhow::Result<Option<i64>> {
+        Ok(if x.is_empty() { None } else { Some(x.parse()?) })
+    };
+
+    Ok(match key.to_ascii_lowercase().as_str() {
+        "type" => Filter::Type(value.to_string()),
+        "style" => Filter::Style(value.parse()?),
+        "name" | "actor" => Filter::Name(value.parse()?),
+        "effect" => Filter::Effect(value.parse()?),
+        "text" => Filter::Text(value.parse()?),
+        "plain" => Filter::Plain(value.parse()?),
+        "tag" => Filter::Tag(value.trim_start_matches('\\').to_string()),
+        "kind" => Filter::Kind(value.parse()?),
+        "layer" => match range(value) {
+            (from, Some(to)) => Filter::Layer(bound(&from)?, bound(&to)?),
+            (x, None) => Filter::Layer(bound(&x)?, bound(&x)?),
+        },
+        "time" => {
+            let (from, to) = range(value);
+            let to = to.context("time filters need a range, such as 0:01:00..0:02:00")?;
+            let time = |x: &str| -> anyhow::Result<Option<AssTime>> {
+                Ok(if x.is_empty() { None } else { Some(x.parse()?) })
+            };
+            Filter::Time(time(&from)?, time(&to)?)
+        }
+        _ => anyhow::bail!("unknown filter key: {key}"),
+    })
+}
+
+/// Events to keep: those matching any include filter, or all when there are
+/// none, that match no exclude filter
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub include: Vec<Filter>,
+    pub exclude: Vec<Filter>,
+}
+
+impl Selection {
+    pub fn matches(&self, event: &EventStrict) -> bool {
+        self.matches_in(event, &Classifier::default())
+    }
+
+    pub fn matches_in(&self, event: &EventStrict, classifier: &Classifier) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|x| x.matches_in(event, classifier)))
+            && !self.exclude.iter().any(|x| x.matches_in(event, classifier))
+    }
+}
+
+impl Events {
+    /// Keeps only the events in `selection`
+    pub fn retain_selected(&mut self, selection: &Selection) {
+        self.entries.retain(|x| selection.matches(x));
+    }
+}
+
+impl AssScript {
+    /// Keeps only the events in `selection`, classifying them in the context
+    /// of the whole script
+    pub fn retain_selected(&mut self, selection: &Selection) {
+        let classifier = Classifier::new(self);
+        self.events
+            .entries
+            .retain(|x| selection.matches_in(x, &classifier));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::event::EventType;
+
+    fn event() -> EventStrict {
+        let mut event = EventStrict::new(
+            AssTime(60_000),
+            AssTime(63_000),
+            "DefaultEN",
+            r"{\pos(10,10)\t(\fs20)}Hello - JP",
+        );
+        event.name = "Levi Ackerman".to_string();
+        event.layer = "2".to_string();
+        event
+    }
+
+    #[rstest]
+    #[case("", true)]
+    #[case("type:dialogue", true)]
+    #[case("type:comment", false)]
+    #[case("style:Default", false)]
+    #[case("style:Default*", true)]
+    #[case("style:/EN$/", true)]
+    #[case("name:\"Levi *\"", true)]
+    #[case("actor:Levi", false)]
+    #[case("layer:2", true)]
+    #[case("layer:0..1", false)]
+    #[case("layer:1..", true)]
+    #[case("time:0:01:02..0:02:00", true)]
+    #[case("time:..0:01:00", false)]
+    #[case("text:\"*- JP\"", true)]
+    #[case("text:/^Hello/", false)]
+    #[case("plain:/^Hello/", true)]
+    #[case("tag:pos", true)]
+    #[case(r"tag:\fs", true)]
+    #[case("tag:move", false)]
+    #[case("style:Default* layer:0", false)]
+    #[case("style:Default* and layer:2", true)]
+    #[case("layer:0 or tag:pos", true)]
+    #[case("-tag:pos", false)]
+    #[case("not (layer:0 or tag:move)", true)]
+    #[case("effect:/ /", false)]
+    #[case(r#"text:"*\"quoted\"*" or layer:2"#, true)]
+    #[case("kind:sign", true)]
+    #[case("kind:Dialogue", false)]
+    fn test_filter_matches(#[case] filter: &str, #[case] should: bool) {
+        let filter = Filter::from_str(filter).unwrap();
+        assert_eq!(filter.matches(&event()), should);
+    }
+
+    #[rstest]
+    #[case("style")]
+    #[case("colour:red")]
+    #[case("(layer:1")]
+    #[case("layer:1)")]
+    #[case("text:/unterminated")]
+    #[case("time:0:01:00")]
+    #[case("kind:credits")]
+    fn test_filter_invalid(#[case] filter: &str) {
+        assert!(Filter::from_str(filter).is_err());
+    }
+
+    #[test]
+    fn test_retain_selected() {
+        let mut comment = event();
+        comment.event_type = EventType::Comment;
+        let mut events = Events {
+            entries: vec![event(), comment],
+            ..Default::default()
+        };
+        events.retain_selected(&Selection {
+            include: vec!["style:DefaultEN".parse().unwrap()],
+            exclude: vec!["type:comment".parse().unwrap()],
+        });
+        assert_eq!(events.entries.len(), 1);
+        assert_eq!(events.entries[0].event_type, EventType::Dialogue);
+    }
+}
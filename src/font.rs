@@ -0,0 +1,485 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::attachment;
+use crate::common::Boolean;
+use crate::event::EventType;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::tag::Tag;
+use crate::tag::TextPart;
+use crate::AssScript;
+
+const FONT_EXTENSIONS: [&str; 4] = ["ttf", "otf", "ttc", "otc"];
+
+/// Where a font face was loaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FontSource {
+    File(PathBuf),
+    /// Embedded in the script's [Fonts] section under the given name
+    Embedded(String),
+    /// Attached to a Matroska file under the given name
+    Attachment(PathBuf, String),
+}
+
+impl fmt::Display for FontSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontSource::File(path) => write!(f, "{}", path.display()),
+            FontSource::Embedded(name) => write!(f, "[Fonts] {name}"),
+            FontSource::Attachment(path, name) => write!(f, "{} attachment {name}", path.display()),
+        }
+    }
+}
+
+/// A single face of a font file, collections contain several
+#[derive(Debug, Clone)]
+pub struct FontFace {
+    /// Family, full and PostScript names in every language the font has. The
+    /// typographic family is left out as renderers don't match on it.
+    pub names: Vec<String>,
+    pub bold: bool,
+    pub italic: bool,
+    pub source: FontSource,
+    pub index: u32,
+    data: Arc<[u8]>,
+}
+
+impl FontFace {
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn parse(&self) -> anyhow::Result<ttf_parser::Face<'_>> {
+        Ok(ttf_parser::Face::parse(&self.data, self.index)?)
+    }
+
+    /// Whether any of the face's names match, ignoring case like renderers do
+    pub fn matches(&self, family: &str) -> bool {
+        self.names.iter().any(|x| x.eq_ignore_ascii_case(family))
+    }
+
+    /// Characters from `chars` the face has no glyph for
+    pub fn missing_glyphs(&self, chars: &BTreeSet<char>) -> anyhow::Result<Vec<char>> {
+        let face = self.parse()?;
+        Ok(chars
+            .iter()
+            .copied()
+            .filter(|c| !c.is_control() && face.glyph_index(*c).is_none())
+            .collect())
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct FontDatabase {
+    pub faces: Vec<FontFace>,
+}
+
+impl FontDatabase {
+    /// Adds every face in a TTF, OTF or collection file. Data that doesn't
+    /// parse as a font is ignored and zero is returned.
+    pub fn load_data(&mut self, data: Vec<u8>, source: &FontSource) -> usize {
+        let data: Arc<[u8]> = data.into();
+        let count = ttf_parser::fonts_in_collection(&data).unwrap_or(1);
+        let mut loaded = 0;
+
+        for index in 0..count {
+            let Ok(face) = ttf_parser::Face::parse(&data, index) else {
+                continue;
+            };
+
+            let mut names = Vec::new();
+            for name in face.names() {
+                let wanted = [
+                    ttf_parser::name_id::FAMILY,
+                    ttf_parser::name_id::FULL_NAME,
+                    ttf_parser::name_id::POST_SCRIPT_NAME,
+                ];
+                if let Some(x) = name.to_string().filter(|_| wanted.contains(&name.name_id)) {
+                    if !names.contains(&x) {
+                        names.push(x);
+                    }
+                }
+            }
+
+            self.faces.push(FontFace {
+                names,
+                bold: face.is_bold(),
+                italic: face.is_italic(),
+                source: source.clone(),
+                index,
+                data: data.clone(),
+            });
+            loaded += 1;
+        }
+
+        loaded
+    }
+
+    /// Recursively loads all font files below a directory. Files and
+    /// directories below it that can't be read are skipped, and symlinked
+    /// directories aren't followed so that links can't loop.
+    pub fn load_dir(&mut self, dir: &Path) -> anyhow::Result<()> {
+        for entry in std::fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            if entry.file_type().is_ok_and(|x| x.is_dir()) {
+                self.load_dir(&path).ok();
+                continue;
+            }
+
+            let is_font = path
+                .extension()
+                .and_then(|x| x.to_str())
+                .is_some_and(|x| FONT_EXTENSIONS.contains(&x.to_ascii_lowercase().as_str()));
+            if let Some(data) = is_font.then(|| std::fs::read(&path).ok()).flatten() {
+                self.load_data(data, &FontSource::File(path));
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the fonts embedded in the script's [Fonts] section
+    pub fn load_script(&mut self, script: &AssScript) -> anyhow::Result<()> {
+        let Some(lines) = script.other_sections.get("Fonts") else {
+            return Ok(());
+        };
+        for x in attachment::decode_embedded(lines)? {
+            self.load_data(x.data, &FontSource::Embedded(x.name));
+        }
+        Ok(())
+    }
+
+    /// Loads the fonts attached to a Matroska file
+    pub fn load_mkv(&mut self, path: &Path) -> anyhow::Result<()> {
+        for x in attachment::mkv_attachments(path)? {
+            let source = FontSource::Attachment(path.to_path_buf(), x.name);
+            self.load_data(x.data, &source);
+        }
+        Ok(())
+    }
+
+    /// Finds the face that best matches a family and style. A face of the
+    /// right family but wrong weight or slant still matches, since renderers
+    /// will synthesize bold and italic.
+    pub fn find(&self, request: &FontRequest) -> Option<&FontFace> {
+        self.faces
+            .iter()
+            .filter(|x| x.matches(request.lookup_name()))
+            .max_by_key(|x| (x.bold == request.bold, x.italic == request.italic))
+    }
+
+    /// The face renderers are likely to use in place of a family that isn't
+    /// found: a common sans-serif family if there is one, else any face
+    pub fn fallback(&self, request: &FontRequest) -> Option<&FontFace> {
+        FALLBACK_FAMILIES
+            .iter()
+            .find_map(|family| {
+                self.find(&FontRequest {
+                    family: (*family).to_string(),
+                    ..request.clone()
+                })
+            })
+            .or_else(|| self.faces.first())
+    }
+}
+
+/// Families tried in order by [`FontDatabase::fallback`]
+const FALLBACK_FAMILIES: &[&str] = &[
+    "Arial",
+    "Helvetica",
+    "Liberation Sans",
+    "DejaVu Sans",
+    "Noto Sans",
+];
+
+/// Font directories of the current platform that exist
+pub fn system_font_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![
+        PathBuf::from("/usr/share/fonts"),
+        PathBuf::from("/usr/local/share/fonts"),
+        PathBuf::from("/Library/Fonts"),
+        PathBuf::from("/System/Library/Fonts"),
+        PathBuf::from(r"C:\Windows\Fonts"),
+    ];
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        dirs.push(home.join(".fonts"));
+        dirs.push(home.join(".local/share/fonts"));
+        dirs.push(home.join("Library/Fonts"));
+    }
+    if let Some(local) = std::env::var_os("LOCALAPPDATA").map(PathBuf::from) {
+        dirs.push(local.join(r"Microsoft\Windows\Fonts"));
+    }
+    dirs.retain(|x| x.is_dir());
+    dirs
+}
+
+/// A font as requested by a script, through a style or override tags
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FontRequest {
+    pub family: String,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+impl FontRequest {
+    pub(crate) fn from_style(style: &StyleStrict) -> Self {
+        Self {
+            family: style.fontname.clone(),
+            bold: style.bold == Boolean::True,
+            italic: style.italic == Boolean::True,
+        }
+    }
+
+    /// Follows a `\fn`, `\b` or `\i` tag, where an empty or invalid
+    /// argument goes back to the style
+    pub(crate) fn apply_tag(&mut self, tag: &Tag, style: &StyleStrict) {
+        match tag.name {
+            "fn" if tag.args.is_empty() => self.family.clone_from(&style.fontname),
+            "fn" => self.family = tag.args.to_string(),
+            "b" => {
+                self.bold = match tag.args.parse::<u32>() {
+                    Ok(0) => false,
+                    Ok(1) => true,
+                    Ok(weight) => weight >= 700,
+                    Err(_) => style.bold == Boolean::True,
+                };
+            }
+            "i" => {
+                self.italic = match tag.args {
+                    "0" => false,
+                    "1" => true,
+                    _ => style.italic == Boolean::True,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    /// Family name used for matching, a leading `@` only selects vertical
+    /// layout
+    pub fn lookup_name(&self) -> &str {
+        self.family.strip_prefix('@').unwrap_or(&self.family)
+    }
+}
+
+impl fmt::Display for FontRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.family)?;
+        match (self.bold, self.italic) {
+            (true, true) => write!(f, " (bold italic)"),
+            (true, false) => write!(f, " (bold)"),
+            (false, true) => write!(f, " (italic)"),
+            (false, false) => Ok(()),
+        }
+    }
+}
+
+/// Every font used by a script with the characters rendered in it
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct FontUsage {
+    pub fonts: BTreeMap<FontRequest, BTreeSet<char>>,
+}
+
+impl FontUsage {
+    /// Walks the text of all dialogue events, following style changes from
+    /// `\fn`, `\b`, `\i` and `\r`
+    pub fn from_script(script: &AssScript) -> Self {
+        let mut usage = Self::default();
+        let find_style = |name: &str| script.styles.entries.iter().find(|x| x.name == name);
+
+        for event in &script.events.entries {
+            if event.event_type != EventType::Dialogue {
+                continue;
+            }
+            let Some(style) = find_style(&event.style).or_else(|| find_style("Default")) else {
+                continue;
+            };
+
+            let mut font = FontRequest::from_style(style);
+            let mut drawing = false;
+            for part in tag::split_text(&event.text) {
+                match part {
+                    TextPart::Override(block) => {
+                        for tag in tag::parse_tags(block) {
+                            match tag.name {
+                                "fn" | "b" | "i" => font.apply_tag(&tag, style),
+                                "r" => {
+                                    let reset = find_style(tag.args).unwrap_or(style);
+                                    font = FontRequest::from_style(reset);
+                                }
+                                "p" => drawing = tag.args.parse::<u32>().unwrap_or(0) > 0,
+                                _ => {}
+                            }
+                        }
+                    }
+                    TextPart::Text(x) if !drawing => {
+                        let chars = usage.fonts.entry(font.clone()).or_default();
+                        chars.extend(tag::unescape(x).chars().filter(|c| *c != '\n'));
+                    }
+                    TextPart::Text(_) => {}
+                }
+            }
+        }
+
+        usage
+    }
+}
+
+/// How well the fonts available can render a font request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontStatus {
+    pub request: FontRequest,
+    /// Source of the matched face, `None` if the font is missing
+    pub source: Option<FontSource>,
+    pub missing_glyphs: Vec<char>,
+}
+
+/// Matches every font in the usage report against the database
+pub fn check_fonts(usage: &FontUsage, db: &FontDatabase) -> anyhow::Result<Vec<FontStatus>> {
+    let mut statuses = Vec::new();
+    for (request, chars) in &usage.fonts {
+        let status = match db.find(request) {
+            Some(face) => FontStatus {
+                request: request.clone(),
+                source: Some(face.source.clone()),
+                missing_glyphs: face.missing_glyphs(chars)?,
+            },
+            None => FontStatus {
+                request: request.clone(),
+                source: None,
+                missing_glyphs: chars.iter().copied().collect(),
+            },
+        };
+        statuses.push(status);
+    }
+    Ok(statuses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const SCRIPT: &str = r"[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Roboto Medium,26,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,1.3,0,2,20,20,23,0
+Style: Sign,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,-1,0,0,0,100,100,0,0,1,2,0,8,10,10,10,0
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,ab{\i1}c{\fn@Foo}d{\rSign}e\Nf
+Dialogue: 0,0:00:00.00,0:00:01.00,Missing,,0,0,0,,{\p1}m 0 0 l 1 1{\p0}g
+Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,h";
+
+    #[test]
+    fn test_font_usage() {
+        let script = AssScript::try_from_str(SCRIPT).unwrap();
+        let usage = FontUsage::from_script(&script);
+
+        let request = |family: &str, bold, italic| FontRequest {
+            family: family.to_string(),
+            bold,
+            italic,
+        };
+        let expected = BTreeMap::from([
+            (
+                request("Roboto Medium", false, false),
+                BTreeSet::from(['a', 'b', 'g']),
+            ),
+            (request("Roboto Medium", false, true), BTreeSet::from(['c'])),
+            (request("@Foo", false, true), BTreeSet::from(['d'])),
+            (request("Arial", true, false), BTreeSet::from(['e', 'f'])),
+        ]);
+        assert_eq!(usage.fonts, expected);
+        assert_eq!(request("@Foo", false, false).lookup_name(), "Foo");
+    }
+
+    #[test]
+    fn test_find() {
+        let db = testing::fonts();
+        let request = |family: &str, bold| FontRequest {
+            family: family.to_string(),
+            bold,
+            italic: false,
+        };
+        let source = |x: Option<&FontFace>| x.map(|x| x.source.to_string());
+        // family and PostScript names, ignoring case and weight
+        assert_eq!(
+            source(db.find(&request("subass test", true))),
+            Some("SubassTest.ttf".to_string())
+        );
+        assert_eq!(
+            source(db.find(&request("@SubassTestCFF", false))),
+            Some("SubassTestCFF.otf".to_string())
+        );
+        assert_eq!(source(db.find(&request("Arial", false))), None);
+        assert_eq!(
+            source(db.fallback(&request("Arial", false))),
+            Some("SubassTest.ttf".to_string())
+        );
+    }
+
+    #[test]
+    fn test_missing_glyphs() {
+        let mut script = testing::script(&[(0, 1000, "AB x{\\fnSubass Test CID}IÄ\\Nz")]);
+        script.styles.entries[0].fontname = "Subass Test".to_string();
+        let usage = FontUsage::from_script(&script);
+        let statuses = check_fonts(&usage, &testing::fonts()).unwrap();
+        let missing: Vec<(&str, Vec<char>)> = statuses
+            .iter()
+            .map(|x| (x.request.family.as_str(), x.missing_glyphs.clone()))
+            .collect();
+        assert_eq!(
+            missing,
+            vec![("Subass Test", vec!['x']), ("Subass Test CID", vec!['z'])]
+        );
+    }
+
+    #[test]
+    fn test_load_mkv() {
+        let mut db = FontDatabase::default();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/fonts/SubassTest.mkv");
+        db.load_mkv(&path).unwrap();
+        let sources: Vec<String> = db.faces.iter().map(|x| x.source.to_string()).collect();
+        assert_eq!(
+            sources,
+            [
+                format!("{} attachment SubassTest.ttf", path.display()),
+                format!("{} attachment SubassTestCFF.otf", path.display())
+            ]
+        );
+        assert!(db.faces[1].matches("Subass Test CFF"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_load_dir() {
+        let dir = std::env::temp_dir().join(format!("subass-load-dir-{}", std::process::id()));
+        let fonts = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/fonts");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::copy(fonts.join("SubassTest.ttf"), dir.join("sub/a.TTF")).unwrap();
+        // a dangling link can't be read, a link to the directory would loop
+        std::os::unix::fs::symlink(dir.join("gone.ttf"), dir.join("broken.ttf")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+
+        let mut db = FontDatabase::default();
+        let result = db.load_dir(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+        assert_eq!(db.faces.len(), 1);
+        assert!(db.faces[0].matches("Subass Test"));
+    }
+
+    #[test]
+    fn test_missing_font() {
+        let script = AssScript::try_from_str(SCRIPT).unwrap();
+        let usage = FontUsage::from_script(&script);
+        let statuses = check_fonts(&usage, &FontDatabase::default()).unwrap();
+        assert_eq!(statuses.len(), 4);
+        assert!(statuses.iter().all(|x| x.source.is_none()));
+    }
+}
@@ -0,0 +1,244 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::event::EventStrict;
+use crate::event::Events;
+use crate::time::AssTime;
+
+/// The time an event is shown, from `start` up to but not including `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: AssTime,
+    pub end: AssTime,
+    /// Index into the events the index was built from
+    pub index: usize,
+}
+
+/// Event times sorted for point and range queries, as an interval tree laid
+/// out over the sorted spans: the middle span of every range stores the
+/// latest end within that range, so queries skip ranges that end too early.
+#[derive(Debug, Clone, Default)]
+pub struct EventIndex {
+    /// Sorted by start, then end, then index
+    spans: Vec<Span>,
+    max_end: Vec<AssTime>,
+}
+
+impl EventIndex {
+    pub fn new(events: &[EventStrict]) -> anyhow::Result<Self> {
+        let mut spans = events
+            .iter()
+            .enumerate()
+            .map(|(index, x)| {
+                Ok(Span {
+                    start: x.start_time()?,
+                    end: x.end_time()?,
+                    index,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        spans.sort_unstable_by_key(|x| (x.start, x.end, x.index));
+
+        let mut index = Self {
+            max_end: vec![AssTime::ZERO; spans.len()],
+            spans,
+        };
+        index.build(0, index.spans.len());
+        Ok(index)
+    }
+
+    /// Fills in the latest end of `lo..hi` and the ranges below it
+    fn build(&mut self, lo: usize, hi: usize) -> Option<AssTime> {
+        if lo >= hi {
+            return None;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let max_end = [self.build(lo, mid), self.build(mid + 1, hi)]
+            .into_iter()
+            .flatten()
+            .fold(self.spans[mid].end, AssTime::max);
+        self.max_end[mid] = max_end;
+        Some(max_end)
+    }
+
+    /// Collects the spans of `lo..hi` that overlap `start..end` in order
+    fn query<'a>(
+        &'a self,
+        lo: usize,
+        hi: usize,
+        start: AssTime,
+        end: AssTime,
+        out: &mut Vec<&'a Span>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.max_end[mid] <= start {
+            return;
+        }
+        self.query(lo, mid, start, end, out);
+        let span = &self.spans[mid];
+        if span.start < end {
+            if span.end > start {
+                out.push(span);
+            }
+            self.query(mid + 1, hi, start, end, out);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Events shown at `time`, by start time
+    pub fn at(&self, time: AssTime) -> Vec<&Span> {
+        self.overlapping(time, AssTime(time.millis() + 1))
+    }
+
+    /// Events shown at some point from `start` up to `end`, by start time
+    pub fn overlapping(&self, start: AssTime, end: AssTime) -> Vec<&Span> {
+        let mut out = Vec::new();
+        self.query(0, self.spans.len(), start, end, &mut out);
+        out
+    }
+
+    /// Every pair of events shown at the same time, the one that starts
+    /// first on the left. Events with no duration are never shown, so they
+    /// don't overlap anything.
+    pub fn overlaps(&self) -> Vec<(&Span, &Span)> {
+        let mut pairs = Vec::new();
+        // events that may still be shown, earliest end first
+        let mut active: BinaryHeap<Reverse<(AssTime, usize)>> = BinaryHeap::new();
+        for (i, span) in self.spans.iter().enumerate() {
+            if span.end <= span.start {
+                continue;
+            }
+            while active.peek().is_some_and(|x| x.0 .0 <= span.start) {
+                active.pop();
+            }
+            let mut earlier: Vec<usize> = active.iter().map(|x| x.0 .1).collect();
+            earlier.sort_unstable();
+            pairs.extend(earlier.into_iter().map(|j| (&self.spans[j], span)));
+            active.push(Reverse((span.end, i)));
+        }
+        pairs
+    }
+
+    /// All events by start time, then end time
+    pub fn iter(&self) -> std::slice::Iter<'_, Span> {
+        self.spans.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a EventIndex {
+    type Item = &'a Span;
+    type IntoIter = std::slice::Iter<'a, Span>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Events {
+    /// Indexes the events by time. The index refers to events by position,
+    /// so it must be rebuilt after events are added, removed or retimed.
+    pub fn index(&self) -> anyhow::Result<EventIndex> {
+        EventIndex::new(&self.entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing::script;
+
+    /// Deterministic events of varied lengths, some long and some empty
+    fn events(count: i64) -> Vec<EventStrict> {
+        let events: Vec<(i64, i64, &str)> = (0..count)
+            .map(|i| {
+                let start = (i * 7919) % 100_000;
+                let length = match i % 10 {
+                    0 => 0,
+                    1 => 30_000,
+                    x => x * 350,
+                };
+                (start, start + length, "")
+            })
+            .collect();
+        script(&events).events.entries
+    }
+
+    fn indexes(spans: &[&Span]) -> Vec<usize> {
+        spans.iter().map(|x| x.index).collect()
+    }
+
+    #[rstest]
+    #[case(0, 1)]
+    #[case(5_000, 5_001)]
+    #[case(12_000, 20_000)]
+    #[case(99_000, 200_000)]
+    fn test_overlapping(#[case] start: i64, #[case] end: i64) {
+        let events = events(1000);
+        let index = EventIndex::new(&events).unwrap();
+        let mut got = indexes(&index.overlapping(AssTime(start), AssTime(end)));
+        got.sort_unstable();
+        let should: Vec<usize> = (0..events.len())
+            .filter(|&i| {
+                let (a, b) = (
+                    events[i].start_time().unwrap(),
+                    events[i].end_time().unwrap(),
+                );
+                a.millis() < end && b.millis() > start
+            })
+            .collect();
+        assert_eq!(got, should);
+    }
+
+    #[test]
+    fn test_at() {
+        let script = script(&[
+            (1000, 3000, "a"),
+            (0, 2000, "b"),
+            (2000, 2000, "c"),
+            (2000, 4000, "d"),
+        ]);
+        let index = script.events.index().unwrap();
+        assert_eq!(indexes(&index.at(AssTime(1999))), vec![1, 0]);
+        assert_eq!(indexes(&index.at(AssTime(2000))), vec![0, 3]);
+        assert!(index.at(AssTime(4000)).is_empty());
+        let order: Vec<usize> = index.iter().map(|x| x.index).collect();
+        assert_eq!(order, vec![1, 0, 2, 3]);
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let events = events(500);
+        let index = EventIndex::new(&events).unwrap();
+        let mut got: Vec<(usize, usize)> = index
+            .overlaps()
+            .into_iter()
+            .map(|(a, b)| (a.index.min(b.index), a.index.max(b.index)))
+            .collect();
+        got.sort_unstable();
+        let times: Vec<_> = events
+            .iter()
+            .map(|x| (x.start_time().unwrap(), x.end_time().unwrap()))
+            .collect();
+        let mut should = Vec::new();
+        for (i, &(s1, e1)) in times.iter().enumerate() {
+            for (j, &(s2, e2)) in times.iter().enumerate().skip(i + 1) {
+                if s1 < e1 && s2 < e2 && s1 < e2 && s2 < e1 {
+                    should.push((i, j));
+                }
+            }
+        }
+        assert_eq!(got, should);
+    }
+}
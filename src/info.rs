@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use crate::classify::Classifier;
+use crate::classify::EventKind;
+use crate::event::EventType;
+use crate::font::FontUsage;
+use crate::time::AssTime;
+use crate::AssScript;
+
+/// An overview of a script
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Summary {
+    pub play_res: (f64, f64),
+    pub styles: usize,
+    pub events: usize,
+    pub events_by_style: BTreeMap<String, usize>,
+    pub events_by_type: BTreeMap<String, usize>,
+    pub events_by_layer: BTreeMap<i64, usize>,
+    /// Start of the first dialogue event
+    pub first: Option<AssTime>,
+    /// End of the last dialogue event
+    pub last: Option<AssTime>,
+    /// Time during which any dialogue event is shown
+    pub covered: AssTime,
+    /// Font families used, through styles or override tags
+    pub fonts: Vec<String>,
+    /// Events classified as signs
+    pub signs: usize,
+    pub unused_styles: Vec<String>,
+    pub missing_styles: Vec<String>,
+}
+
+impl AssScript {
+    pub fn summary(&self) -> anyhow::Result<Summary> {
+        let mut summary = Summary {
+            play_res: self.play_res(),
+            styles: self.styles.entries.len(),
+            events: self.events.entries.len(),
+            events_by_style: BTreeMap::new(),
+            events_by_type: BTreeMap::new(),
+            events_by_layer: BTreeMap::new(),
+            first: None,
+            last: None,
+            covered: AssTime::ZERO,
+            fonts: Vec::new(),
+            signs: 0,
+            unused_styles: Vec::new(),
+            missing_styles: Vec::new(),
+        };
+
+        let classifier = Classifier::new(self);
+        let mut intervals = Vec::new();
+        for event in &self.events.entries {
+            *summary
+                .events_by_style
+                .entry(event.style.clone())
+                .or_default() += 1;
+            *summary
+                .events_by_type
+                .entry(event.event_type.to_string())
+                .or_default() += 1;
+            // renderers treat a layer that isn't a number as layer 0
+            let layer = event.layer.trim().parse().unwrap_or(0);
+            *summary.events_by_layer.entry(layer).or_default() += 1;
+            if classifier.classify(event) == EventKind::Sign {
+                summary.signs += 1;
+            }
+
+            if event.event_type == EventType::Dialogue {
+                intervals.push((event.start_time()?, event.end_time()?));
+            }
+        }
+
+        summary.first = intervals.iter().map(|x| x.0).min();
+        summary.last = intervals.iter().map(|x| x.1).max();
+        intervals.sort_unstable();
+        let mut covered_until = AssTime::ZERO;
+        for (start, end) in intervals {
+            let start = start.max(covered_until);
+            if end > start {
+                summary.covered = summary.covered + (end - start);
+                covered_until = end;
+            }
+        }
+
+        let fonts: BTreeSet<String> = FontUsage::from_script(self)
+            .fonts
+            .into_keys()
+            .map(|x| x.family)
+            .collect();
+        summary.fonts = fonts.into_iter().collect();
+
+        let usage = self.style_usage();
+        summary.unused_styles = usage.unused;
+        summary.missing_styles = usage.missing.into_keys().collect();
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventStrict;
+    use crate::style::StyleStrict;
+    use crate::testing::script;
+
+    #[test]
+    fn test_summary() {
+        let mut script = AssScript::new();
+        script.styles.entries.push(StyleStrict {
+            name: "Default".to_string(),
+            fontname: "Arial".to_string(),
+            ..Default::default()
+        });
+        script.events.entries = vec![
+            EventStrict::new(AssTime(1000), AssTime(3000), "Default", "a"),
+            EventStrict::new(AssTime(2000), AssTime(4000), "Default", r"{\fnFoo}b"),
+            EventStrict::new(AssTime(6000), AssTime(7000), "Sign", r"{\pos(1,2)}c"),
+            EventStrict::new(AssTime(0), AssTime(9000), "Default", "comment"),
+        ];
+        script.events.entries[2].layer = "1".to_string();
+        script.events.entries[3].event_type = EventType::Comment;
+
+        let summary = script.summary().unwrap();
+        assert_eq!(summary.events, 4);
+        assert_eq!(summary.events_by_style["Default"], 3);
+        assert_eq!(summary.events_by_type["Comment"], 1);
+        assert_eq!(summary.events_by_layer, BTreeMap::from([(0, 3), (1, 1)]));
+        assert_eq!(summary.first, Some(AssTime(1000)));
+        assert_eq!(summary.last, Some(AssTime(7000)));
+        assert_eq!(summary.covered, AssTime(4000));
+        assert_eq!(summary.fonts, vec!["Arial", "Foo"]);
+        assert_eq!(summary.signs, 1);
+        assert_eq!(summary.missing_styles, vec!["Sign"]);
+    }
+
+    #[test]
+    fn test_summary_invalid_layer() {
+        let mut script = script(&[(0, 1000, 1, "a"), (0, 1000, 0, "b"), (0, 1000, 0, "c")]);
+        script.events.entries[1].layer = "top".to_string();
+        script.events.entries[2].layer = String::new();
+
+        let summary = script.summary().unwrap();
+        assert_eq!(summary.events_by_layer, BTreeMap::from([(0, 2), (1, 1)]));
+    }
+}
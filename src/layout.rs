@@ -0,0 +1,538 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::collision;
+use crate::event::EventStrict;
+use crate::font::FontDatabase;
+use crate::font::FontRequest;
+use crate::lint;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::tag::Tag;
+use crate::tag::TextPart;
+use crate::AssScript;
+
+/// A line of an event as renderers would break it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub text: String,
+    /// Offset from the left of the layout, which depends on the alignment
+    pub left: f64,
+    /// Offset from the top of the layout
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
+    /// Which characters of the text the line shows, counting the ones
+    /// [`walk_text`] visits
+    pub chars: Range<usize>,
+}
+
+/// The lines of an event and the box around them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Layout {
+    pub lines: Vec<Line>,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// How text is drawn, as changed by override tags
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Format {
+    pub(crate) font: FontRequest,
+    pub(crate) size: f64,
+    pub(crate) scale_x: f64,
+    pub(crate) scale_y: f64,
+    pub(crate) spacing: f64,
+}
+
+impl Format {
+    pub(crate) fn from_style(style: &StyleStrict) -> Self {
+        let number = |x: &str, default: f64| x.trim().parse::<f64>().unwrap_or(default);
+        Self {
+            font: FontRequest::from_style(style),
+            size: number(&style.fontsize, 20.0),
+            scale_x: number(&style.scale_x, 100.0) / 100.0,
+            scale_y: number(&style.scale_y, 100.0) / 100.0,
+            spacing: number(&style.spacing, 0.0),
+        }
+    }
+
+    /// Follows a tag that changes the size or font of the text
+    pub(crate) fn apply_tag(&mut self, tag: &Tag, style: &StyleStrict) {
+        let reset = Format::from_style(style);
+        let value = tag.args.trim().parse::<f64>().ok();
+        match tag.name {
+            "fn" | "b" | "i" => self.font.apply_tag(tag, style),
+            // `\fs+2` and `\fs-2` are relative
+            "fs" if tag.args.starts_with(['+', '-']) => {
+                self.size = (self.size + value.unwrap_or(0.0)).max(0.0);
+            }
+            "fs" => self.size = value.filter(|x| *x > 0.0).unwrap_or(reset.size),
+            "fscx" => self.scale_x = value.map_or(reset.scale_x, |x| x.max(0.0) / 100.0),
+            "fscy" => self.scale_y = value.map_or(reset.scale_y, |x| x.max(0.0) / 100.0),
+            "fsp" => self.spacing = value.unwrap_or(reset.spacing),
+            _ => {}
+        }
+    }
+}
+
+/// A character with the space it takes up, `\n` for a line break
+#[derive(Debug, Clone)]
+struct Item {
+    c: char,
+    advance: f64,
+    height: f64,
+}
+
+/// Whether renderers may break a line at a character. Like libass and
+/// `VSFilter`, lines are only broken at spaces, so text without them such as
+/// Chinese isn't wrapped.
+fn is_break(c: char) -> bool {
+    matches!(c, ' ' | '\u{3000}')
+}
+
+pub(crate) type Faces<'a> = HashMap<FontRequest, Option<ttf_parser::Face<'a>>>;
+
+/// Ascent and height of a face in font units. Faces are scaled so that
+/// their Windows ascent and descent add up to the font size, as renderers
+/// do.
+pub(crate) fn face_metrics(face: &ttf_parser::Face) -> (f64, f64) {
+    match face.tables().os2 {
+        Some(x) if x.windows_ascender() > 0 || x.windows_descender() < 0 => (
+            f64::from(x.windows_ascender()),
+            f64::from(x.windows_ascender()) - f64::from(x.windows_descender()),
+        ),
+        _ => (
+            f64::from(face.ascender()),
+            f64::from(face.ascender()) - f64::from(face.descender()),
+        ),
+    }
+}
+
+/// Advance of a character for a font size of 1. Characters without a glyph
+/// are estimated the way [`collision::estimate_size`] does.
+pub(crate) fn advance(face: Option<&ttf_parser::Face>, c: char) -> f64 {
+    if let Some((face, glyph)) = face.and_then(|x| Some((x, x.glyph_index(c)?))) {
+        let (_, height) = face_metrics(face);
+        if height > 0.0 {
+            return f64::from(face.glyph_hor_advance(glyph).unwrap_or(0)) / height;
+        }
+    }
+    if lint::width(c.encode_utf8(&mut [0; 4])) > 1 {
+        1.0
+    } else {
+        0.5
+    }
+}
+
+/// Lays out event text the way libass does, using the fonts it would pick
+pub struct Layouter<'a> {
+    script: &'a AssScript,
+    fonts: Option<&'a FontDatabase>,
+}
+
+impl<'a> Layouter<'a> {
+    /// Without fonts, the size of text is estimated from the number of
+    /// characters
+    pub fn new(script: &'a AssScript, fonts: Option<&'a FontDatabase>) -> Self {
+        Self { script, fonts }
+    }
+
+    /// `WrapStyle` from [Script Info], 0 if it is missing or invalid
+    pub fn wrap_style(&self) -> u8 {
+        self.script
+            .script_info("WrapStyle")
+            .and_then(|x| x.parse().ok())
+            .filter(|x| *x <= 3)
+            .unwrap_or(0)
+    }
+
+    /// A style by name, falling back to `Default` like renderers do
+    pub(crate) fn style(&self, name: &str) -> Option<&'a StyleStrict> {
+        let styles = &self.script.styles;
+        styles.find(name).or_else(|| styles.find("Default"))
+    }
+
+    /// The style a `\r` tag resets to: the named style, or the event's own
+    /// style when the name is empty or unknown, like libass
+    pub(crate) fn reset_style<'s>(&self, name: &str, style: &'s StyleStrict) -> &'s StyleStrict
+    where
+        'a: 's,
+    {
+        let name = name.trim();
+        if name.is_empty() {
+            return style;
+        }
+        self.script.styles.find(name).unwrap_or(style)
+    }
+
+    /// Lays out an event in its style, wrapped to the width between its
+    /// margins
+    pub fn layout(&self, event: &EventStrict) -> Layout {
+        let default_style = StyleStrict::default();
+        let style = self.style(&event.style).unwrap_or(&default_style);
+        let (margin_l, margin_r, _) = event.margins(Some(style));
+        let max_width = self.script.play_res().0 - margin_l - margin_r;
+        self.layout_text(&event.text, style, Some(max_width))
+    }
+
+    /// Width and height of an event, to pass to
+    /// [`AssScript::collisions_with`]
+    pub fn measure(
+        &self,
+        event: &EventStrict,
+        style: &StyleStrict,
+        max_width: Option<f64>,
+    ) -> (f64, f64) {
+        let layout = self.layout_text(&event.text, style, max_width);
+        (layout.width, layout.height)
+    }
+
+    /// Lays out text in a style, wrapped to `max_width` unless it is `None`
+    /// or the wrap style is 2
+    pub fn layout_text(&self, text: &str, style: &StyleStrict, max_width: Option<f64>) -> Layout {
+        let wrap_style = self.event_wrap_style(text);
+        let items = self.items(text, style, wrap_style);
+        let max_width = max_width.filter(|_| wrap_style != 2);
+        let alignment = collision::overrides(text)
+            .0
+            .or(style.alignment.numpad())
+            .unwrap_or(2);
+        lay_out(&items, max_width, wrap_style, alignment)
+    }
+
+    /// The wrap style of an event, where the last `\q` tag applies to the
+    /// whole event
+    pub(crate) fn event_wrap_style(&self, text: &str) -> u8 {
+        let mut wrap_style = self.wrap_style();
+        walk_text(text, 0, |piece| {
+            if let Piece::Tag(tag) = piece {
+                if let Some(x) = (tag.name == "q")
+                    .then(|| tag.args.parse::<u8>().ok())
+                    .flatten()
+                    .filter(|x| *x <= 3)
+                {
+                    wrap_style = x;
+                }
+            }
+        });
+        wrap_style
+    }
+
+    /// The face renderers would pick for a font, parsed, falling back to
+    /// another family when it isn't found
+    pub(crate) fn face(&self, font: &FontRequest) -> Option<ttf_parser::Face<'a>> {
+        self.fonts
+            .and_then(|db| db.find(font).or_else(|| db.fallback(font)))
+            .and_then(|x| x.parse().ok())
+    }
+
+    /// The characters of text with the space they take up, following the
+    /// tags that change it
+    fn items(&self, text: &str, style: &StyleStrict, wrap_style: u8) -> Vec<Item> {
+        let mut faces = Faces::new();
+        let mut current = style;
+        let mut format = Format::from_style(style);
+        let mut items = Vec::new();
+        walk_text(text, wrap_style, |piece| match piece {
+            Piece::Tag(tag) if tag.name == "r" => {
+                current = self.reset_style(tag.args, style);
+                format = Format::from_style(current);
+            }
+            Piece::Tag(tag) => format.apply_tag(&tag, current),
+            Piece::Char(c) => {
+                let face = faces
+                    .entry(format.font.clone())
+                    .or_insert_with(|| self.face(&format.font));
+                let advance = if c == '\n' {
+                    0.0
+                } else {
+                    advance(face.as_ref(), c) * format.size * format.scale_x + format.spacing
+                };
+                items.push(Item {
+                    c,
+                    advance,
+                    height: format.size * format.scale_y,
+                });
+            }
+        });
+        items
+    }
+}
+
+/// An override tag or a character of event text
+pub(crate) enum Piece<'a> {
+    Tag(Tag<'a>),
+    Char(char),
+}
+
+/// Walks event text as renderers show it, one override tag or character at
+/// a time. `\N` becomes `\n`, as does `\n` with wrap style 2 while it is a
+/// space otherwise, `\h` becomes U+00A0 and drawings are skipped.
+pub(crate) fn walk_text<'a>(text: &'a str, wrap_style: u8, mut visit: impl FnMut(Piece<'a>)) {
+    let mut drawing = false;
+    for part in tag::split_text(text) {
+        match part {
+            TextPart::Override(block) => {
+                for tag in tag::parse_tags(block) {
+                    if tag.name == "p" {
+                        drawing = tag.args.parse::<u32>().unwrap_or(0) > 0;
+                    }
+                    visit(Piece::Tag(tag));
+                }
+            }
+            TextPart::Text(x) if !drawing => {
+                let mut chars = x.chars().peekable();
+                while let Some(mut c) = chars.next() {
+                    if c == '\\' {
+                        match chars.peek() {
+                            Some('N') => c = '\n',
+                            Some('n') if wrap_style == 2 => c = '\n',
+                            Some('n') => c = ' ',
+                            Some('h') => c = '\u{a0}',
+                            _ => {}
+                        }
+                        if c != '\\' {
+                            chars.next();
+                        }
+                    }
+                    visit(Piece::Char(c));
+                }
+            }
+            TextPart::Text(_) => {}
+        }
+    }
+}
+
+/// The first word of each line a paragraph is broken into. `width` gives
+/// the width of a range of words.
+fn break_words(
+    words: usize,
+    width: impl Fn(usize, usize) -> f64,
+    max_width: f64,
+    wrap_style: u8,
+) -> Vec<usize> {
+    // fill lines as far as they go
+    let mut starts = vec![0];
+    for w in 1..words {
+        if width(starts[starts.len() - 1], w + 1) > max_width {
+            starts.push(w);
+        }
+    }
+    if wrap_style == 1 {
+        return starts;
+    }
+
+    // then move words down to even out the lines, keeping the top line wider
+    // with wrap style 0 and the bottom one with 3
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for k in 1..starts.len() {
+            let (a, b) = (starts[k - 1], starts[k]);
+            let c = starts.get(k + 1).copied().unwrap_or(words);
+            if b - a < 2 {
+                continue;
+            }
+            let (new_top, new_bottom) = (width(a, b - 1), width(b - 1, c));
+            let moves = new_bottom <= max_width
+                && if wrap_style == 3 {
+                    width(a, b) > width(b, c)
+                } else {
+                    new_top >= new_bottom
+                };
+            if moves {
+                starts[k] -= 1;
+                changed = true;
+            }
+        }
+    }
+    starts
+}
+
+/// Breaks items into lines and places them
+fn lay_out(items: &[Item], max_width: Option<f64>, wrap_style: u8, alignment: u8) -> Layout {
+    let mut offsets = vec![0.0];
+    for item in items {
+        offsets.push(offsets[offsets.len() - 1] + item.advance);
+    }
+    let span_width = |x: &Range<usize>| offsets[x.end] - offsets[x.start];
+
+    // ranges of items per line, with the height of empty lines
+    let mut lines: Vec<(Range<usize>, f64)> = Vec::new();
+    let mut start = 0;
+    for end in (0..items.len())
+        .filter(|&i| items[i].c == '\n')
+        .chain([items.len()])
+    {
+        let mut words: Vec<Range<usize>> = Vec::new();
+        for (i, item) in items.iter().enumerate().take(end).skip(start) {
+            if is_break(item.c) {
+                continue;
+            }
+            match words.last_mut() {
+                Some(x) if x.end == i => x.end = i + 1,
+                _ => words.push(i..i + 1),
+            }
+        }
+        let empty_height = items
+            .get(end)
+            .or_else(|| items.get(end.wrapping_sub(1)))
+            .map_or(0.0, |x| x.height);
+
+        if words.is_empty() {
+            lines.push((start..start, empty_height));
+        } else {
+            let width = |a: usize, b: usize| span_width(&(words[a].start..words[b - 1].end));
+            let starts = match max_width {
+                Some(max) => break_words(words.len(), width, max, wrap_style),
+                None => vec![0],
+            };
+            for (k, &first) in starts.iter().enumerate() {
+                let last = starts.get(k + 1).map_or(words.len(), |x| *x) - 1;
+                lines.push((words[first].start..words[last].end, empty_height));
+            }
+        }
+        start = end + 1;
+    }
+
+    let mut layout = Layout::default();
+    for (range, empty_height) in lines {
+        let line = &items[range.clone()];
+        layout.lines.push(Line {
+            text: line.iter().map(|x| x.c).collect(),
+            left: 0.0,
+            top: layout.height,
+            width: span_width(&range),
+            height: line
+                .iter()
+                .map(|x| x.height)
+                .reduce(f64::max)
+                .unwrap_or(empty_height),
+            chars: range,
+        });
+        let line = &layout.lines[layout.lines.len() - 1];
+        layout.width = layout.width.max(line.width);
+        layout.height += line.height;
+    }
+    for line in &mut layout.lines {
+        line.left = match alignment % 3 {
+            1 => 0.0,
+            0 => layout.width - line.width,
+            _ => (layout.width - line.width) / 2.0,
+        };
+    }
+    layout
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing;
+
+    fn script(wrap_style: &str) -> AssScript {
+        let mut script = testing::script::<(i64, i64, &str)>(&[]);
+        testing::set_info(&mut script, "WrapStyle", wrap_style);
+        script.styles.entries[0].fontsize = "20".to_string();
+        script
+    }
+
+    fn lines(layout: &Layout) -> Vec<&str> {
+        layout.lines.iter().map(|x| x.text.as_str()).collect()
+    }
+
+    #[rstest]
+    #[case::smart("0", "aaaa bb cc dd ee", vec!["aaaa bb cc", "dd ee"])]
+    #[case::end_of_line("1", "aaaa bb cc dd ee", vec!["aaaa bb cc dd", "ee"])]
+    #[case::none("2", "aaaa bb cc dd ee", vec!["aaaa bb cc dd ee"])]
+    #[case::bottom_wider("3", "aaaa bb cc dd ee", vec!["aaaa bb", "cc dd ee"])]
+    #[case::hard_break("0", "aa\\Nbb", vec!["aa", "bb"])]
+    #[case::soft_break("0", "aa\\nbb", vec!["aa bb"])]
+    #[case::soft_break_q2("0", "{\\q2}aa\\nbb cc dd ee ff gg hh", vec!["aa", "bb cc dd ee ff gg hh"])]
+    #[case::empty_line("0", "aa\\N\\Nbb", vec!["aa", "", "bb"])]
+    fn test_wrap(#[case] wrap_style: &str, #[case] text: &str, #[case] should: Vec<&str>) {
+        let script = script(wrap_style);
+        let layouter = Layouter::new(&script, None);
+        // characters are 10 wide, so lines fit 13 of them
+        let layout = layouter.layout_text(text, &script.styles.entries[0], Some(130.0));
+        assert_eq!(lines(&layout), should);
+    }
+
+    #[test]
+    fn test_sizes() {
+        let script = script("0");
+        let layouter = Layouter::new(&script, None);
+        let style = &script.styles.entries[0];
+        let layout = layouter.layout_text("{\\an1}ab\\N{\\fs40\\fscx50}abcd", style, None);
+        let sizes: Vec<(f64, f64, f64, f64)> = layout
+            .lines
+            .iter()
+            .map(|x| (x.left, x.top, x.width, x.height))
+            .collect();
+        assert_eq!(sizes, vec![(0.0, 0.0, 20.0, 20.0), (0.0, 20.0, 40.0, 40.0)]);
+        assert_eq!((layout.width, layout.height), (40.0, 60.0));
+
+        let layout = layouter.layout_text("{\\fsp5}ab\\h{\\r}中", style, None);
+        assert_eq!((layout.width, layout.height), (65.0, 20.0));
+        let layout = layouter.layout_text("ab{\\p1}m 0 0 l 100 0{\\p0}", style, None);
+        assert_eq!((layout.width, layout.height), (20.0, 20.0));
+    }
+
+    #[rstest]
+    // advances are 250 for I and space and 520 for A and B, in 1000 units
+    #[case("IAB I", (358, 200))]
+    #[case("{\\fs40}I", (100, 400))]
+    // x has no glyph, so it is estimated
+    #[case("Ix", (150, 200))]
+    #[case("{\\fnSubass Test CFF\\b1\\i1}IA", (154, 200))]
+    fn test_font_metrics(#[case] text: &str, #[case] should: (i64, i64)) {
+        let mut script = script("0");
+        script.styles.entries[0].fontname = "Subass Test".to_string();
+        let fonts = testing::fonts();
+        let layouter = Layouter::new(&script, Some(&fonts));
+        let layout = layouter.layout_text(text, &script.styles.entries[0], None);
+        #[allow(clippy::cast_possible_truncation)]
+        let tenths = |x: f64| (x * 10.0).round() as i64;
+        assert_eq!((tenths(layout.width), tenths(layout.height)), should);
+    }
+
+    #[test]
+    fn test_font_switching() {
+        let mut script = script("0");
+        script.styles.entries[0].fontname = "Subass Test".to_string();
+        let fonts = testing::fonts();
+        let layouter = Layouter::new(&script, Some(&fonts));
+
+        let style = &script.styles.entries[0];
+        let mut format = Format::from_style(style);
+        let mut outlines = Vec::new();
+        for tag in tag::parse_tags(r"\fnSubass Test CFF\b1\i1\fn\b0") {
+            format.apply_tag(&tag, style);
+            let face = layouter.face(&format.font).unwrap();
+            outlines.push(if face.tables().cff.is_some() {
+                "cff"
+            } else {
+                "glyf"
+            });
+        }
+        assert_eq!(outlines, ["cff", "cff", "cff", "glyf", "glyf"]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut script = script("0");
+        script.styles.entries.push(StyleStrict {
+            name: "Big".to_string(),
+            fontsize: "40".to_string(),
+            ..Default::default()
+        });
+        let layouter = Layouter::new(&script, None);
+        let style = &script.styles.entries[1];
+        // a bare or unknown \r goes back to the event's style, not Default
+        let layout =
+            layouter.layout_text("a{\\fs10}b{\\r}c{\\fs10\\rNope}d{\\rDefault}e", style, None);
+        assert_eq!((layout.width, layout.height), (75.0, 40.0));
+    }
+}
@@ -0,0 +1,338 @@
+#![warn(clippy::pedantic)]
+#![allow(clippy::module_name_repetitions)]
+#![allow(clippy::struct_field_names)]
+#![allow(clippy::missing_errors_doc)]
+#![allow(clippy::must_use_candidate)]
+#![allow(clippy::format_push_string)]
+
+pub mod attachment;
+pub mod autofix;
+pub mod bilingual;
+pub mod canonical;
+pub mod classify;
+pub mod collision;
+pub mod common;
+pub mod convert;
+pub mod diff;
+pub mod event;
+pub mod filter;
+pub mod font;
+pub mod index;
+pub mod info;
+pub mod layout;
+pub mod lint;
+pub mod markup;
+pub mod merge;
+pub mod microdvd;
+#[cfg(feature = "render")]
+pub mod render;
+pub mod srt;
+pub mod style;
+pub mod style_ops;
+pub mod subset;
+pub mod sync;
+pub mod tag;
+#[cfg(test)]
+mod testing;
+pub mod time;
+pub mod timing;
+pub mod ttml;
+pub mod vtt;
+
+use std::collections::HashMap;
+
+use event::Events;
+use style::Styles;
+
+use crate::event::EventContext;
+use crate::style::StyleContext;
+
+/// Sections whose lines are opaque data and must be kept verbatim
+const DATA_SECTIONS: [&str; 2] = ["Fonts", "Graphics"];
+
+/// Sections that end a data section. uuencoded lines can start with `[` and
+/// end with `]`, so only these names are taken as headers inside one.
+const KNOWN_SECTIONS: [&str; 8] = [
+    "Script Info",
+    "V4+ Styles",
+    "V4 Styles",
+    "Events",
+    "Fonts",
+    "Graphics",
+    "Aegisub Project Garbage",
+    "Aegisub Extradata",
+];
+
+/// The name of the section a trimmed line starts, when read in `section`
+fn section_header<'a>(line: &'a str, section: &str) -> Option<&'a str> {
+    let name = line.strip_prefix('[')?.strip_suffix(']')?;
+    (!DATA_SECTIONS.contains(&section) || KNOWN_SECTIONS.contains(&name)).then_some(name)
+}
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AssScript {
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::common::serialize_sorted")
+    )]
+    pub other_sections: HashMap<String, Vec<String>>,
+    pub styles: Styles,
+    pub events: Events,
+}
+
+impl AssScript {
+    /// An empty script with the [Script Info] Aegisub writes for new files
+    pub fn new() -> Self {
+        let info = [
+            "ScriptType: v4.00+",
+            "WrapStyle: 0",
+            "ScaledBorderAndShadow: yes",
+            "PlayResX: 1920",
+            "PlayResY: 1080",
+        ];
+        Self {
+            other_sections: HashMap::from([(
+                "Script Info".to_string(),
+                info.iter().map(ToString::to_string).collect(),
+            )]),
+            ..Default::default()
+        }
+    }
+
+    pub fn try_from_file(filename: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        Self::try_from_str(&std::fs::read_to_string(filename)?)
+    }
+
+    pub fn try_from_str(contents: &str) -> anyhow::Result<Self> {
+        let contents = contents.trim_start_matches('\u{feff}');
+        let mut script = AssScript::default();
+
+        // initial state is before a section
+        let mut section = "";
+
+        let mut set_style_format = false;
+        let mut set_event_format = false;
+
+        for line in contents.lines() {
+            // ensure line doesn't have leading or trailing space
+            let line = line.trim();
+
+            // TODO: Collect comments in this struct as well
+            // skip comments and newlines. uuencoded data may legitimately start
+            // with ';', so comments are only recognized outside of data sections
+            if line.is_empty() || (line.starts_with(';') && !DATA_SECTIONS.contains(&section)) {
+                continue;
+            }
+
+            // parse the sections
+            if let Some(name) = section_header(line, section) {
+                section = name;
+                continue;
+            }
+
+            match section {
+                "V4+ Styles" | "V4 Styles" => {
+                    // first line must be format
+                    if !set_style_format {
+                        script.styles.context = StyleContext::from_format_line(line)?;
+                        script.styles.context.ssa = section == "V4 Styles";
+                        set_style_format = true;
+                        continue;
+                    }
+
+                    let parsed = script.styles.context.style_strict_from_line(line)?;
+                    script.styles.entries.push(parsed);
+                }
+                "Events" => {
+                    // first line must be format
+                    if !set_event_format {
+                        script.events.context = EventContext::from_format_line(line)?;
+                        set_event_format = true;
+                        continue;
+                    }
+
+                    let parsed = script.events.context.event_strict_from_line(line)?;
+                    script.events.entries.push(parsed);
+                }
+                _ => {
+                    script
+                        .other_sections
+                        .entry(section.to_string())
+                        .and_modify(|list| list.push(line.to_string()))
+                        .or_insert_with(|| vec![line.to_string()]);
+                }
+            }
+        }
+
+        Ok(script)
+    }
+
+    /// The `;` comment lines of a script's source, which parsing drops
+    pub(crate) fn comment_lines(contents: &str) -> Vec<&str> {
+        let mut section = "";
+        let mut comments = Vec::new();
+        for line in contents.lines().map(str::trim) {
+            if let Some(name) = section_header(line, section) {
+                section = name;
+            } else if line.starts_with(';') && !DATA_SECTIONS.contains(&section) {
+                comments.push(line);
+            }
+        }
+        comments
+    }
+
+    /// Serializes the script. [Script Info] comes first and embedded data
+    /// sections last, other sections are ordered by name in between.
+    pub fn to_ass_string(&self) -> anyhow::Result<String> {
+        let mut out = String::new();
+        let mut write_section = |name: &str, lines: &[String]| {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push('[');
+            out.push_str(name);
+            out.push_str("]\n");
+            for line in lines {
+                out.push_str(line);
+                out.push('\n');
+            }
+        };
+
+        if let Some(lines) = self.other_sections.get("Script Info") {
+            write_section("Script Info", lines);
+        }
+
+        let mut names: Vec<_> = self
+            .other_sections
+            .keys()
+            .filter(|x| *x != "Script Info" && !DATA_SECTIONS.contains(&x.as_str()))
+            .collect();
+        names.sort();
+        for name in names {
+            write_section(name, &self.other_sections[name]);
+        }
+
+        let mut styles = vec![self.styles.context.format_line()];
+        for x in &self.styles.entries {
+            styles.push(self.styles.context.line_from_style_strict(x)?);
+        }
+        write_section(self.styles.context.section(), &styles);
+
+        let mut events = vec![self.events.context.format_line()];
+        for x in &self.events.entries {
+            events.push(self.events.context.line_from_event_strict(x)?);
+        }
+        write_section("Events", &events);
+
+        for name in DATA_SECTIONS {
+            if let Some(lines) = self.other_sections.get(name) {
+                write_section(name, lines);
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// `PlayResX` and `PlayResY`, filling in missing values the way renderers
+    /// do
+    pub fn play_res(&self) -> (f64, f64) {
+        let get = |key| self.script_info(key).and_then(|x| x.parse::<f64>().ok());
+        match (get("PlayResX"), get("PlayResY")) {
+            (Some(x), Some(y)) => (x, y),
+            (Some(x), None) if (x - 1280.0).abs() < f64::EPSILON => (x, 1024.0),
+            (Some(x), None) => (x, x * 3.0 / 4.0),
+            (None, Some(y)) if (y - 1024.0).abs() < f64::EPSILON => (1280.0, y),
+            (None, Some(y)) => (y * 4.0 / 3.0, y),
+            (None, None) => (384.0, 288.0),
+        }
+    }
+
+    /// Looks up a `Key: Value` entry in the [Script Info] section
+    pub fn script_info(&self, key: &str) -> Option<&str> {
+        self.other_sections
+            .get("Script Info")?
+            .iter()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(k, _)| k.trim() == key)
+            .map(|(_, v)| v.trim())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::english(include_str!("../example.en.ass"))]
+    #[case::chinese(include_str!("../example.zh-TW.ass"))]
+    fn test_script_roundtrip(#[case] contents: &str) {
+        let script = AssScript::try_from_str(contents).unwrap();
+        let serialized = script.to_ass_string().unwrap();
+        let reparsed = AssScript::try_from_str(&serialized).unwrap();
+        assert_eq!(reparsed.to_ass_string().unwrap(), serialized);
+        assert_eq!(reparsed.other_sections, script.other_sections);
+        assert_eq!(reparsed.styles.entries, script.styles.entries);
+        assert_eq!(reparsed.events.entries, script.events.entries);
+    }
+
+    #[test]
+    fn test_ssa_roundtrip() {
+        let contents = "[Script Info]
+ScriptType: v4.00
+
+[V4 Styles]
+Format: Name,Fontname,Fontsize,PrimaryColour,SecondaryColour,TertiaryColour,BackColour,Bold,Italic,BorderStyle,Outline,Shadow,Alignment,MarginL,MarginR,MarginV,AlphaLevel,Encoding
+Style: Default,Arial,20,16777215,65535,65535,-2147483640,-1,0,1,3,0,2,30,30,30,0,0
+
+[Events]
+Format: Marked, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: Marked=0,0:00:01.00,0:00:02.00,Default,,0000,0000,0000,,Hi
+";
+        let script = AssScript::try_from_str(contents).unwrap();
+        assert_eq!(script.styles.entries[0].fontname, "Arial");
+        assert_eq!(script.to_ass_string().unwrap(), contents);
+    }
+
+    #[test]
+    fn test_data_section_brackets() {
+        // a uuencoded line that happens to look like a section header
+        let contents = "[Script Info]\nScriptType: v4.00+\n\n[Fonts]\nfontname: a.ttf\n\
+                        [E=[?]\n;line\n[Graphics]\nfilename: b.png\n!!!\n[Events]\n";
+        let script = AssScript::try_from_str(contents).unwrap();
+        assert_eq!(
+            script.other_sections["Fonts"],
+            vec!["fontname: a.ttf", "[E=[?]", ";line"]
+        );
+        assert_eq!(
+            script.other_sections["Graphics"],
+            vec!["filename: b.png", "!!!"]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[rstest]
+    #[case::english(include_str!("../example.en.ass"))]
+    #[case::chinese(include_str!("../example.zh-TW.ass"))]
+    fn test_json_roundtrip(#[case] contents: &str) {
+        let script = AssScript::try_from_str(contents).unwrap();
+        let json = serde_json::to_string(&script).unwrap();
+        let reparsed: AssScript = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            reparsed.to_ass_string().unwrap(),
+            script.to_ass_string().unwrap()
+        );
+        assert_eq!(serde_json::to_string(&reparsed).unwrap(), json);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_enums_as_script_values() {
+        let json = serde_json::to_value(style::StyleStrict::default()).unwrap();
+        assert_eq!(json["alignment"], "2");
+        assert_eq!(json["bold"], "0");
+        assert_eq!(json["style_type"], "Style");
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Context;
+
+use crate::autofix::repair_boolean;
+use crate::autofix::AutoFix;
+use crate::classify::Classifier;
+use crate::classify::EventKind;
+use crate::common::Boolean;
+use crate::event::EventType;
+use crate::font;
+use crate::font::FontDatabase;
+use crate::font::FontUsage;
+use crate::style::Alignment;
+use crate::style::StyleField;
+use crate::tag;
+use crate::time::AssTime;
+use crate::AssScript;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, strum::Display, strum::EnumString,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Rule {
+    /// Start or End that can't be parsed
+    InvalidTime,
+    EndBeforeStart,
+    ZeroDuration,
+    /// Dialogue on the same style and layer shown at the same time
+    Overlap,
+    /// Characters per second above `max-cps`
+    ReadingSpeed,
+    /// Lines wider than `max-line-length` columns
+    LineLength,
+    UnknownTag,
+    UnbalancedBraces,
+    MissingStyle,
+    MissingFont,
+    InvalidBoolean,
+    InvalidAlignment,
+}
+
+impl Rule {
+    pub fn default_severity(self) -> Severity {
+        match self {
+            Rule::InvalidTime | Rule::EndBeforeStart | Rule::MissingFont => Severity::Error,
+            Rule::Overlap => Severity::Info,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Where a problem is, by index into the styles or events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Location {
+    Script,
+    Style(usize),
+    Event(usize),
+}
+
+/// Counted from 1, like editors do
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Script => write!(f, "script"),
+            Location::Style(i) => write!(f, "style {}", i + 1),
+            Location::Event(i) => write!(f, "event {}", i + 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub location: Location,
+    pub message: String,
+    /// The fix that resolves the problem, applied by [`AssScript::autofix`]
+    pub fix: Option<AutoFix>,
+}
+
+/// Which rules run at which severity, and their limits. Read from lines of
+/// `key = value`, where keys are rule ids set to a severity or `off`, or
+/// `max-cps` and `max-line-length`. `#` starts a comment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintConfig {
+    /// Severity of rules, or `None` to disable them. Missing rules use their
+    /// default severity.
+    pub rules: HashMap<Rule, Option<Severity>>,
+    pub max_cps: f64,
+    pub max_line_length: usize,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            rules: HashMap::new(),
+            max_cps: 25.0,
+            max_line_length: 42,
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn severity(&self, rule: Rule) -> Option<Severity> {
+        self.rules
+            .get(&rule)
+            .copied()
+            .unwrap_or(Some(rule.default_severity()))
+    }
+}
+
+impl FromStr for LintConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::default();
+        for line in s.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .context(format!("expected key = value, found {line}"))?;
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "max-cps" => config.max_cps = value.parse()?,
+                "max-line-length" => config.max_line_length = value.parse()?,
+                _ => {
+                    let rule = key.parse().context(format!("unknown lint rule {key}"))?;
+                    let severity = if value.eq_ignore_ascii_case("off") {
+                        None
+                    } else {
+                        Some(value.parse().context(format!("invalid severity {value}"))?)
+                    };
+                    config.rules.insert(rule, severity);
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, rule: Rule, location: Location, message: String, fix: Option<AutoFix>) {
+        if let Some(severity) = self.config.severity(rule) {
+            self.diagnostics.push(Diagnostic {
+                rule,
+                severity,
+                location,
+                message,
+                fix,
+            });
+        }
+    }
+}
+
+/// Columns a line takes up, counting East Asian wide characters as two
+pub(crate) fn width(line: &str) -> usize {
+    line.chars()
+        .map(|c| match u32::from(c) {
+            0x1100..=0x115F
+            | 0x2E80..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x2_0000..=0x3_FFFF => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+/// Position of the first brace without a partner, as renderers show those as
+/// text. Override blocks end at the first `}`, so a `{` inside one is fine.
+fn unbalanced_brace(text: &str) -> Option<usize> {
+    let mut open = None;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' if open.is_none() => open = Some(i),
+            '}' if open.is_none() => return Some(i),
+            '}' => open = None,
+            _ => {}
+        }
+    }
+    open
+}
+
+/// Checks a script against the rules enabled in `config`. Fonts are only
+/// checked when a database is given.
+pub fn lint(
+    script: &AssScript,
+    config: &LintConfig,
+    fonts: Option<&FontDatabase>,
+) -> anyhow::Result<Vec<Diagnostic>> {
+    let mut linter = Linter {
+        config,
+        diagnostics: Vec::new(),
+    };
+
+    for (i, style) in script.styles.entries.iter().enumerate() {
+        let location = Location::Style(i);
+        let booleans = [
+            (StyleField::Bold, &style.bold),
+            (StyleField::Italic, &style.italic),
+            (StyleField::Underline, &style.underline),
+            (StyleField::StrikeOut, &style.strike_out),
+        ];
+        for (field, value) in booleans {
+            if let Boolean::Unknown(x) = value {
+                let fix = repair_boolean(value).map(|_| AutoFix::InvalidBoolean);
+                linter.report(
+                    Rule::InvalidBoolean,
+                    location,
+                    format!("{} has {field} {x}, expected -1 or 0", style.name),
+                    fix,
+                );
+            }
+        }
+        if let Alignment::Unknown(x) = &style.alignment {
+            linter.report(
+                Rule::InvalidAlignment,
+                location,
+                format!("{} has alignment {x}, expected 1 to 9", style.name),
+                None,
+            );
+        }
+    }
+
+    if let Some(db) = fonts {
+        for status in font::check_fonts(&FontUsage::from_script(script), db)? {
+            if status.source.is_none() {
+                linter.report(
+                    Rule::MissingFont,
+                    Location::Script,
+                    format!("font {} not found", status.request),
+                    None,
+                );
+            }
+        }
+    }
+
+    for (style, events) in script.style_usage().missing {
+        for i in events {
+            linter.report(
+                Rule::MissingStyle,
+                Location::Event(i),
+                format!("style {style} doesn't exist"),
+                None,
+            );
+        }
+    }
+
+    lint_events(script, &mut linter);
+    linter.diagnostics.sort_by_key(|x| x.location);
+    Ok(linter.diagnostics)
+}
+
+/// Tags and braces in event text
+fn lint_text(text: &str, location: Location, linter: &mut Linter) {
+    for tag in tag::split_text(text)
+        .into_iter()
+        .filter_map(|x| match x {
+            tag::TextPart::Override(block) => Some(tag::parse_tags(block)),
+            tag::TextPart::Text(_) => None,
+        })
+        .flatten()
+        .filter(|x| !x.is_known())
+    {
+        linter.report(
+            Rule::UnknownTag,
+            location,
+            format!("unknown tag \\{}", tag.name),
+            None,
+        );
+    }
+    if let Some(x) = unbalanced_brace(text) {
+        linter.report(
+            Rule::UnbalancedBraces,
+            location,
+            format!(
+                "unbalanced brace at character {}",
+                text[..x].chars().count() + 1
+            ),
+            None,
+        );
+    }
+}
+
+/// Start, end and index of an event
+type Span = (AssTime, AssTime, usize);
+
+fn lint_events(script: &AssScript, linter: &mut Linter) {
+    let classifier = Classifier::new(script);
+    // dialogue by style and layer
+    let mut tracks: HashMap<(&str, &str), Vec<Span>> = HashMap::new();
+
+    for (i, event) in script.events.entries.iter().enumerate() {
+        if event.event_type == EventType::Comment {
+            continue;
+        }
+        let location = Location::Event(i);
+        lint_text(&event.text, location, linter);
+
+        let (Ok(start), Ok(end)) = (event.start_time(), event.end_time()) else {
+            linter.report(
+                Rule::InvalidTime,
+                location,
+                format!("invalid time {} - {}", event.start, event.end),
+                None,
+            );
+            continue;
+        };
+        if end < start {
+            linter.report(
+                Rule::EndBeforeStart,
+                location,
+                format!("ends at {end} before starting at {start}"),
+                Some(AutoFix::EndBeforeStart),
+            );
+        } else if end == start {
+            linter.report(
+                Rule::ZeroDuration,
+                location,
+                "is never shown".to_string(),
+                Some(AutoFix::ZeroDuration),
+            );
+        }
+
+        if end <= start || classifier.classify(event) != EventKind::Dialogue {
+            continue;
+        }
+        tracks
+            .entry((&event.style, &event.layer))
+            .or_default()
+            .push((start, end, i));
+
+        let plain = tag::plain_text(&event.text);
+        let characters = plain.chars().filter(|c| !c.is_whitespace()).count();
+        let duration = (end - start).millis();
+        // counts and durations are far below the range where the casts lose
+        // precision
+        #[allow(clippy::cast_precision_loss)]
+        let cps = characters as f64 * 1000.0 / duration as f64;
+        if duration > 0 && cps > linter.config.max_cps {
+            linter.report(
+                Rule::ReadingSpeed,
+                location,
+                format!("{cps:.1} characters per second"),
+                None,
+            );
+        }
+        if let Some(line) = plain
+            .lines()
+            .find(|x| width(x) > linter.config.max_line_length)
+        {
+            linter.report(
+                Rule::LineLength,
+                location,
+                format!("line is {} columns wide: {line}", width(line)),
+                None,
+            );
+        }
+    }
+
+    lint_overlaps(tracks.into_values(), linter);
+}
+
+fn lint_overlaps(tracks: impl Iterator<Item = Vec<Span>>, linter: &mut Linter) {
+    for mut events in tracks {
+        events.sort_unstable();
+        let mut latest: Option<(AssTime, usize)> = None;
+        for &(start, end, i) in &events {
+            if let Some((latest_end, j)) = latest {
+                if start < latest_end {
+                    linter.report(
+                        Rule::Overlap,
+                        Location::Event(i),
+                        format!("overlaps {}", Location::Event(j)),
+                        None,
+                    );
+                }
+            }
+            if latest.is_none_or(|(x, _)| end > x) {
+                latest = Some((end, i));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::autofix::FixOptions;
+    use crate::testing::script;
+
+    fn rules(script: &AssScript) -> Vec<Rule> {
+        lint(script, &LintConfig::default(), None)
+            .unwrap()
+            .into_iter()
+            .map(|x| x.rule)
+            .collect()
+    }
+
+    #[rstest]
+    #[case(&[(0, 2000, "Hello")], &[])]
+    #[case(&[(2000, 1000, "Hello")], &[Rule::EndBeforeStart])]
+    #[case(&[(1000, 1000, "Hello")], &[Rule::ZeroDuration])]
+    #[case(&[(0, 2000, "Hello"), (1000, 3000, "World")], &[Rule::Overlap])]
+    #[case(&[(0, 2000, "Hello"), (1000, 3000, r"{\pos(1,1)}EXIT")], &[])]
+    #[case(&[(0, 500, "This line is read far too quickly")], &[Rule::ReadingSpeed])]
+    #[case(&[(0, 9000, "This line is much too long to fit on the screen")], &[Rule::LineLength])]
+    #[case(&[(0, 9000, "這一行中文字幕實在是太長了根本放不下螢幕啊啊")], &[Rule::LineLength])]
+    #[case(&[(0, 2000, r"{\fs20\foo}Hello")], &[Rule::UnknownTag])]
+    #[case(&[(0, 2000, r"{\i1Hello")], &[Rule::UnbalancedBraces])]
+    #[case(&[(0, 2000, r"{\i1}Hello}")], &[Rule::UnbalancedBraces])]
+    fn test_lint_events(#[case] events: &[(i64, i64, &str)], #[case] should: &[Rule]) {
+        assert_eq!(rules(&script(events)), should);
+    }
+
+    #[test]
+    fn test_lint_styles() {
+        let mut script = script(&[(0, 1000, "Hi")]);
+        script.styles.entries[0].bold = Boolean::Unknown("1".to_string());
+        script.styles.entries[0].alignment = Alignment::Unknown("10".to_string());
+        script.events.entries[0].style = "Typo".to_string();
+        assert_eq!(
+            rules(&script),
+            vec![
+                Rule::InvalidBoolean,
+                Rule::InvalidAlignment,
+                Rule::MissingStyle
+            ]
+        );
+
+        script.autofix(&fix_options(&script)).unwrap();
+        assert_eq!(script.styles.entries[0].bold, Boolean::True);
+    }
+
+    /// Only the fixes lint suggests
+    fn fix_options(script: &AssScript) -> FixOptions {
+        FixOptions {
+            fixes: lint(script, &LintConfig::default(), None)
+                .unwrap()
+                .into_iter()
+                .filter_map(|x| x.fix)
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_apply_fixes() {
+        let mut script = script(&[(2000, 1000, "a"), (1000, 1000, "b"), (0, 0, "c")]);
+        script.autofix(&fix_options(&script)).unwrap();
+        assert_eq!(script.events.entries.len(), 1);
+        assert_eq!(script.events.entries[0].start, "0:00:01.00");
+        assert_eq!(script.events.entries[0].end, "0:00:02.00");
+    }
+
+    #[test]
+    fn test_config() {
+        let config: LintConfig = "
+            # noisy on this show
+            overlap = off
+            line-length = error
+            max-cps = 18.5
+        "
+        .parse()
+        .unwrap();
+        assert_eq!(config.severity(Rule::Overlap), None);
+        assert_eq!(config.severity(Rule::LineLength), Some(Severity::Error));
+        assert_eq!(config.severity(Rule::UnknownTag), Some(Severity::Warning));
+        assert!((config.max_cps - 18.5).abs() < f64::EPSILON);
+
+        assert!("overlaps = off".parse::<LintConfig>().is_err());
+        assert!("overlap = loud".parse::<LintConfig>().is_err());
+    }
+}
@@ -1,104 +1,885 @@
 #![warn(clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
 
-mod common;
-mod event;
-mod style;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
 
-use std::collections::HashMap;
+use anyhow::Context;
+use clap::Args;
+use clap::Parser;
+use clap::Subcommand;
+use subass::autofix::AutoFix;
+use subass::autofix::FixOptions;
+use subass::bilingual;
+use subass::bilingual::Layout;
+use subass::bilingual::PairOptions;
+use subass::canonical;
+use subass::canonical::FmtOptions;
+use subass::convert;
+use subass::convert::ConvertOptions;
+use subass::convert::Format;
+use subass::diff;
+use subass::event::EventType;
+use subass::filter::Filter;
+use subass::filter::Selection;
+use subass::font;
+use subass::font::FontDatabase;
+use subass::font::FontUsage;
+use subass::layout::Layouter;
+use subass::lint;
+use subass::lint::LintConfig;
+use subass::lint::Location;
+use subass::lint::Severity;
+use subass::merge;
+#[cfg(feature = "render")]
+use subass::render::Background;
+#[cfg(feature = "render")]
+use subass::render::Renderer;
+use subass::srt::TagMode;
+use subass::style::StyleField;
+use subass::style_ops::OrphanEvents;
+use subass::subset;
+use subass::sync;
+use subass::sync::SyncOptions;
+use subass::time::AssTime;
+use subass::time::FrameRate;
+use subass::AssScript;
 
-use event::Events;
-use style::Styles;
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the styles and events of a script as parsed
+    Dump { file: PathBuf },
+    /// Convert between subtitle formats
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// Format of the input, guessed from its extension by default
+        #[arg(long)]
+        from: Option<Format>,
+        /// Format of the output, guessed from its extension by default
+        #[arg(long)]
+        to: Option<Format>,
+        /// Style events are assigned to when the input has no styles
+        #[arg(long, default_value = "Default")]
+        style: String,
+        /// Script to copy the style from instead of using default values
+        #[arg(long)]
+        style_from: Option<PathBuf>,
+        /// Drop formatting the output format can't express as override tags
+        #[arg(long)]
+        strip_tags: bool,
+        /// Frame rate of frame based formats, such as 23.976 or 24000/1001
+        #[arg(long, default_value_t)]
+        fps: FrameRate,
+    },
+    /// Keep only the events matching a filter, such as `style:Default*`
+    Filter {
+        input: PathBuf,
+        output: PathBuf,
+        /// Keep events matching this filter, may be repeated to keep events
+        /// matching any of them
+        #[arg(long)]
+        include: Vec<Filter>,
+        /// Drop events matching this filter, may be repeated
+        #[arg(long)]
+        exclude: Vec<Filter>,
+    },
+    /// Copy, change, rename or delete styles
+    Style {
+        input: PathBuf,
+        output: PathBuf,
+        #[command(subcommand)]
+        command: StyleCommand,
+    },
+    /// Estimate the timing difference of an overlay track against a reference
+    /// track and retime the overlay to match
+    Sync {
+        reference: PathBuf,
+        overlay: PathBuf,
+        /// Where to write the retimed overlay, only the estimate is printed
+        /// when omitted
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Largest offset searched, such as 0:01:00.00
+        #[arg(long, default_value = "0:01:00.00")]
+        max_offset: AssTime,
+        /// Only estimate an offset, not a drift
+        #[arg(long)]
+        no_drift: bool,
+        /// Refuse to write the overlay below this share of matching boundaries
+        #[arg(long, default_value_t = 0.25)]
+        min_confidence: f64,
+    },
+    /// Combine the lines of two scripts that overlap in time into bilingual
+    /// events
+    Pair {
+        primary: PathBuf,
+        secondary: PathBuf,
+        output: PathBuf,
+        /// Switch to this style with \r for the secondary line
+        #[arg(long)]
+        secondary_style: Option<String>,
+        /// Put the secondary line above the primary one
+        #[arg(long)]
+        secondary_first: bool,
+        /// Share of the shorter event two events must overlap by to be paired
+        #[arg(long, default_value_t = 0.5)]
+        min_overlap: f64,
+        /// Appended to secondary style names taken by a different primary
+        /// style
+        #[arg(long, default_value = "-secondary")]
+        style_suffix: String,
+    },
+    /// Summarize the resolution, styles, events and fonts of scripts
+    Info(InfoArgs),
+    /// Rewrite scripts in a canonical form, in place. Scripts with `;`
+    /// comment lines are left alone, as formatting would drop them
+    Fmt(FmtArgs),
+    /// Compare the styles and events of two versions of a script
+    Diff(DiffArgs),
+    /// Merge two versions of a script edited from a common base, usable as a
+    /// git merge driver with `subass merge %O %A %B`
+    Merge(MergeArgs),
+    /// Repair common defects and list the changes made
+    Fix(FixArgs),
+    /// Check a script for timing, text and style problems
+    Lint(LintArgs),
+    /// Simulate how simultaneous events stack and report overlapping and
+    /// off-screen lines
+    Collisions {
+        file: PathBuf,
+        #[command(flatten)]
+        fonts: FontArgs,
+    },
+    /// Print how the lines of each event break and the size they render at
+    Layout {
+        file: PathBuf,
+        #[command(flatten)]
+        fonts: FontArgs,
+    },
+    /// Render the events shown at a time to a PNG image
+    #[cfg(feature = "render")]
+    Render(RenderArgs),
+    /// Report fonts used by a script that are missing or lack glyphs
+    Fonts {
+        file: PathBuf,
+        #[command(flatten)]
+        fonts: FontArgs,
+    },
+    /// Subset the fonts used by a script to the glyphs it renders
+    Subset {
+        file: PathBuf,
+        /// Directory the subset fonts and script are written to
+        #[arg(long)]
+        out_dir: PathBuf,
+        /// Give fonts new family names and update the script to match
+        #[arg(long)]
+        rename: bool,
+        #[command(flatten)]
+        fonts: FontArgs,
+    },
+}
+
+#[derive(Debug, Args)]
+struct InfoArgs {
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    /// Print the summaries as JSON
+    #[cfg(feature = "serde")]
+    #[arg(long)]
+    json: bool,
+}
 
-use crate::event::EventContext;
-use crate::style::StyleContext;
+#[derive(Debug, Args)]
+struct FmtArgs {
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    /// List the files that aren't formatted instead of rewriting them
+    #[arg(long)]
+    check: bool,
+    /// Sort events by start time, then layer
+    #[arg(long)]
+    sort_events: bool,
+    /// Sort styles by name
+    #[arg(long)]
+    sort_styles: bool,
+}
 
-#[derive(Default, Debug, Clone)]
-pub struct AssScript {
-    pub other_sections: HashMap<String, Vec<String>>,
-    pub styles: Styles,
-    pub events: Events,
+#[derive(Debug, Args)]
+struct DiffArgs {
+    old: PathBuf,
+    new: PathBuf,
+    /// Print the changes as JSON
+    #[cfg(feature = "serde")]
+    #[arg(long)]
+    json: bool,
 }
 
-impl AssScript {
-    fn try_from_file(filename: &str) -> anyhow::Result<Self> {
-        let mut script = AssScript::default();
+#[derive(Debug, Args)]
+struct MergeArgs {
+    base: PathBuf,
+    ours: PathBuf,
+    theirs: PathBuf,
+    /// Where to write the merged script, ours by default
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
 
-        // initial state is before a section
-        let mut section = "";
+#[cfg(feature = "render")]
+#[derive(Debug, Args)]
+struct RenderArgs {
+    file: PathBuf,
+    /// Time of the frame, such as 0:01:23.45
+    time: AssTime,
+    output: PathBuf,
+    /// `transparent`, a colour as &HAABBGGRR or #RRGGBB, or a PNG image
+    #[arg(long, default_value = "transparent")]
+    background: Background,
+    #[command(flatten)]
+    fonts: FontArgs,
+}
 
-        let mut set_style_format = false;
-        let mut set_event_format = false;
+#[derive(Debug, Args)]
+struct FixArgs {
+    input: PathBuf,
+    output: PathBuf,
+    /// Only apply this fix, may be repeated
+    #[arg(long)]
+    only: Vec<AutoFix>,
+    /// Don't apply this fix, may be repeated
+    #[arg(long)]
+    skip: Vec<AutoFix>,
+    /// Largest gap between lines closed by snap-gaps
+    #[arg(long, default_value = "0:00:00.30")]
+    max_gap: AssTime,
+    /// Duration short lines are extended to by min-duration
+    #[arg(long, default_value = "0:00:00.50")]
+    min_duration: AssTime,
+}
+
+#[derive(Debug, Args)]
+struct LintArgs {
+    file: PathBuf,
+    /// Rule configuration, `.subass-lint` in the current directory by default
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Write the script with the available fixes applied, failing if errors
+    /// no fix covers remain
+    #[arg(long)]
+    fix: Option<PathBuf>,
+    /// Also report fonts that aren't available
+    #[arg(long)]
+    check_fonts: bool,
+    #[command(flatten)]
+    fonts: FontArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum StyleCommand {
+    /// Copy a style from another script, replacing one of the same name
+    Copy {
+        /// Script to copy the style from
+        #[arg(long)]
+        from: PathBuf,
+        style: String,
+        /// Name of the copy, the original name by default
+        #[arg(long = "as")]
+        new_name: Option<String>,
+    },
+    /// Change fields of a style, given as Field=value such as Fontsize=70
+    Set {
+        style: String,
+        #[arg(required = true, value_parser = parse_field)]
+        fields: Vec<(StyleField, String)>,
+    },
+    /// Rename a style and update the events using it
+    Rename { style: String, new_name: String },
+    /// Delete a style. Its events are kept unless told otherwise.
+    Delete {
+        style: String,
+        /// Delete the events using the style as well
+        #[arg(long, conflicts_with = "reassign")]
+        delete_events: bool,
+        /// Move the events using the style to this one
+        #[arg(long)]
+        reassign: Option<String>,
+    },
+    /// Delete the styles no event uses, and list events using undefined ones
+    Prune,
+}
+
+fn parse_field(s: &str) -> anyhow::Result<(StyleField, String)> {
+    let (field, value) = s.split_once('=').context("expected Field=value")?;
+    Ok((field.trim().parse()?, value.to_string()))
+}
 
-        for line in std::fs::read_to_string(filename)?.lines() {
-            // ensure line doesn't have leading or trailing space
-            let line = line.trim();
+#[derive(Debug, Args)]
+struct FontArgs {
+    /// Directory to search for font files, may be repeated
+    #[arg(long = "font-dir")]
+    font_dirs: Vec<PathBuf>,
+    /// Matroska file whose attached fonts are available, may be repeated
+    #[arg(long)]
+    mkv: Vec<PathBuf>,
+    /// Don't search the platform's font directories
+    #[arg(long)]
+    no_system_fonts: bool,
+}
 
-            // TODO: Collect comments in this struct as well
-            // skip comments and newlines
-            if line.starts_with(';') || line.is_empty() {
-                continue;
+impl FontArgs {
+    fn load(&self, script: &AssScript) -> anyhow::Result<FontDatabase> {
+        let mut db = FontDatabase::default();
+        for dir in &self.font_dirs {
+            db.load_dir(dir)?;
+        }
+        if !self.no_system_fonts {
+            for dir in font::system_font_dirs() {
+                db.load_dir(&dir)?;
             }
+        }
+        for x in &self.mkv {
+            db.load_mkv(x)?;
+        }
+        db.load_script(script)?;
+        Ok(db)
+    }
+}
 
-            // parse the sections
-            if line.starts_with('[') && line.ends_with(']') {
-                section = &line[1..line.len() - 1];
-                continue;
+// a match arm per subcommand, the larger ones call a function of their own
+#[allow(clippy::too_many_lines)]
+fn main() -> anyhow::Result<()> {
+    match Cli::parse().command {
+        Command::Dump { file } => dump(&file),
+        Command::Convert {
+            input,
+            output,
+            from,
+            to,
+            style,
+            style_from,
+            strip_tags,
+            fps,
+        } => {
+            let mut options = ConvertOptions {
+                tags: if strip_tags {
+                    TagMode::Strip
+                } else {
+                    TagMode::Translate
+                },
+                framerate: fps,
+                ..Default::default()
+            };
+            options.style.name.clone_from(&style);
+            if let Some(path) = style_from {
+                let script = AssScript::try_from_file(&path)?;
+                options.style = script
+                    .styles
+                    .entries
+                    .into_iter()
+                    .find(|x| x.name == style)
+                    .context(format!("style {style} not found in {}", path.display()))?;
             }
+            convert(&input, &output, from, to, &options)
+        }
+        Command::Filter {
+            input,
+            output,
+            include,
+            exclude,
+        } => edit(&input, &output, |script| {
+            script.retain_selected(&Selection { include, exclude });
+            Ok(())
+        }),
+        Command::Style {
+            input,
+            output,
+            command,
+        } => edit(&input, &output, |script| edit_style(script, command)),
+        Command::Sync {
+            reference,
+            overlay,
+            output,
+            max_offset,
+            no_drift,
+            min_confidence,
+        } => {
+            let options = SyncOptions {
+                max_offset,
+                drift: !no_drift,
+                ..Default::default()
+            };
+            sync(
+                &reference,
+                &overlay,
+                output.as_deref(),
+                &options,
+                min_confidence,
+            )
+        }
+        Command::Pair {
+            primary,
+            secondary,
+            output,
+            secondary_style,
+            secondary_first,
+            min_overlap,
+            style_suffix,
+        } => {
+            let options = PairOptions {
+                layout: secondary_style.map_or(Layout::Join, Layout::Inline),
+                secondary_first,
+                min_overlap,
+                style_suffix,
+            };
+            let primary = AssScript::try_from_file(primary)?;
+            let secondary = AssScript::try_from_file(secondary)?;
+            let paired = bilingual::pair(&primary, &secondary, &options)?;
+            std::fs::write(output, paired.to_ass_string()?)?;
+            Ok(())
+        }
+        Command::Info(args) => info(&args),
+        Command::Fmt(args) => format_files(&args),
+        Command::Diff(args) => diff(&args),
+        Command::Merge(args) => merge(&args),
+        Command::Fix(args) => autofix(&args),
+        Command::Lint(args) => lint(&args),
+        Command::Collisions { file, fonts } => check_collisions(&file, &fonts),
+        Command::Layout { file, fonts } => print_layout(&file, &fonts),
+        #[cfg(feature = "render")]
+        Command::Render(args) => render(&args),
+        Command::Fonts { file, fonts } => check_fonts(&file, &fonts),
+        Command::Subset {
+            file,
+            out_dir,
+            rename,
+            fonts,
+        } => subset_fonts(&file, &out_dir, rename, &fonts),
+    }
+}
 
-            match section {
-                "V4+ Styles" => {
-                    // first line must be format
-                    if !set_style_format {
-                        script.styles.context = StyleContext::from_format_line(line)?;
-                        set_style_format = true;
-                        continue;
-                    }
-
-                    let parsed = script.styles.context.style_strict_from_line(line)?;
-                    script.styles.entries.push(parsed);
-                }
-                "Events" => {
-                    // first line must be format
-                    if !set_event_format {
-                        script.events.context = EventContext::from_format_line(line)?;
-                        set_event_format = true;
-                        continue;
-                    }
-
-                    let parsed = script.events.context.event_strict_from_line(line)?;
-                    script.events.entries.push(parsed);
-                }
-                _ => {
-                    script
-                        .other_sections
-                        .entry(section.to_string())
-                        .and_modify(|list| list.push(line.to_string()))
-                        .or_insert_with(|| vec![line.to_string()]);
-                }
+fn dump(file: &Path) -> anyhow::Result<()> {
+    let script = AssScript::try_from_file(file)?;
+    println!("{}", script.styles.context.format_line());
+    for x in script.styles.entries {
+        println!("{}", script.styles.context.line_from_style_strict(&x)?);
+    }
+    println!("{}", script.events.context.format_line());
+    for x in script.events.entries {
+        println!("{}", script.events.context.line_from_event_strict(&x)?);
+    }
+    Ok(())
+}
+
+fn convert(
+    input: &Path,
+    output: &Path,
+    from: Option<Format>,
+    to: Option<Format>,
+    options: &ConvertOptions,
+) -> anyhow::Result<()> {
+    let from = from
+        .or_else(|| Format::from_path(input))
+        .context("unable to guess input format, use --from")?;
+    let to = to
+        .or_else(|| Format::from_path(output))
+        .context("unable to guess output format, use --to")?;
+
+    let script = convert::read(&std::fs::read_to_string(input)?, from, options)?;
+    std::fs::write(output, convert::write(&script, to, options)?)?;
+    Ok(())
+}
+
+/// Reads a script, changes it with `f` and writes it to `output`
+fn edit(
+    input: &Path,
+    output: &Path,
+    f: impl FnOnce(&mut AssScript) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut script = AssScript::try_from_file(input)?;
+    f(&mut script)?;
+    std::fs::write(output, script.to_ass_string()?)?;
+    Ok(())
+}
+
+fn edit_style(script: &mut AssScript, command: StyleCommand) -> anyhow::Result<()> {
+    match command {
+        StyleCommand::Copy {
+            from,
+            style,
+            new_name,
+        } => {
+            let source = AssScript::try_from_file(from)?;
+            let new_name = new_name.unwrap_or_else(|| style.clone());
+            script.styles.copy_from(&source.styles, &style, &new_name)
+        }
+        StyleCommand::Set { style, fields } => {
+            let target = script
+                .styles
+                .find_mut(&style)
+                .context(format!("style {style} not found"))?;
+            for (field, value) in fields {
+                // renaming also updates the events, which only the script can
+                anyhow::ensure!(
+                    field != StyleField::Name,
+                    "use `style rename` to change the name of a style"
+                );
+                target.set_field(&field, &value)?;
             }
+            Ok(())
+        }
+        StyleCommand::Rename { style, new_name } => script.rename_style(&style, &new_name),
+        StyleCommand::Delete {
+            style,
+            delete_events,
+            reassign,
+        } => {
+            let events = match (delete_events, reassign) {
+                (_, Some(x)) => OrphanEvents::Reassign(x),
+                (true, None) => OrphanEvents::Delete,
+                (false, None) => OrphanEvents::Keep,
+            };
+            script.delete_style(&style, &events)
         }
+        StyleCommand::Prune => {
+            let usage = script.style_usage();
+            for (style, events) in &usage.missing {
+                println!("missing style {style}, used by {} events", events.len());
+            }
+            for style in script.prune_styles() {
+                println!("removed unused style {style}");
+            }
+            Ok(())
+        }
+    }
+}
 
-        Ok(script)
+fn sync(
+    reference: &Path,
+    overlay: &Path,
+    output: Option<&Path>,
+    options: &SyncOptions,
+    min_confidence: f64,
+) -> anyhow::Result<()> {
+    let reference = AssScript::try_from_file(reference)?;
+    let mut overlay = AssScript::try_from_file(overlay)?;
+
+    let estimate = sync::estimate(&reference.events, &overlay.events, options)?;
+    println!("offset: {:+.3}s", estimate.retiming.offset / 1000.0);
+    println!("scale: {:.6}", estimate.retiming.scale);
+    println!(
+        "confidence: {:.1}% ({}/{} boundaries)",
+        estimate.confidence() * 100.0,
+        estimate.matched,
+        estimate.total
+    );
+
+    if let Some(output) = output {
+        anyhow::ensure!(
+            estimate.confidence() >= min_confidence,
+            "confidence too low, not writing {}",
+            output.display()
+        );
+        overlay.events.retime(&estimate.retiming, |_| true)?;
+        std::fs::write(output, overlay.to_ass_string()?)?;
     }
+    Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
-    let file_bot = "example.en.ass";
-    // let file_top = "example.zh-TW.ass";
+fn info(args: &InfoArgs) -> anyhow::Result<()> {
+    let mut summaries = Vec::new();
+    for file in &args.files {
+        let script = AssScript::try_from_file(file)?;
+        summaries.push((file.display().to_string(), script.summary()?));
+    }
+
+    #[cfg(feature = "serde")]
+    if args.json {
+        #[derive(serde::Serialize)]
+        struct FileSummary<'a> {
+            path: &'a str,
+            summary: &'a subass::info::Summary,
+        }
+        let summaries: Vec<_> = summaries
+            .iter()
+            .map(|(path, summary)| FileSummary { path, summary })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&summaries)?);
+        return Ok(());
+    }
+
+    let counts = |map: &BTreeMap<String, usize>| -> String {
+        let counts: Vec<String> = map.iter().map(|(k, v)| format!("{k} {v}")).collect();
+        counts.join(", ")
+    };
+    for (file, summary) in summaries {
+        println!("{file}");
+        println!(
+            "  resolution   {}x{}",
+            summary.play_res.0, summary.play_res.1
+        );
+        println!(
+            "  styles       {} ({} unused)",
+            summary.styles,
+            summary.unused_styles.len()
+        );
+        if !summary.missing_styles.is_empty() {
+            println!("  missing      {}", summary.missing_styles.join(", "));
+        }
+        println!("  events       {}", summary.events);
+        println!("  by type      {}", counts(&summary.events_by_type));
+        println!("  by style     {}", counts(&summary.events_by_style));
+        let layers: Vec<String> = summary
+            .events_by_layer
+            .iter()
+            .map(|(k, v)| format!("{k}: {v}"))
+            .collect();
+        println!("  by layer     {}", layers.join(", "));
+        if let (Some(first), Some(last)) = (summary.first, summary.last) {
+            println!(
+                "  time         {first} - {last}, {} covered",
+                summary.covered
+            );
+        }
+        println!("  signs        {}", summary.signs);
+        println!("  fonts        {}", summary.fonts.join(", "));
+    }
+    Ok(())
+}
+
+fn format_files(args: &FmtArgs) -> anyhow::Result<()> {
+    let options = FmtOptions {
+        sort_events: args.sort_events,
+        sort_styles: args.sort_styles,
+    };
+    let mut unformatted = 0;
+    for file in &args.files {
+        let contents = std::fs::read_to_string(file)?;
+        let formatted = canonical::format(&contents, &options)
+            .context(format!("failed to format {}", file.display()))?;
+        if formatted == contents {
+            continue;
+        }
+        if args.check {
+            println!("{}", file.display());
+            unformatted += 1;
+        } else {
+            std::fs::write(file, formatted)?;
+        }
+    }
+    if unformatted > 0 {
+        anyhow::bail!("{unformatted} file(s) not formatted");
+    }
+    Ok(())
+}
+
+fn diff(args: &DiffArgs) -> anyhow::Result<()> {
+    let old = AssScript::try_from_file(&args.old)?;
+    let new = AssScript::try_from_file(&args.new)?;
+    let diff = diff::diff(&old, &new)?;
+
+    #[cfg(feature = "serde")]
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+
+    print!("{diff}");
+    Ok(())
+}
 
-    let script_bot = AssScript::try_from_file(file_bot)?;
-    // TODO: Print format line
-    for x in script_bot.styles.entries {
-        println!("{}", script_bot.styles.context.line_from_style_strict(&x)?);
+fn merge(args: &MergeArgs) -> anyhow::Result<()> {
+    let base = AssScript::try_from_file(&args.base)?;
+    let ours = AssScript::try_from_file(&args.ours)?;
+    let theirs = AssScript::try_from_file(&args.theirs)?;
+    let merge = merge::merge(&base, &ours, &theirs)?;
+
+    let output = args.output.as_ref().unwrap_or(&args.ours);
+    std::fs::write(output, merge.script.to_ass_string()?)?;
+    for conflict in &merge.conflicts {
+        println!("{conflict}");
+    }
+    if !merge.conflicts.is_empty() {
+        anyhow::bail!("{} conflict(s)", merge.conflicts.len());
     }
-    // TODO: Print format line
-    for x in script_bot.events.entries {
-        println!("{}", script_bot.events.context.line_from_event_strict(&x)?);
+    Ok(())
+}
+
+fn check_collisions(file: &Path, fonts: &FontArgs) -> anyhow::Result<()> {
+    let script = AssScript::try_from_file(file)?;
+    let db = fonts.load(&script)?;
+    let layouter = Layouter::new(&script, Some(&db));
+    let collisions = script
+        .collisions_with(&|event, style, max_width| layouter.measure(event, style, max_width))?;
+    for problem in &collisions.problems {
+        println!("{problem}");
     }
+    let stacked = collisions
+        .placements
+        .iter()
+        .filter(|x| x.shift != 0.0)
+        .count();
+    println!(
+        "{} events, {stacked} moved by collisions, {} problem(s)",
+        collisions.placements.len(),
+        collisions.problems.len()
+    );
+    Ok(())
+}
+
+fn print_layout(file: &Path, fonts: &FontArgs) -> anyhow::Result<()> {
+    let script = AssScript::try_from_file(file)?;
+    let db = fonts.load(&script)?;
+    let layouter = Layouter::new(&script, Some(&db));
+    for (i, event) in script.events.entries.iter().enumerate() {
+        if event.event_type != EventType::Dialogue {
+            continue;
+        }
+        let layout = layouter.layout(event);
+        println!(
+            "{} {:.0}x{:.0}",
+            Location::Event(i),
+            layout.width,
+            layout.height
+        );
+        for line in &layout.lines {
+            println!("    {:>5.0} | {}", line.width, line.text);
+        }
+    }
+    Ok(())
+}
 
-    // let script_top = AssScript::try_from_file(file_top)?;
-    // dbg!(script_top);
+#[cfg(feature = "render")]
+fn render(args: &RenderArgs) -> anyhow::Result<()> {
+    let script = AssScript::try_from_file(&args.file)?;
+    let db = args.fonts.load(&script)?;
+    let frame = Renderer::new(&script, &db)?.frame(args.time, &args.background)?;
+    frame
+        .save_png(&args.output)
+        .with_context(|| format!("failed to write {}", args.output.display()))
+}
+
+fn autofix(args: &FixArgs) -> anyhow::Result<()> {
+    let mut options = FixOptions {
+        max_gap: args.max_gap,
+        min_duration: args.min_duration,
+        ..Default::default()
+    };
+    if !args.only.is_empty() {
+        options.fixes = args.only.iter().copied().collect();
+    }
+    options.fixes.retain(|x| !args.skip.contains(x));
+
+    edit(&args.input, &args.output, |script| {
+        for x in script.autofix(&options)? {
+            println!("{}: [{}] {}", x.location, x.fix, x.message);
+        }
+        Ok(())
+    })
+}
+
+fn lint(args: &LintArgs) -> anyhow::Result<()> {
+    let file = &args.file;
+    let config: LintConfig = match &args.config {
+        Some(path) => std::fs::read_to_string(path)?.parse()?,
+        None => match std::fs::read_to_string(".subass-lint") {
+            Ok(x) => x.parse().context("invalid .subass-lint")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => LintConfig::default(),
+            Err(e) => return Err(e).context("failed to read .subass-lint"),
+        },
+    };
+    let mut script = AssScript::try_from_file(file)?;
+    let db = if args.check_fonts {
+        Some(args.fonts.load(&script)?)
+    } else {
+        None
+    };
+    let diagnostics = lint::lint(&script, &config, db.as_ref())?;
+
+    let mut errors = 0;
+    for x in &diagnostics {
+        let fixable = if x.fix.is_some() { " (fixable)" } else { "" };
+        println!(
+            "{}: {}: {} [{}] {}{fixable}",
+            file.display(),
+            x.location,
+            x.severity,
+            x.rule,
+            x.message
+        );
+        if x.severity == Severity::Error {
+            errors += 1;
+        }
+    }
+
+    if let Some(output) = &args.fix {
+        let options = FixOptions {
+            fixes: diagnostics.iter().filter_map(|x| x.fix).collect(),
+            ..Default::default()
+        };
+        let changes = script.autofix(&options)?;
+        std::fs::write(output, script.to_ass_string()?)?;
+        println!("applied {} fix(es)", changes.len());
+        // errors no fix covers are still there after fixing
+        let remaining = lint::lint(&script, &config, db.as_ref())?
+            .iter()
+            .filter(|x| x.severity == Severity::Error)
+            .count();
+        if remaining > 0 {
+            anyhow::bail!("{remaining} error(s) remain after fixing");
+        }
+    } else if errors > 0 {
+        anyhow::bail!("{errors} error(s)");
+    }
+    Ok(())
+}
+
+fn check_fonts(file: &Path, fonts: &FontArgs) -> anyhow::Result<()> {
+    let script = AssScript::try_from_file(file)?;
+    let db = fonts.load(&script)?;
+
+    let usage = FontUsage::from_script(&script);
+    let mut missing = 0;
+    for status in font::check_fonts(&usage, &db)? {
+        match &status.source {
+            None => {
+                missing += 1;
+                println!("missing: {}", status.request);
+            }
+            Some(source) => {
+                println!("found: {} -> {source}", status.request);
+                if !status.missing_glyphs.is_empty() {
+                    let glyphs: String = status.missing_glyphs.iter().collect();
+                    println!("  missing glyphs: {glyphs}");
+                }
+            }
+        }
+    }
+
+    if missing > 0 {
+        anyhow::bail!("{missing} font(s) missing");
+    }
+    Ok(())
+}
+
+fn subset_fonts(file: &Path, out_dir: &Path, rename: bool, fonts: &FontArgs) -> anyhow::Result<()> {
+    let mut script = AssScript::try_from_file(file)?;
+    let db = fonts.load(&script)?;
+
+    std::fs::create_dir_all(out_dir)?;
+    for x in subset::subset_script(&mut script, &db, rename)? {
+        println!("{} ({} bytes)", x.filename, x.data.len());
+        std::fs::write(out_dir.join(&x.filename), &x.data)?;
+    }
 
+    let filename = file.file_name().unwrap_or(file.as_os_str());
+    std::fs::write(out_dir.join(filename), script.to_ass_string()?)?;
     Ok(())
 }
@@ -0,0 +1,326 @@
+use crate::common::Colour;
+use crate::tag;
+use crate::tag::TextPart;
+
+/// The HTML-like markup of `SubRip` and `WebVTT` cue text, and the `<span>`
+/// styling of TTML paragraphs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Srt,
+    Vtt,
+    Ttml,
+}
+
+/// Event text converted to markup, with the positioning markup can't express
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Markup {
+    pub text: String,
+    /// Numpad alignment from the first `\an`
+    pub alignment: Option<u8>,
+    /// Coordinates from the first `\pos`
+    pub position: Option<(f64, f64)>,
+    /// Every colour used, `WebVTT` needs a class defined for each
+    pub colours: Vec<Colour>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Span {
+    Italic,
+    Bold,
+    Underline,
+    StrikeOut,
+    Colour(Colour),
+}
+
+impl Span {
+    fn open(self, dialect: Dialect) -> String {
+        match (self, dialect) {
+            (Span::Italic, Dialect::Ttml) => "<span tts:fontStyle=\"italic\">".to_string(),
+            (Span::Bold, Dialect::Ttml) => "<span tts:fontWeight=\"bold\">".to_string(),
+            (Span::Underline, Dialect::Ttml) => {
+                "<span tts:textDecoration=\"underline\">".to_string()
+            }
+            (Span::StrikeOut, Dialect::Ttml) => {
+                "<span tts:textDecoration=\"lineThrough\">".to_string()
+            }
+            (Span::Colour(x), Dialect::Ttml) => format!("<span tts:color=\"{}\">", x.to_html()),
+            (Span::Italic, _) => "<i>".to_string(),
+            (Span::Bold, _) => "<b>".to_string(),
+            (Span::Underline, _) => "<u>".to_string(),
+            (Span::StrikeOut, Dialect::Srt) => "<s>".to_string(),
+            (Span::StrikeOut, Dialect::Vtt) => "<c.strike>".to_string(),
+            (Span::Colour(x), Dialect::Srt) => format!("<font color=\"{}\">", x.to_html()),
+            (Span::Colour(x), Dialect::Vtt) => format!("<c.{}>", colour_class(x)),
+        }
+    }
+
+    fn close(self, dialect: Dialect) -> &'static str {
+        match (self, dialect) {
+            (_, Dialect::Ttml) => "</span>",
+            (Span::Italic, _) => "</i>",
+            (Span::Bold, _) => "</b>",
+            (Span::Underline, _) => "</u>",
+            (Span::StrikeOut, Dialect::Srt) => "</s>",
+            (Span::Colour(_), Dialect::Srt) => "</font>",
+            (Span::StrikeOut | Span::Colour(_), Dialect::Vtt) => "</c>",
+        }
+    }
+
+    fn same_kind(self, other: Self) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+}
+
+/// `WebVTT` class name for a colour, such as `color_FF0000`
+pub fn colour_class(colour: Colour) -> String {
+    format!("color_{}", &colour.to_html()[1..])
+}
+
+/// Converts event text to markup. Italic, bold, underline, strikeout and
+/// primary colour are translated, drawings and other tags are dropped.
+pub fn from_ass(text: &str, dialect: Dialect) -> Markup {
+    let mut markup = Markup::default();
+    // currently open spans, innermost last
+    let mut open: Vec<Span> = Vec::new();
+    let mut drawing = false;
+
+    let close = |out: &mut String, open: &mut Vec<Span>, span: Span| {
+        if let Some(i) = open.iter().rposition(|x| x.same_kind(span)) {
+            // close everything opened after it as well, then reopen those
+            let reopen: Vec<Span> = open.drain(i..).skip(1).collect();
+            for x in reopen.iter().rev() {
+                out.push_str(x.close(dialect));
+            }
+            out.push_str(span.close(dialect));
+            for x in reopen {
+                out.push_str(&x.open(dialect));
+                open.push(x);
+            }
+        }
+    };
+
+    for part in tag::split_text(text) {
+        match part {
+            TextPart::Override(block) => {
+                for tag in tag::parse_tags(block) {
+                    let enabled = match tag.name {
+                        "b" => tag.args.parse::<u32>().is_ok_and(|x| x == 1 || x >= 700),
+                        _ => tag.args == "1",
+                    };
+                    let span = match tag.name {
+                        "i" => Span::Italic,
+                        "b" => Span::Bold,
+                        "u" => Span::Underline,
+                        "s" => Span::StrikeOut,
+                        "c" | "1c" => Span::Colour(Colour::default()),
+                        "r" => {
+                            while let Some(x) = open.pop() {
+                                markup.text.push_str(x.close(dialect));
+                            }
+                            continue;
+                        }
+                        "an" => {
+                            markup.alignment = markup.alignment.or(tag.args.parse().ok());
+                            continue;
+                        }
+                        "pos" => {
+                            let params: Vec<f64> =
+                                tag.params().iter().filter_map(|x| x.parse().ok()).collect();
+                            if let [x, y] = params[..] {
+                                markup.position = markup.position.or(Some((x, y)));
+                            }
+                            continue;
+                        }
+                        "p" => {
+                            drawing = tag.args.parse::<u32>().unwrap_or(0) > 0;
+                            continue;
+                        }
+                        _ => continue,
+                    };
+
+                    close(&mut markup.text, &mut open, span);
+                    let span = match span {
+                        Span::Colour(_) => match tag.args.parse::<Colour>() {
+                            Ok(x) => {
+                                if !markup.colours.contains(&x) {
+                                    markup.colours.push(x);
+                                }
+                                Span::Colour(x)
+                            }
+                            // an empty \c resets to the style colour
+                            Err(_) => continue,
+                        },
+                        _ if enabled => span,
+                        _ => continue,
+                    };
+                    markup.text.push_str(&span.open(dialect));
+                    open.push(span);
+                }
+            }
+            TextPart::Text(x) if !drawing => {
+                let text = tag::unescape(x);
+                match dialect {
+                    Dialect::Srt => markup.text.push_str(&text),
+                    Dialect::Vtt => markup.text.push_str(&escape(&text)),
+                    Dialect::Ttml => markup.text.push_str(&escape(&text).replace('\n', "<br/>")),
+                }
+            }
+            TextPart::Text(_) => {}
+        }
+    }
+    while let Some(x) = open.pop() {
+        markup.text.push_str(x.close(dialect));
+    }
+
+    markup
+}
+
+/// Converts cue text to event text. Line breaks become `\N`, spans become
+/// override tags and markup that has no equivalent is dropped. Unknown tags
+/// in `SubRip` are kept as text, since it has no escaping. TTML spans have
+/// no equivalent here and are dropped.
+pub fn to_ass(text: &str, dialect: Dialect) -> String {
+    let mut out = String::new();
+    // open <font> and <c> tags, with the colour they set if any
+    let mut colours: Vec<Option<Colour>> = Vec::new();
+    let mut rest = text;
+
+    while let Some(open) = rest.find('<') {
+        push_text(&mut out, &rest[..open], dialect);
+        let Some(close) = rest[open..].find('>').map(|x| x + open) else {
+            rest = &rest[open..];
+            break;
+        };
+        let tag = rest[open + 1..close].trim();
+        let name = tag
+            .split(|c: char| c == '.' || c.is_whitespace())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        let converted = match name.as_str() {
+            "i" | "b" | "u" | "s" => Some(format!("{{\\{name}1}}")),
+            "/i" | "/b" | "/u" | "/s" => Some(format!("{{\\{}0}}", &name[1..])),
+            "font" | "c" => {
+                let colour = match dialect {
+                    Dialect::Srt => font_colour(tag),
+                    Dialect::Vtt => tag.split('.').skip(1).find_map(class_colour),
+                    Dialect::Ttml => None,
+                };
+                colours.push(colour);
+                Some(colour.map_or_else(String::new, |x| format!("{{\\c{}}}", x.to_tag_string())))
+            }
+            "/font" | "/c" => {
+                let closed = colours.pop().flatten();
+                Some(match (closed, colours.iter().rev().find_map(|x| *x)) {
+                    (None, _) => String::new(),
+                    (Some(_), Some(x)) => format!("{{\\c{}}}", x.to_tag_string()),
+                    (Some(_), None) => "{\\c}".to_string(),
+                })
+            }
+            // voices, ruby, languages and karaoke timestamps have no
+            // equivalent but are valid markup
+            _ if dialect != Dialect::Srt => Some(String::new()),
+            _ => None,
+        };
+
+        match converted {
+            Some(x) => out.push_str(&x),
+            None => out.push_str(&rest[open..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    push_text(&mut out, rest, dialect);
+
+    out
+}
+
+fn push_text(out: &mut String, text: &str, dialect: Dialect) {
+    let text = match dialect {
+        Dialect::Srt => text.to_string(),
+        Dialect::Vtt | Dialect::Ttml => unescape(text),
+    };
+    out.push_str(&text.replace('\n', "\\N"));
+}
+
+fn font_colour(tag: &str) -> Option<Colour> {
+    let value = tag.split_once("color=")?.1;
+    let value = value.trim_start_matches(['"', '\'']);
+    let end = value.find(['"', '\'', ' ']).unwrap_or(value.len());
+    Colour::from_html(&value[..end]).ok()
+}
+
+fn class_colour(class: &str) -> Option<Colour> {
+    Colour::from_html(class.strip_prefix("color_")?).ok()
+}
+
+/// Escapes the characters `WebVTT` reserves for markup
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", "\\h")
+        .replace("&lrm;", "\u{200e}")
+        .replace("&rlm;", "\u{200f}")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(Dialect::Srt, r"{\i1}Hello{\i0} world", "<i>Hello</i> world")]
+    #[case(Dialect::Srt, r"{\b1\i1}a{\b0}b", "<b><i>a</i></b><i>b</i>")]
+    #[case(
+        Dialect::Srt,
+        r"{\c&H0000FF&}a\Nb{\c}c",
+        "<font color=\"#FF0000\">a\nb</font>c"
+    )]
+    #[case(Dialect::Srt, r"{\p1}m 0 0 l 1 1{\p0}x", "x")]
+    #[case(
+        Dialect::Vtt,
+        r"{\1c&H0000FF&\s1}a<b",
+        "<c.color_FF0000><c.strike>a&lt;b</c></c>"
+    )]
+    #[case(
+        Dialect::Ttml,
+        r"{\i1}a\N{\i0}b&",
+        "<span tts:fontStyle=\"italic\">a<br/></span>b&amp;"
+    )]
+    fn test_from_ass(#[case] dialect: Dialect, #[case] got: &str, #[case] should: &str) {
+        assert_eq!(from_ass(got, dialect).text, should);
+    }
+
+    #[test]
+    fn test_from_ass_positioning() {
+        let markup = from_ass(r"{\an8\pos(320,20)\c&H0000FF&}top", Dialect::Vtt);
+        assert_eq!(markup.alignment, Some(8));
+        assert_eq!(markup.position, Some((320.0, 20.0)));
+        assert_eq!(markup.colours, vec![Colour::from_html("#FF0000").unwrap()]);
+    }
+
+    #[rstest]
+    #[case(Dialect::Srt, "<i>a</i>\n<x>", r"{\i1}a{\i0}\N<x>")]
+    #[case(
+        Dialect::Srt,
+        "<font color=\"#FF0000\">a</font>",
+        r"{\c&H0000FF&}a{\c}"
+    )]
+    #[case(
+        Dialect::Vtt,
+        "<v Levi><c.color_FF0000>a</c> &amp; b",
+        r"{\c&H0000FF&}a{\c} & b"
+    )]
+    #[case(Dialect::Vtt, "<c.Default>a<00:00:01.000>b</c>", "ab")]
+    fn test_to_ass(#[case] dialect: Dialect, #[case] got: &str, #[case] should: &str) {
+        assert_eq!(to_ass(got, dialect), should);
+    }
+}
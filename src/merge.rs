@@ -0,0 +1,550 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::diff::info_entries;
+use crate::diff::match_events;
+use crate::event::EventField;
+use crate::event::EventStrict;
+use crate::event::EventType;
+use crate::lint::Location;
+use crate::style::StyleField;
+use crate::style::StyleStrict;
+use crate::AssScript;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictKind {
+    /// Both sides changed a field to different values, ours is kept
+    Field {
+        field: String,
+        base: Option<String>,
+        ours: Option<String>,
+        theirs: Option<String>,
+    },
+    /// One side deleted what the other changed, the changed version is kept
+    Deleted { by: Side },
+    /// Both sides changed a section other than [Script Info], ours is kept
+    Section { name: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// Index into the merged script
+    pub location: Location,
+    pub kind: ConflictKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct Merge {
+    pub script: AssScript,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// A merged style or event with its conflicts, before its index is known
+type Merged<T> = (T, Vec<ConflictKind>);
+
+/// Three-way merge of a single value: a change on one side wins, changes on
+/// both sides conflict unless they agree. Returns the merged value and
+/// whether it conflicts.
+fn merge_value<'a, T: PartialEq + ?Sized>(
+    base: Option<&'a T>,
+    ours: Option<&'a T>,
+    theirs: Option<&'a T>,
+) -> (Option<&'a T>, bool) {
+    if ours == theirs || theirs == base {
+        (ours, false)
+    } else if ours == base {
+        (theirs, false)
+    } else {
+        (ours, true)
+    }
+}
+
+/// Merges named values field by field, in the order ours has them. `None`
+/// marks fields that were removed.
+fn merge_fields(
+    base: &[(String, String)],
+    ours: &[(String, String)],
+    theirs: &[(String, String)],
+) -> (Vec<(String, Option<String>)>, Vec<ConflictKind>) {
+    let get = |list: &[(String, String)], field: &str| {
+        list.iter().find(|x| x.0 == field).map(|x| x.1.clone())
+    };
+    let mut names: Vec<&String> = Vec::new();
+    for (name, _) in ours.iter().chain(theirs).chain(base) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    let mut values = Vec::new();
+    let mut conflicts = Vec::new();
+    for name in names {
+        let (b, o, t) = (get(base, name), get(ours, name), get(theirs, name));
+        let (value, conflict) = merge_value(b.as_ref(), o.as_ref(), t.as_ref());
+        values.push((name.clone(), value.cloned()));
+        if conflict {
+            conflicts.push(ConflictKind::Field {
+                field: name.clone(),
+                base: b,
+                ours: o,
+                theirs: t,
+            });
+        }
+    }
+    (values, conflicts)
+}
+
+/// What merging needs to know of styles and events
+trait Entry: Clone + PartialEq {
+    fn named_fields(&self) -> Vec<(String, String)>;
+
+    /// Sets a field from [`Entry::named_fields`], `None` removes it
+    fn set_named_field(&mut self, field: &str, value: Option<&str>) -> anyhow::Result<()>;
+}
+
+impl Entry for StyleStrict {
+    fn named_fields(&self) -> Vec<(String, String)> {
+        self.fields()
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value))
+            .collect()
+    }
+
+    fn set_named_field(&mut self, field: &str, value: Option<&str>) -> anyhow::Result<()> {
+        match (StyleField::from_str(field)?, value) {
+            (StyleField::Unknown(x), None) => {
+                self.unknown_fields.remove(&x);
+            }
+            (StyleField::Unknown(x), Some(value)) => {
+                self.unknown_fields.insert(x, value.to_string());
+            }
+            (field, Some(value)) => self.set_field(&field, value)?,
+            // known fields are never missing
+            (_, None) => {}
+        }
+        Ok(())
+    }
+}
+
+impl Entry for EventStrict {
+    /// The fields and the type, such as `Comment`
+    fn named_fields(&self) -> Vec<(String, String)> {
+        self.fields()
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value))
+            .chain([("Type".to_string(), self.event_type.to_string())])
+            .collect()
+    }
+
+    fn set_named_field(&mut self, field: &str, value: Option<&str>) -> anyhow::Result<()> {
+        if field == "Type" {
+            if let Some(value) = value {
+                self.event_type = EventType::from_str(value)?;
+            }
+            return Ok(());
+        }
+        match (EventField::from_str(field)?, value) {
+            (EventField::Unknown(x), None) => {
+                self.unknown_fields.remove(&x);
+            }
+            (EventField::Unknown(x), Some(value)) => {
+                self.unknown_fields.insert(x, value.to_string());
+            }
+            (field, Some(value)) => self.set_field(&field, value)?,
+            (_, None) => {}
+        }
+        Ok(())
+    }
+}
+
+/// Merges the versions of a style or event, `None` where a side doesn't have
+/// it. Returns `None` if the merge deletes it.
+fn merge_entry<T: Entry>(
+    base: Option<&T>,
+    ours: Option<&T>,
+    theirs: Option<&T>,
+) -> anyhow::Result<Option<Merged<T>>> {
+    match (ours, theirs) {
+        (Some(ours), Some(theirs)) => {
+            let base_fields = base.map(Entry::named_fields).unwrap_or_default();
+            let (values, conflicts) =
+                merge_fields(&base_fields, &ours.named_fields(), &theirs.named_fields());
+            let mut merged = ours.clone();
+            for (field, value) in values {
+                merged.set_named_field(&field, value.as_deref())?;
+            }
+            Ok(Some((merged, conflicts)))
+        }
+        (Some(x), None) | (None, Some(x)) => {
+            let by = if ours.is_none() {
+                Side::Ours
+            } else {
+                Side::Theirs
+            };
+            Ok(match base {
+                // added on one side
+                None => Some((x.clone(), Vec::new())),
+                // deleted on one side and left alone on the other
+                Some(base) if base == x => None,
+                Some(_) => Some((x.clone(), vec![ConflictKind::Deleted { by }])),
+            })
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// Numbers the merged entries, turning their conflicts into [`Conflict`]s
+fn place<T>(
+    merged: Vec<Merged<T>>,
+    location: fn(usize) -> Location,
+    conflicts: &mut Vec<Conflict>,
+) -> Vec<T> {
+    merged
+        .into_iter()
+        .enumerate()
+        .map(|(i, (entry, kinds))| {
+            conflicts.extend(kinds.into_iter().map(|kind| Conflict {
+                location: location(i),
+                kind,
+            }));
+            entry
+        })
+        .collect()
+}
+
+fn merge_info(
+    base: &AssScript,
+    ours: &AssScript,
+    theirs: &AssScript,
+    conflicts: &mut Vec<Conflict>,
+) -> HashMap<String, Vec<String>> {
+    let mut sections = ours.other_sections.clone();
+
+    let (values, kinds) = merge_fields(
+        &info_entries(base),
+        &info_entries(ours),
+        &info_entries(theirs),
+    );
+    let info: Vec<String> = values
+        .into_iter()
+        .filter_map(|(key, value)| Some(format!("{key}: {}", value?)))
+        .collect();
+    if !info.is_empty() {
+        sections.insert("Script Info".to_string(), info);
+    }
+
+    let mut names: Vec<&String> = ours
+        .other_sections
+        .keys()
+        .chain(theirs.other_sections.keys())
+        .chain(base.other_sections.keys())
+        .filter(|x| *x != "Script Info")
+        .collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let (lines, conflict) = merge_value(
+            base.other_sections.get(name),
+            ours.other_sections.get(name),
+            theirs.other_sections.get(name),
+        );
+        match lines {
+            Some(lines) => sections.insert(name.clone(), lines.clone()),
+            None => sections.remove(name),
+        };
+        if conflict {
+            conflicts.push(Conflict {
+                location: Location::Script,
+                kind: ConflictKind::Section { name: name.clone() },
+            });
+        }
+    }
+
+    conflicts.extend(kinds.into_iter().map(|kind| Conflict {
+        location: Location::Script,
+        kind,
+    }));
+    sections
+}
+
+fn merge_styles(
+    base: &[StyleStrict],
+    ours: &[StyleStrict],
+    theirs: &[StyleStrict],
+) -> anyhow::Result<Vec<Merged<StyleStrict>>> {
+    fn find<'a>(list: &'a [StyleStrict], name: &str) -> Option<&'a StyleStrict> {
+        list.iter().find(|x| x.name == name)
+    }
+    let mut merged = Vec::new();
+    for style in ours {
+        let name = &style.name;
+        merged.extend(merge_entry(
+            find(base, name),
+            Some(style),
+            find(theirs, name),
+        )?);
+    }
+    for style in theirs {
+        if find(ours, &style.name).is_none() {
+            merged.extend(merge_entry(find(base, &style.name), None, Some(style))?);
+        }
+    }
+    Ok(merged)
+}
+
+/// Merges events matched by [`match_events`]. Events only theirs has are
+/// placed after the event they follow in theirs.
+fn merge_events(
+    base: &[EventStrict],
+    ours: &[EventStrict],
+    theirs: &[EventStrict],
+) -> anyhow::Result<Vec<Merged<EventStrict>>> {
+    let mut ours_base = vec![None; ours.len()];
+    let mut theirs_base = vec![None; theirs.len()];
+    let mut base_theirs = vec![None; base.len()];
+    for (i, j) in match_events(base, ours)? {
+        ours_base[j] = Some(i);
+    }
+    for (i, j) in match_events(base, theirs)? {
+        theirs_base[j] = Some(i);
+        base_theirs[i] = Some(j);
+    }
+
+    // events added on both sides may be the same line
+    let ours_added: Vec<usize> = (0..ours.len())
+        .filter(|&j| ours_base[j].is_none())
+        .collect();
+    let theirs_added: Vec<usize> = (0..theirs.len())
+        .filter(|&j| theirs_base[j].is_none())
+        .collect();
+    let mut added_pairs = HashMap::new();
+    let pick = |events: &[EventStrict], indexes: &[usize]| -> Vec<EventStrict> {
+        indexes.iter().map(|&i| events[i].clone()).collect()
+    };
+    for (a, b) in match_events(&pick(ours, &ours_added), &pick(theirs, &theirs_added))? {
+        added_pairs.insert(ours_added[a], theirs_added[b]);
+    }
+
+    let mut partner = vec![None; ours.len()];
+    let mut theirs_partner = vec![None; theirs.len()];
+    for (j, event) in ours.iter().enumerate() {
+        let other = match ours_base[j] {
+            Some(i) => base_theirs[i],
+            None => added_pairs.get(&j).copied(),
+        };
+        if let Some(k) = other {
+            theirs_partner[k] = Some(j);
+        }
+        partner[j] = Some(merge_entry(
+            ours_base[j].map(|i| &base[i]),
+            Some(event),
+            other.map(|k| &theirs[k]),
+        )?);
+    }
+
+    // what follows each event of ours, the first entry for what comes first
+    let mut after: Vec<Vec<Merged<EventStrict>>> = vec![Vec::new(); ours.len() + 1];
+    let mut anchor = 0;
+    for (k, event) in theirs.iter().enumerate() {
+        match theirs_partner[k] {
+            Some(j) => anchor = j + 1,
+            None => after[anchor].extend(merge_entry(
+                theirs_base[k].map(|i| &base[i]),
+                None,
+                Some(event),
+            )?),
+        }
+    }
+
+    let mut merged = Vec::new();
+    let mut after = after.into_iter();
+    merged.extend(after.next().into_iter().flatten());
+    for (entry, following) in partner.into_iter().zip(after) {
+        merged.extend(entry.flatten());
+        merged.extend(following);
+    }
+    Ok(merged)
+}
+
+/// Three-way merge of two versions of a script edited from a common base.
+/// Styles are matched by name and events as [`match_events`] does, then
+/// merged field by field. Where both sides disagree, ours is kept and the
+/// conflict is listed.
+pub fn merge(base: &AssScript, ours: &AssScript, theirs: &AssScript) -> anyhow::Result<Merge> {
+    let mut conflicts = Vec::new();
+    let mut script = ours.clone();
+
+    script.other_sections = merge_info(base, ours, theirs, &mut conflicts);
+    let styles = merge_styles(
+        &base.styles.entries,
+        &ours.styles.entries,
+        &theirs.styles.entries,
+    )?;
+    script.styles.entries = place(styles, Location::Style, &mut conflicts);
+    let events = merge_events(
+        &base.events.entries,
+        &ours.events.entries,
+        &theirs.events.entries,
+    )?;
+    script.events.entries = place(events, Location::Event, &mut conflicts);
+
+    Ok(Merge { script, conflicts })
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |x: &Option<String>| x.as_deref().unwrap_or("(none)").to_string();
+        match &self.kind {
+            ConflictKind::Field {
+                field,
+                base,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "{}: {field} changed from {} to {} in ours and {} in theirs, kept ours",
+                self.location,
+                value(base),
+                value(ours),
+                value(theirs)
+            ),
+            ConflictKind::Deleted { by } => {
+                let other = match by {
+                    Side::Ours => Side::Theirs,
+                    Side::Theirs => Side::Ours,
+                };
+                write!(
+                    f,
+                    "{}: deleted in {by} but changed in {other}, kept the changed version",
+                    self.location
+                )
+            }
+            ConflictKind::Section { name } => {
+                write!(f, "{}: both changed [{name}], kept ours", self.location)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::script;
+    use crate::time::AssTime;
+
+    fn texts(script: &AssScript) -> Vec<(&str, &str)> {
+        script
+            .events
+            .entries
+            .iter()
+            .map(|x| (x.start.as_str(), x.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_clean() {
+        let base = script(&[
+            (1000, 2000, "One"),
+            (3000, 4000, "Two"),
+            (5000, 6000, "Three"),
+        ]);
+        let mut ours = script(&[
+            (1000, 2000, "One"),
+            (2000, 3000, "Ours"),
+            (3000, 4000, "Two"),
+            (5000, 6000, "Three"),
+        ]);
+        ours.events.entries[2].set_start_time(AssTime(3500));
+        ours.styles.entries[0].fontsize = "60".to_string();
+        let theirs = script(&[
+            (1000, 2000, "One"),
+            (3000, 4000, "Two!"),
+            (6000, 7000, "Theirs"),
+        ]);
+        let mut theirs = theirs;
+        theirs.styles.entries[0].outline = "3".to_string();
+
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(merged.conflicts, vec![]);
+        assert_eq!(
+            texts(&merged.script),
+            vec![
+                ("0:00:01.00", "One"),
+                ("0:00:02.00", "Ours"),
+                ("0:00:03.50", "Two!"),
+                ("0:00:06.00", "Theirs")
+            ]
+        );
+        assert_eq!(merged.script.styles.entries[0].fontsize, "60");
+        assert_eq!(merged.script.styles.entries[0].outline, "3");
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let base = script(&[(1000, 2000, "One"), (3000, 4000, "Two")]);
+        let ours = script(&[(1000, 2000, "Ours one"), (3000, 4000, "Two")]);
+        let mut theirs = script(&[(1000, 2000, "Theirs one")]);
+        theirs.events.entries.push(EventStrict::new(
+            AssTime(3000),
+            AssTime(4500),
+            "Default",
+            "Two",
+        ));
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(
+            merged.conflicts,
+            vec![Conflict {
+                location: Location::Event(0),
+                kind: ConflictKind::Field {
+                    field: "Text".to_string(),
+                    base: Some("One".to_string()),
+                    ours: Some("Ours one".to_string()),
+                    theirs: Some("Theirs one".to_string()),
+                },
+            }]
+        );
+        assert_eq!(
+            texts(&merged.script),
+            vec![("0:00:01.00", "Ours one"), ("0:00:03.00", "Two")]
+        );
+        assert_eq!(merged.script.events.entries[1].end, "0:00:04.50");
+
+        let ours = script(&[(1000, 2000, "One")]);
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(
+            merged.conflicts,
+            vec![Conflict {
+                location: Location::Event(1),
+                kind: ConflictKind::Deleted { by: Side::Ours },
+            }]
+        );
+        assert_eq!(
+            texts(&merged.script),
+            vec![("0:00:01.00", "Theirs one"), ("0:00:03.00", "Two")]
+        );
+    }
+
+    #[test]
+    fn test_merge_info() {
+        let base = script::<(i64, i64, &str)>(&[]);
+        let mut ours = script::<(i64, i64, &str)>(&[]);
+        ours.other_sections
+            .get_mut("Script Info")
+            .unwrap()
+            .push("Title: Ours".to_string());
+        let mut theirs = script::<(i64, i64, &str)>(&[]);
+        theirs.other_sections.get_mut("Script Info").unwrap()[4] = "PlayResY: 720".to_string();
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.script.script_info("Title"), Some("Ours"));
+        assert_eq!(merged.script.script_info("PlayResY"), Some("720"));
+    }
+}
@@ -0,0 +1,345 @@
+use std::str::FromStr;
+
+use anyhow::Context;
+
+use crate::common::Colour;
+use crate::event::EventStrict;
+use crate::event::EventType;
+use crate::srt::TagMode;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::tag::TextPart;
+use crate::time::FrameRate;
+use crate::AssScript;
+
+/// Formatting of a line `MicroDVD` control codes can express
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+struct LineFormat {
+    italic: bool,
+    bold: bool,
+    underline: bool,
+    strike_out: bool,
+    colour: Option<Colour>,
+}
+
+impl LineFormat {
+    /// Control codes for this format, uppercase ones apply to every line
+    fn codes(self, whole: bool) -> String {
+        let mut styles = Vec::new();
+        for (enabled, code) in [
+            (self.italic, "i"),
+            (self.bold, "b"),
+            (self.underline, "u"),
+            (self.strike_out, "s"),
+        ] {
+            if enabled {
+                styles.push(code);
+            }
+        }
+
+        let mut out = String::new();
+        if !styles.is_empty() {
+            let key = if whole { 'Y' } else { 'y' };
+            out.push_str(&format!("{{{key}:{}}}", styles.join(",")));
+        }
+        if let Some(x) = self.colour {
+            let key = if whole { 'C' } else { 'c' };
+            out.push_str(&format!("{{{key}:${:02X}{:02X}{:02X}}}", x.b, x.g, x.r));
+        }
+        out
+    }
+}
+
+/// Reads a `MicroDVD` file into a script with a single style. A leading
+/// `{1}{1}<fps>` cue overrides `framerate`.
+pub fn import(input: &str, style: &StyleStrict, framerate: FrameRate) -> anyhow::Result<AssScript> {
+    let mut script = AssScript::new();
+    script.styles.entries.push(style.clone());
+    script.events.entries = parse_events(input, &style.name, framerate)?;
+    Ok(script)
+}
+
+/// Parses the `{start}{end}text` lines of a `MicroDVD` file into dialogue
+/// events of `style`, converting `{y:…}`, `{c:$BBGGRR}`, `{f:…}` and `{s:…}`
+/// codes to override tags
+pub fn parse_events(
+    input: &str,
+    style: &str,
+    mut framerate: FrameRate,
+) -> anyhow::Result<Vec<EventStrict>> {
+    let input = input.trim_start_matches('\u{feff}');
+    let mut events = Vec::new();
+
+    for (i, line) in input.lines().map(str::trim).enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let (start, rest) = frame(line).context(format!("invalid line: {line}"))?;
+        // the end frame may be left empty, meaning until the next line
+        let (end, text) = frame(rest).unwrap_or((start, rest.trim_start_matches("{}")));
+
+        if i == 0 && start <= 1 && end <= 1 {
+            if let Ok(x) = FrameRate::from_str(text) {
+                framerate = x;
+                continue;
+            }
+        }
+        events.push(EventStrict::new(
+            framerate.frame_to_time(start)?,
+            framerate.frame_to_time(end)?,
+            style,
+            &to_ass(text),
+        ));
+    }
+
+    // open ended lines last until the next one starts
+    for i in 1..events.len() {
+        if events[i - 1].start == events[i - 1].end {
+            let start = events[i].start.clone();
+            events[i - 1].end = start;
+        }
+    }
+    Ok(events)
+}
+
+/// Splits a leading `{frame}` off a line
+fn frame(line: &str) -> Option<(i64, &str)> {
+    let rest = line.strip_prefix('{')?;
+    let close = rest.find('}')?;
+    Some((rest[..close].trim().parse().ok()?, &rest[close + 1..]))
+}
+
+/// Converts the text of a `MicroDVD` line, where `|` separates lines and
+/// lowercase codes only apply to the line they start
+fn to_ass(text: &str) -> String {
+    let mut out = String::new();
+    for (i, line) in text.split('|').enumerate() {
+        if i > 0 {
+            out.push_str("\\N");
+        }
+
+        let mut rest = line;
+        let mut closing = String::new();
+        while let Some(code) = rest.strip_prefix('{') {
+            let Some(close) = code.find('}') else {
+                break;
+            };
+            let Some((key, value)) = code[..close].split_once(':') else {
+                break;
+            };
+            rest = &code[close + 1..];
+
+            let whole = key.chars().all(char::is_uppercase);
+            let mut tags = String::new();
+            match key.to_ascii_lowercase().as_str() {
+                "y" => {
+                    for x in value.split(',').map(|x| x.trim().to_ascii_lowercase()) {
+                        if ["i", "b", "u", "s"].contains(&x.as_str()) {
+                            tags.push_str(&format!("\\{x}1"));
+                            closing.push_str(&format!("\\{x}0"));
+                        }
+                    }
+                }
+                "c" => {
+                    if let Ok(x) = Colour::from_str(&format!("&H{}", value.trim_start_matches('$')))
+                    {
+                        tags.push_str(&format!("\\c{}", x.to_tag_string()));
+                        closing.push_str("\\c");
+                    }
+                }
+                "f" => {
+                    tags.push_str(&format!("\\fn{value}"));
+                    closing.push_str("\\fn");
+                }
+                "s" => {
+                    tags.push_str(&format!("\\fs{value}"));
+                    closing.push_str("\\fs");
+                }
+                // positions and charsets have no equivalent
+                _ => {}
+            }
+            if whole {
+                closing.clear();
+            }
+            if !tags.is_empty() {
+                out.push_str(&format!("{{{tags}}}"));
+            }
+        }
+
+        out.push_str(rest);
+        if !closing.is_empty() {
+            out.push_str(&format!("{{{closing}}}"));
+        }
+    }
+    out
+}
+
+/// Writes the dialogue events of a script as `MicroDVD`, starting with a
+/// `{1}{1}<fps>` line. Formatting can only change at the start of a line, so
+/// the formatting of each line's first character is used. Comments and
+/// drawings are dropped.
+pub fn export(script: &AssScript, tags: TagMode, framerate: FrameRate) -> anyhow::Result<String> {
+    let mut lines = Vec::new();
+    for event in &script.events.entries {
+        if event.event_type != EventType::Dialogue || tag::plain_text(&event.text).trim().is_empty()
+        {
+            continue;
+        }
+        let text = match tags {
+            TagMode::Strip => tag::plain_text(&event.text).replace('\n', "|"),
+            TagMode::Translate => from_ass(&event.text),
+        };
+        let start = framerate.time_to_frame(event.start_time()?)?;
+        let end = framerate.time_to_frame(event.end_time()?)?;
+        lines.push((start, end, text));
+    }
+    lines.sort_by_key(|(start, end, _)| (*start, *end));
+
+    let mut out = format!("{{1}}{{1}}{framerate}\n");
+    for (start, end, text) in lines {
+        out.push_str(&format!("{{{start}}}{{{end}}}{text}\n"));
+    }
+    Ok(out)
+}
+
+fn from_ass(text: &str) -> String {
+    let mut format = LineFormat::default();
+    let mut drawing = false;
+    // the formatting each line starts with, and its text
+    let mut lines = vec![(format, String::new())];
+
+    for part in tag::split_text(text) {
+        match part {
+            TextPart::Override(block) => {
+                for tag in tag::parse_tags(block) {
+                    let enabled = match tag.name {
+                        "b" => tag.args.parse::<u32>().is_ok_and(|x| x == 1 || x >= 700),
+                        _ => tag.args == "1",
+                    };
+                    match tag.name {
+                        "i" => format.italic = enabled,
+                        "b" => format.bold = enabled,
+                        "u" => format.underline = enabled,
+                        "s" => format.strike_out = enabled,
+                        "c" | "1c" => format.colour = tag.args.parse().ok(),
+                        "r" => format = LineFormat::default(),
+                        "p" => drawing = tag.args.parse::<u32>().unwrap_or(0) > 0,
+                        _ => {}
+                    }
+                }
+            }
+            TextPart::Text(x) if !drawing => {
+                for (i, piece) in tag::unescape(x).split('\n').enumerate() {
+                    if i > 0 {
+                        lines.push((format, String::new()));
+                    }
+                    let line = lines.last_mut().expect("lines is never empty");
+                    if line.1.is_empty() {
+                        line.0 = format;
+                    }
+                    line.1.push_str(piece);
+                }
+            }
+            TextPart::Text(_) => {}
+        }
+    }
+
+    let whole = lines.iter().all(|(x, _)| *x == lines[0].0);
+    let mut out = if whole {
+        lines[0].0.codes(true)
+    } else {
+        String::new()
+    };
+    for (i, (format, text)) in lines.iter().enumerate() {
+        if i > 0 {
+            out.push('|');
+        }
+        if !whole {
+            out.push_str(&format.codes(false));
+        }
+        out.push_str(text);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    const SUB: &str = "{1}{1}25
+{25}{75}{y:i}Hello|world
+{100}{}{C:$0000FF}Red|{y:b}bold
+{150}{200}{f:Courier}{s:20}Typed
+";
+
+    #[test]
+    fn test_parse_events() {
+        let events = parse_events(SUB, "Default", FrameRate::NTSC_FILM).unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].start, "0:00:01.00");
+        assert_eq!(events[0].end, "0:00:03.00");
+        assert_eq!(events[0].text, r"{\i1}Hello{\i0}\Nworld");
+        // an empty end frame lasts until the next line
+        assert_eq!(events[1].end, "0:00:06.00");
+        assert_eq!(events[1].text, r"{\c&H0000FF&}Red\N{\b1}bold{\b0}");
+        assert_eq!(events[2].text, r"{\fnCourier}{\fs20}Typed{\fn\fs}");
+    }
+
+    #[test]
+    fn test_parse_events_without_header() {
+        let events = parse_events("{24}{48}Hi", "Default", FrameRate::NTSC_FILM).unwrap();
+        assert_eq!(events[0].start, "0:00:01.00");
+        assert_eq!(events[0].end, "0:00:02.00");
+    }
+
+    #[test]
+    fn test_parse_events_large_frames() {
+        let events = parse_events(
+            "{9999999999999}{10000000000000}Hi",
+            "Default",
+            FrameRate::NTSC_FILM,
+        )
+        .unwrap();
+        assert_eq!(
+            events[0].start_time().unwrap().millis(),
+            417_083_333_333_290
+        );
+        let error = parse_events(
+            "{9223372036854775807}{0}Hi",
+            "Default",
+            FrameRate::NTSC_FILM,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "frame 9223372036854775807 is out of range"
+        );
+    }
+
+    #[rstest]
+    #[case(r"{\i1}Hello{\i0}\Nworld", "{y:i}Hello|world")]
+    #[case(r"{\b1\c&H0000FF&}a\Nb", "{Y:b}{C:$0000FF}a|b")]
+    #[case(r"plain", "plain")]
+    #[case(r"{\p1}m 0 0 l 1 1{\p0}x", "x")]
+    fn test_from_ass(#[case] got: &str, #[case] should: &str) {
+        assert_eq!(from_ass(got), should);
+    }
+
+    #[test]
+    fn test_export() {
+        let framerate = FrameRate::from_str("25").unwrap();
+        let script = import(SUB, &StyleStrict::default(), framerate).unwrap();
+        let exported = export(&script, TagMode::Translate, framerate).unwrap();
+        assert_eq!(
+            exported,
+            "{1}{1}25
+{25}{75}{y:i}Hello|world
+{100}{150}{c:$0000FF}Red|{y:b}{c:$0000FF}bold
+{150}{200}Typed
+"
+        );
+    }
+}
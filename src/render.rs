@@ -0,0 +1,906 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Context;
+use tiny_skia::FillRule;
+use tiny_skia::FilterQuality;
+use tiny_skia::LineJoin;
+use tiny_skia::Mask;
+use tiny_skia::Paint;
+use tiny_skia::PathBuilder;
+use tiny_skia::Pixmap;
+use tiny_skia::PixmapPaint;
+use tiny_skia::Stroke;
+use tiny_skia::Transform;
+
+use crate::collision;
+use crate::collision::Placement;
+use crate::common::Colour;
+use crate::event::EventStrict;
+use crate::font::FontDatabase;
+use crate::index::EventIndex;
+use crate::layout;
+use crate::layout::Faces;
+use crate::layout::Format;
+use crate::layout::Layouter;
+use crate::layout::Piece;
+use crate::style::BorderStyle;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::tag::Tag;
+use crate::tag::TextPart;
+use crate::time::AssTime;
+use crate::AssScript;
+
+/// What frames are rendered over
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Background {
+    #[default]
+    Transparent,
+    Colour(Colour),
+    /// A PNG image, stretched to the frame
+    Image(PathBuf),
+}
+
+impl Background {
+    fn draw(&self, width: u32, height: u32) -> anyhow::Result<Pixmap> {
+        let mut frame = Pixmap::new(width, height).context("invalid frame size")?;
+        match self {
+            Self::Transparent => {}
+            Self::Colour(x) => frame.fill(tiny_skia::Color::from_rgba8(x.r, x.g, x.b, 255 - x.a)),
+            Self::Image(path) => {
+                let image = Pixmap::load_png(path)
+                    .with_context(|| format!("failed to load {}", path.display()))?;
+                #[allow(clippy::cast_precision_loss)] // frame sizes are small
+                let transform = Transform::from_scale(
+                    width as f32 / image.width() as f32,
+                    height as f32 / image.height() as f32,
+                );
+                let paint = PixmapPaint {
+                    quality: FilterQuality::Bicubic,
+                    ..PixmapPaint::default()
+                };
+                frame.draw_pixmap(0, 0, image.as_ref(), &paint, transform, None);
+            }
+        }
+        Ok(frame)
+    }
+}
+
+/// Parses `transparent`, a colour as `&HAABBGGRR` or `#RRGGBB`, or else the
+/// path of an image
+impl FromStr for Background {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(if s.eq_ignore_ascii_case("transparent") {
+            Self::Transparent
+        } else if s.starts_with("&H") || s.starts_with("&h") {
+            Self::Colour(s.parse()?)
+        } else if s.starts_with('#') {
+            Self::Colour(Colour::from_html(s)?)
+        } else {
+            Self::Image(s.into())
+        })
+    }
+}
+
+/// The largest `\blur` and `\be` libass accepts, which also bounds the time
+/// and memory blurring takes
+const MAX_BLUR: f64 = 100.0;
+const MAX_BE: f64 = 127.0;
+
+/// How a run of characters is painted, as changed by override tags
+#[derive(Debug, Clone, PartialEq)]
+struct Ink {
+    /// Primary, secondary, outline and back colours
+    colours: [Colour; 4],
+    border: f64,
+    shadow: (f64, f64),
+    blur: f64,
+    be: u32,
+    opaque_box: bool,
+}
+
+impl Ink {
+    fn from_style(style: &StyleStrict) -> Self {
+        let colour = |x: &str| x.parse().unwrap_or_default();
+        let number = |x: &str| x.trim().parse::<f64>().unwrap_or(0.0).max(0.0);
+        let shadow = number(&style.shadow);
+        Self {
+            colours: [
+                colour(&style.primary_color),
+                colour(&style.secondary_color),
+                colour(&style.outline_color),
+                colour(&style.back_color),
+            ],
+            border: number(&style.outline),
+            shadow: (shadow, shadow),
+            blur: 0.0,
+            be: 0,
+            opaque_box: style.border_style == BorderStyle::OpaqueBox,
+        }
+    }
+
+    /// Follows a colour, alpha, border, shadow or blur tag, where an empty
+    /// argument goes back to the style
+    fn apply_tag(&mut self, tag: &Tag, style: &StyleStrict) {
+        let reset = Self::from_style(style);
+        let number = |default: f64| {
+            let x = tag.args.trim().parse::<f64>().ok();
+            x.filter(|x| x.is_finite()).unwrap_or(default)
+        };
+        match tag.name {
+            "c" | "1c" | "2c" | "3c" | "4c" => {
+                let i = match tag.name {
+                    "c" | "1c" => 0,
+                    "2c" => 1,
+                    "3c" => 2,
+                    _ => 3,
+                };
+                let colour = tag.args.parse::<Colour>().unwrap_or(reset.colours[i]);
+                let current = &mut self.colours[i];
+                (current.r, current.g, current.b) = (colour.r, colour.g, colour.b);
+            }
+            "alpha" => {
+                for (current, reset) in self.colours.iter_mut().zip(reset.colours) {
+                    current.a = parse_alpha(tag.args).unwrap_or(reset.a);
+                }
+            }
+            "1a" | "2a" | "3a" | "4a" => {
+                let i = usize::from(tag.name.as_bytes()[0] - b'1');
+                self.colours[i].a = parse_alpha(tag.args).unwrap_or(reset.colours[i].a);
+            }
+            "bord" => self.border = number(reset.border).max(0.0),
+            "shad" => {
+                let shadow = number(reset.shadow.0).max(0.0);
+                self.shadow = (shadow, shadow);
+            }
+            "xshad" => self.shadow.0 = number(reset.shadow.0),
+            "yshad" => self.shadow.1 = number(reset.shadow.1),
+            "blur" => self.blur = number(0.0).clamp(0.0, MAX_BLUR),
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // clamped
+            "be" => self.be = number(0.0).round().clamp(0.0, MAX_BE) as u32,
+            _ => {}
+        }
+    }
+}
+
+/// Parses the argument of an alpha tag such as `&H80&`
+fn parse_alpha(args: &str) -> Option<u8> {
+    let hex = args.trim().trim_matches('&');
+    let hex = hex.strip_prefix('H').or_else(|| hex.strip_prefix('h'))?;
+    // renderers keep the lowest byte of longer values
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .map(|x| x.to_le_bytes()[0])
+}
+
+/// The tags that apply to a whole event, the first of each winning
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Effects {
+    /// How far `\move` has taken the event from where it started
+    offset: (f64, f64),
+    /// Opacity from `\fad` and `\fade`
+    opacity: f64,
+    /// Clip rectangle or drawing, and whether it is inverted
+    clip: Option<(tiny_skia::Path, bool)>,
+}
+
+impl Effects {
+    /// The effects of event text `elapsed` milliseconds into an event of
+    /// `duration` milliseconds
+    fn new(text: &str, elapsed: f64, duration: f64) -> Self {
+        let mut effects = Self {
+            opacity: 1.0,
+            ..Self::default()
+        };
+        let (mut moved, mut faded) = (false, false);
+        for part in tag::split_text(text) {
+            let TextPart::Override(block) = part else {
+                continue;
+            };
+            for tag in tag::parse_tags(block) {
+                let params: Vec<f64> = tag.params().iter().filter_map(|x| x.parse().ok()).collect();
+                match tag.name {
+                    "pos" => moved = true,
+                    "move" if !moved && params.len() >= 4 => {
+                        moved = true;
+                        let (t1, t2) = match params[..] {
+                            [_, _, _, _, t1, t2, ..] if t1 < t2 => (t1, t2),
+                            _ => (0.0, duration),
+                        };
+                        let k = ((elapsed - t1) / (t2 - t1)).clamp(0.0, 1.0);
+                        effects.offset = ((params[2] - params[0]) * k, (params[3] - params[1]) * k);
+                    }
+                    "fad" | "fade" if !faded => {
+                        faded = true;
+                        effects.opacity = fade(&params, elapsed, duration);
+                    }
+                    "clip" | "iclip" if effects.clip.is_none() => {
+                        effects.clip = clip_path(&tag).map(|x| (x, tag.name == "iclip"));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        effects
+    }
+}
+
+/// Opacity from the parameters of `\fad(in,out)` or
+/// `\fade(a1,a2,a3,t1,t2,t3,t4)`, where the alphas are transparency
+fn fade(params: &[f64], elapsed: f64, duration: f64) -> f64 {
+    let alpha = match *params {
+        [fade_in, fade_out] => {
+            if elapsed < fade_in {
+                1.0 - elapsed / fade_in
+            } else if elapsed > duration - fade_out {
+                1.0 - (duration - elapsed) / fade_out
+            } else {
+                0.0
+            }
+        }
+        [a1, a2, a3, t1, t2, t3, t4] => {
+            let between = |a: f64, b: f64, start: f64, end: f64| {
+                if end <= start {
+                    // a fade that takes no time jumps straight to its end
+                    if elapsed < end {
+                        a
+                    } else {
+                        b
+                    }
+                } else {
+                    a + (b - a) * ((elapsed - start) / (end - start)).clamp(0.0, 1.0)
+                }
+            };
+            let alpha = if elapsed < t2 {
+                between(a1, a2, t1, t2)
+            } else if elapsed < t3 {
+                a2
+            } else {
+                between(a2, a3, t3, t4)
+            };
+            alpha / 255.0
+        }
+        _ => 0.0,
+    };
+    (1.0 - alpha).clamp(0.0, 1.0)
+}
+
+/// The shape of `\clip(x1,y1,x2,y2)` or `\clip([scale,]drawing)`
+fn clip_path(tag: &Tag) -> Option<tiny_skia::Path> {
+    let params = tag.params();
+    let numbers: Vec<f32> = params.iter().filter_map(|x| x.parse().ok()).collect();
+    match (params.len(), numbers.len()) {
+        (4, 4) => tiny_skia::Rect::from_ltrb(
+            numbers[0].min(numbers[2]),
+            numbers[1].min(numbers[3]),
+            numbers[0].max(numbers[2]),
+            numbers[1].max(numbers[3]),
+        )
+        .map(PathBuilder::from_rect),
+        (1, 0) => drawing_path(params[0], 1),
+        (2, 1) => drawing_path(params[1], params[0].parse().ok()?),
+        _ => None,
+    }
+}
+
+/// Parses the `m`, `n`, `l` and `b` commands of an ASS drawing, where
+/// coordinates are divided by 2 to the power of `scale - 1`
+fn drawing_path(drawing: &str, scale: i32) -> Option<tiny_skia::Path> {
+    let divisor = 2f32.powi(scale.max(1) - 1);
+    let mut builder = PathBuilder::new();
+    let mut command = "m";
+    let mut numbers = Vec::new();
+    for token in drawing.split_whitespace() {
+        if let Ok(x) = token.parse::<f32>() {
+            numbers.push(x / divisor);
+        } else {
+            command = token;
+            numbers.clear();
+            continue;
+        }
+        match (command, &numbers[..]) {
+            ("m" | "n", &[x, y]) => builder.move_to(x, y),
+            ("l", &[x, y]) => builder.line_to(x, y),
+            ("b", &[x1, y1, x2, y2, x, y]) => builder.cubic_to(x1, y1, x2, y2, x, y),
+            _ => continue,
+        }
+        numbers.clear();
+    }
+    builder.close();
+    builder.finish()
+}
+
+/// Glyph outlines in font units, placed and scaled into a path
+struct Outline<'a> {
+    builder: &'a mut PathBuilder,
+    origin: (f32, f32),
+    scale: (f32, f32),
+    /// Horizontal slant per unit up, to fake italics
+    skew: f32,
+}
+
+impl Outline<'_> {
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.origin.0 + (x + self.skew * y) * self.scale.0,
+            self.origin.1 - y * self.scale.1,
+        )
+    }
+}
+
+impl ttf_parser::OutlineBuilder for Outline<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.builder.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.builder.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let ((x1, y1), (x, y)) = (self.point(x1, y1), self.point(x, y));
+        self.builder.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let ((x1, y1), (x2, y2)) = (self.point(x1, y1), self.point(x2, y2));
+        let (x, y) = self.point(x, y);
+        self.builder.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.builder.close();
+    }
+}
+
+/// Characters next to each other on a line, painted the same way
+struct Run {
+    ink: Ink,
+    glyphs: PathBuilder,
+    /// Left, top, right and bottom of the line the characters take up
+    bounds: (f64, f64, f64, f64),
+}
+
+impl Run {
+    /// The shape of the outline and the area it covers: the glyphs widened
+    /// by the border, or the box around them with an opaque box
+    fn border_shape(&self, glyphs: Option<&tiny_skia::Path>) -> Option<(tiny_skia::Path, f64)> {
+        if !self.ink.opaque_box {
+            return glyphs.map(|x| (x.clone(), self.ink.border));
+        }
+        let (left, top, right, bottom) = self.bounds;
+        let border = self.ink.border;
+        #[allow(clippy::cast_possible_truncation)] // script coordinates
+        let rect = tiny_skia::Rect::from_ltrb(
+            (left - border) as f32,
+            (top - border) as f32,
+            (right + border) as f32,
+            (bottom + border) as f32,
+        )?;
+        Some((PathBuilder::from_rect(rect), 0.0))
+    }
+}
+
+/// A painted part of an event and where it goes in the frame
+struct Layer {
+    pixmap: Pixmap,
+    x: i32,
+    y: i32,
+    opacity: f64,
+}
+
+impl Layer {
+    /// Fills a shape widened by `border`, blurred by `\blur` and `\be`.
+    /// The colour's alpha is kept apart, so that the outline doesn't show
+    /// through where it overlaps the shape.
+    #[allow(clippy::cast_possible_truncation)] // script coordinates
+    fn new(
+        shape: &tiny_skia::Path,
+        offset: (f64, f64),
+        border: f64,
+        colour: Colour,
+        ink: &Ink,
+    ) -> Option<Self> {
+        let pad = (border + ink.blur * 3.0 + f64::from(ink.be)).ceil() + 2.0;
+        let bounds = shape.bounds();
+        let left = (f64::from(bounds.left()) + offset.0 - pad).floor();
+        let top = (f64::from(bounds.top()) + offset.1 - pad).floor();
+        let width = (f64::from(bounds.right()) + offset.0 + pad).ceil() - left;
+        let height = (f64::from(bounds.bottom()) + offset.1 + pad).ceil() - top;
+        #[allow(clippy::cast_sign_loss)] // padded, so always positive
+        let mut pixmap = Pixmap::new(width as u32, height as u32)?;
+
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(colour.r, colour.g, colour.b, 255);
+        paint.anti_alias = true;
+        let transform =
+            Transform::from_translate((offset.0 - left) as f32, (offset.1 - top) as f32);
+        pixmap.fill_path(shape, &paint, FillRule::Winding, transform, None);
+        if border > 0.0 {
+            let stroke = Stroke {
+                width: (border * 2.0) as f32,
+                line_join: LineJoin::Round,
+                ..Stroke::default()
+            };
+            pixmap.stroke_path(shape, &paint, &stroke, transform, None);
+        }
+        blur(&mut pixmap, ink.blur, ink.be);
+        Some(Self {
+            pixmap,
+            x: left as i32,
+            y: top as i32,
+            opacity: 1.0 - f64::from(colour.a) / 255.0,
+        })
+    }
+}
+
+/// Blurs premultiplied pixels, approximating a gaussian blur with standard
+/// deviation `sigma` with three box blurs, then softening edges the way `be`
+/// passes of `\be` do
+fn blur(pixmap: &mut Pixmap, sigma: f64, be: u32) {
+    let (width, height) = (pixmap.width() as usize, pixmap.height() as usize);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // small and positive
+    let radius = sigma.round() as usize;
+    let passes = std::iter::repeat_n(radius, if radius > 0 { 3 } else { 0 })
+        .chain(std::iter::repeat_n(1, be as usize));
+    let data = pixmap.data_mut();
+    for radius in passes {
+        for y in 0..height {
+            blur_line(data, y * width * 4, 4, width, radius);
+        }
+        for x in 0..width {
+            blur_line(data, x * 4, width * 4, height, radius);
+        }
+    }
+}
+
+/// Box blurs `len` RGBA pixels `step` bytes apart. Everything outside the
+/// line counts as transparent.
+fn blur_line(data: &mut [u8], start: usize, step: usize, len: usize, radius: usize) {
+    let line: Vec<[usize; 4]> = (0..len)
+        .map(|i| {
+            let p = start + i * step;
+            [data[p], data[p + 1], data[p + 2], data[p + 3]].map(usize::from)
+        })
+        .collect();
+    let size = 2 * radius + 1;
+    let mut sum = [0; 4];
+    for pixel in line.iter().take(radius) {
+        for c in 0..4 {
+            sum[c] += pixel[c];
+        }
+    }
+    for i in 0..len {
+        if let Some(pixel) = line.get(i + radius) {
+            for c in 0..4 {
+                sum[c] += pixel[c];
+            }
+        }
+        if let Some(pixel) = i.checked_sub(radius + 1).map(|x| line[x]) {
+            for c in 0..4 {
+                sum[c] -= pixel[c];
+            }
+        }
+        // rounding every channel down keeps colours within alpha
+        for c in 0..4 {
+            data[start + i * step + c] = u8::try_from(sum[c] / size).unwrap_or(u8::MAX);
+        }
+    }
+}
+
+/// Renders events to images the way libass would, with fill, outline,
+/// shadow, blur, positioning, `\move`, fades and clips. Rotation, other
+/// transforms, karaoke and drawings aren't drawn.
+pub struct Renderer<'a> {
+    script: &'a AssScript,
+    layouter: Layouter<'a>,
+    index: EventIndex,
+    /// Where each event is drawn, by event position, `None` if it isn't
+    placements: Vec<Option<Placement>>,
+}
+
+impl<'a> Renderer<'a> {
+    /// Events are placed and stacked up front, with text measured using
+    /// `fonts`
+    pub fn new(script: &'a AssScript, fonts: &'a FontDatabase) -> anyhow::Result<Self> {
+        let layouter = Layouter::new(script, Some(fonts));
+        let collisions = script.collisions_with(&|event, style, max_width| {
+            layouter.measure(event, style, max_width)
+        })?;
+        let mut placements = vec![None; script.events.entries.len()];
+        for placement in collisions.placements {
+            let index = placement.index;
+            placements[index] = Some(placement);
+        }
+        Ok(Self {
+            script,
+            layouter,
+            index: script.events.index()?,
+            placements,
+        })
+    }
+
+    /// Width and height of frames, from `PlayResX` and `PlayResY`
+    pub fn size(&self) -> (u32, u32) {
+        let (width, height) = self.script.play_res();
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let size = |x: f64| x.round().max(1.0) as u32;
+        (size(width), size(height))
+    }
+
+    /// Renders the events shown at `time` over a background
+    pub fn frame(&self, time: AssTime, background: &Background) -> anyhow::Result<Pixmap> {
+        let (width, height) = self.size();
+        let mut frame = background.draw(width, height)?;
+        self.render(time, &mut frame);
+        Ok(frame)
+    }
+
+    /// Draws the events shown at `time` onto a frame of [`Self::size`],
+    /// lower layers first
+    pub fn render(&self, time: AssTime, frame: &mut Pixmap) {
+        let mut shown: Vec<&Placement> = self
+            .index
+            .at(time)
+            .into_iter()
+            .filter_map(|x| self.placements[x.index].as_ref())
+            .collect();
+        shown.sort_by_key(|x| (x.layer, x.index));
+        for placement in shown {
+            self.render_event(placement, time, frame);
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)] // milliseconds within an event
+    fn render_event(&self, placement: &Placement, time: AssTime, frame: &mut Pixmap) {
+        let event = &self.script.events.entries[placement.index];
+        let effects = Effects::new(
+            &event.text,
+            (time.millis() - placement.start.millis()) as f64,
+            (placement.end.millis() - placement.start.millis()) as f64,
+        );
+        let mask = effects.clip.as_ref().and_then(|(clip, inverse)| {
+            let mut mask = Mask::new(frame.width(), frame.height())?;
+            mask.fill_path(clip, FillRule::Winding, true, Transform::identity());
+            if *inverse {
+                mask.invert();
+            }
+            Some(mask)
+        });
+
+        let (mut shadows, mut borders, mut fills) = (Vec::new(), Vec::new(), Vec::new());
+        for run in self.runs(event, placement, effects.offset) {
+            let glyphs = run.glyphs.clone().finish();
+            let border = run.border_shape(glyphs.as_ref());
+            let ink = &run.ink;
+            if let Some((shape, width)) = border.as_ref().filter(|_| ink.shadow != (0.0, 0.0)) {
+                shadows.extend(Layer::new(shape, ink.shadow, *width, ink.colours[3], ink));
+            }
+            if let Some((shape, width)) = border.filter(|x| ink.opaque_box || x.1 > 0.0) {
+                borders.extend(Layer::new(&shape, (0.0, 0.0), width, ink.colours[2], ink));
+            }
+            if let Some(glyphs) = glyphs {
+                let fill_ink = Ink {
+                    blur: if ink.border > 0.0 { 0.0 } else { ink.blur },
+                    be: if ink.border > 0.0 { 0 } else { ink.be },
+                    ..ink.clone()
+                };
+                fills.extend(Layer::new(
+                    &glyphs,
+                    (0.0, 0.0),
+                    0.0,
+                    ink.colours[0],
+                    &fill_ink,
+                ));
+            }
+        }
+        for layer in shadows.iter().chain(&borders).chain(&fills) {
+            #[allow(clippy::cast_possible_truncation)]
+            let paint = PixmapPaint {
+                opacity: (layer.opacity * effects.opacity) as f32,
+                ..PixmapPaint::default()
+            };
+            frame.draw_pixmap(
+                layer.x,
+                layer.y,
+                layer.pixmap.as_ref(),
+                &paint,
+                Transform::identity(),
+                mask.as_ref(),
+            );
+        }
+    }
+
+    /// The glyphs of an event placed on the frame, grouped by how they are
+    /// painted
+    #[allow(clippy::cast_possible_truncation)] // script coordinates
+    fn runs(&self, event: &EventStrict, placement: &Placement, offset: (f64, f64)) -> Vec<Run> {
+        let default_style = StyleStrict::default();
+        let style = self
+            .script
+            .styles
+            .find(&event.style)
+            .unwrap_or(&default_style);
+        let (margin_l, margin_r, _) = event.margins(Some(style));
+        let max_width = collision::overrides(&event.text)
+            .1
+            .is_none()
+            .then(|| self.script.play_res().0 - margin_l - margin_r);
+        let layout = self.layouter.layout_text(&event.text, style, max_width);
+        let chars = self.chars(&event.text, style);
+
+        let mut faces = Faces::new();
+        let mut runs: Vec<Run> = Vec::new();
+        for line in &layout.lines {
+            let line_chars = &chars[line.chars.clone()];
+            let top = placement.rect.top + line.top + offset.1;
+            let baseline = top
+                + line_chars
+                    .iter()
+                    .map(|(_, format, _)| {
+                        let face = faces
+                            .entry(format.font.clone())
+                            .or_insert_with(|| self.layouter.face(&format.font));
+                        let ascent = face.as_ref().map_or(0.8, |x| {
+                            let (ascent, height) = layout::face_metrics(x);
+                            ascent / height
+                        });
+                        ascent * format.size * format.scale_y
+                    })
+                    .reduce(f64::max)
+                    .unwrap_or(0.0);
+
+            let mut x = placement.rect.left + line.left + offset.0;
+            let mut current: Option<Run> = None;
+            for (c, format, ink) in line_chars {
+                let face = faces
+                    .entry(format.font.clone())
+                    .or_insert_with(|| self.layouter.face(&format.font));
+                let advance = layout::advance(face.as_ref(), *c) * format.size * format.scale_x
+                    + format.spacing;
+                if current.as_ref().is_none_or(|run| run.ink != *ink) {
+                    runs.extend(current.take());
+                    current = Some(Run {
+                        ink: ink.clone(),
+                        glyphs: PathBuilder::new(),
+                        bounds: (x, top, x, top + line.height),
+                    });
+                }
+                let run = current.as_mut().expect("run was just started");
+                run.bounds.2 = x + advance;
+                if let Some(face) = face {
+                    let (_, height) = layout::face_metrics(face);
+                    let skew = if format.font.italic && !face.is_italic() {
+                        0.2
+                    } else {
+                        0.0
+                    };
+                    let mut outline = Outline {
+                        builder: &mut run.glyphs,
+                        origin: (x as f32, baseline as f32),
+                        scale: (
+                            (format.size * format.scale_x / height) as f32,
+                            (format.size * format.scale_y / height) as f32,
+                        ),
+                        skew,
+                    };
+                    if let Some(glyph) = face.glyph_index(*c) {
+                        face.outline_glyph(glyph, &mut outline);
+                    }
+                }
+                x += advance;
+            }
+            runs.extend(current);
+        }
+        runs
+    }
+
+    /// Every character of event text with how it is drawn, in the order
+    /// [`layout::walk_text`] visits them
+    fn chars(&self, text: &str, style: &StyleStrict) -> Vec<(char, Format, Ink)> {
+        let mut current = style;
+        let mut format = Format::from_style(style);
+        let mut ink = Ink::from_style(style);
+        let mut chars = Vec::new();
+        let wrap_style = self.layouter.event_wrap_style(text);
+        layout::walk_text(text, wrap_style, |piece| match piece {
+            Piece::Tag(tag) if tag.name == "r" => {
+                current = self.layouter.reset_style(tag.args, style);
+                format = Format::from_style(current);
+                ink = Ink::from_style(current);
+            }
+            Piece::Tag(tag) => {
+                format.apply_tag(&tag, current);
+                ink.apply_tag(&tag, current);
+            }
+            Piece::Char(c) => chars.push((c, format.clone(), ink.clone())),
+        });
+        chars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing;
+
+    fn script(events: &[&str]) -> AssScript {
+        let events: Vec<_> = events.iter().map(|text| (0, 2000, *text)).collect();
+        let mut script = testing::script(&events);
+        testing::set_info(&mut script, "PlayResX", "64");
+        testing::set_info(&mut script, "PlayResY", "48");
+        let style = &mut script.styles.entries[0];
+        style.fontsize = "20".to_string();
+        style.border_style = BorderStyle::OpaqueBox;
+        style.shadow = "0".to_string();
+        script
+    }
+
+    fn pixel(frame: &Pixmap, x: u32, y: u32) -> (u8, u8, u8, u8) {
+        let p = frame.pixel(x, y).unwrap().demultiply();
+        (p.red(), p.green(), p.blue(), p.alpha())
+    }
+
+    #[rstest]
+    #[case("transparent", Background::Transparent)]
+    #[case("&H00FF0000", Background::Colour(Colour { r: 0, g: 0, b: 255, a: 0 }))]
+    #[case("#FF0000", Background::Colour(Colour { r: 255, g: 0, b: 0, a: 0 }))]
+    #[case("frame.png", Background::Image("frame.png".into()))]
+    fn test_background(#[case] s: &str, #[case] should: Background) {
+        assert_eq!(s.parse::<Background>().unwrap(), should);
+    }
+
+    #[rstest]
+    #[case("\\fad(500,500)", 250.0, 0.5)]
+    #[case("\\fad(500,500)", 1000.0, 1.0)]
+    #[case("\\fad(500,500)", 1900.0, 0.2)]
+    #[case("\\fade(255,0,255,0,1000,1500,2000)", 500.0, 0.5)]
+    #[case("\\fade(255,0,255,0,1000,1500,2000)", 1200.0, 1.0)]
+    #[case("\\fade(255,0,255,0,1000,1500,2000)", 2000.0, 0.0)]
+    #[case("\\fade(255,0,255,500,500,1500,1500)", 500.0, 1.0)]
+    #[case("\\fade(255,0,255,500,500,1500,1500)", 1500.0, 0.0)]
+    fn test_fade(#[case] text: &str, #[case] elapsed: f64, #[case] should: f64) {
+        let text = format!("{{{text}}}");
+        let opacity = Effects::new(&text, elapsed, 2000.0).opacity;
+        assert!((opacity - should).abs() < 1e-9, "{opacity} != {should}");
+    }
+
+    #[test]
+    fn test_move() {
+        let effects = Effects::new("{\\move(0,0,100,50,500,1500)}", 1000.0, 2000.0);
+        assert_eq!(effects.offset, (50.0, 25.0));
+        let effects = Effects::new("{\\pos(0,0)\\move(0,0,100,50)}", 1000.0, 2000.0);
+        assert_eq!(effects.offset, (0.0, 0.0));
+    }
+
+    #[rstest]
+    #[case("\\clip(10,20,0,5)", Some((0.0, 5.0, 10.0, 20.0)))]
+    #[case("\\iclip(m 0 0 l 8 0 8 4 0 4)", Some((0.0, 0.0, 8.0, 4.0)))]
+    #[case("\\clip(2,m 0 0 l 8 0 8 4 0 4)", Some((0.0, 0.0, 4.0, 2.0)))]
+    #[case("\\clip(1,2,3)", None)]
+    fn test_clip(#[case] text: &str, #[case] should: Option<(f32, f32, f32, f32)>) {
+        let text = format!("{{{text}}}");
+        let clip = Effects::new(&text, 0.0, 1.0).clip.map(|(x, _)| {
+            let b = x.bounds();
+            (b.left(), b.top(), b.right(), b.bottom())
+        });
+        assert_eq!(clip, should);
+    }
+
+    #[rstest]
+    #[case("\\1c&H0000FF&\\3a&H80&", [(255, 0, 0, 0), (0, 0, 0, 128)])]
+    #[case("\\c&HFF0000&\\alpha&HFF&", [(0, 0, 255, 255), (0, 0, 0, 255)])]
+    #[case("\\c&HFF0000&\\c", [(255, 255, 255, 0), (0, 0, 0, 0)])]
+    fn test_ink(#[case] text: &str, #[case] should: [(u8, u8, u8, u8); 2]) {
+        let script = script(&[]);
+        let style = script.styles.find("Default").unwrap();
+        let mut ink = Ink::from_style(style);
+        for tag in tag::parse_tags(text) {
+            ink.apply_tag(&tag, style);
+        }
+        let colour = |x: Colour| (x.r, x.g, x.b, x.a);
+        assert_eq!([colour(ink.colours[0]), colour(ink.colours[2])], should);
+    }
+
+    #[rstest]
+    #[case("\\blur2.5\\be3", (2.5, 3))]
+    #[case("\\blur1e9\\be1000", (100.0, 127))]
+    #[case("\\blur-5\\be-5", (0.0, 0))]
+    #[case("\\blurnan\\be1.6", (0.0, 2))]
+    fn test_blur_limits(#[case] text: &str, #[case] should: (f64, u32)) {
+        let script = script(&[]);
+        let style = script.styles.find("Default").unwrap();
+        let mut ink = Ink::from_style(style);
+        for tag in tag::parse_tags(text) {
+            ink.apply_tag(&tag, style);
+        }
+        assert_eq!((ink.blur, ink.be), should);
+    }
+
+    #[test]
+    fn test_blur() {
+        // an opaque 3x3 square in the middle
+        let mut pixmap = Pixmap::new(9, 9).unwrap();
+        pixmap.fill_rect(
+            tiny_skia::Rect::from_xywh(3.0, 3.0, 3.0, 3.0).unwrap(),
+            &Paint::default(),
+            Transform::identity(),
+            None,
+        );
+        let middle_row =
+            |x: &Pixmap| -> Vec<u8> { x.data()[4 * 36..5 * 36].chunks(4).map(|x| x[3]).collect() };
+        blur(&mut pixmap, 0.0, 1);
+        assert_eq!(middle_row(&pixmap), vec![0, 0, 85, 170, 255, 170, 85, 0, 0]);
+        blur(&mut pixmap, 1.0, 0);
+        let alpha = middle_row(&pixmap);
+        assert!(alpha.iter().all(|x| *x > 0));
+        assert!(alpha.windows(2).take(4).all(|x| x[0] < x[1]));
+    }
+
+    #[test]
+    fn test_frame() {
+        // without fonts, opaque boxes are still drawn where the text goes
+        let script = script(&["{\\3c&H0000FF&}Text", "{\\pos(0,0)\\an7\\clip(0,0,4,4)}x"]);
+        let fonts = FontDatabase::default();
+        let renderer = Renderer::new(&script, &fonts).unwrap();
+        assert_eq!(renderer.size(), (64, 48));
+
+        let background = Background::Colour(Colour {
+            r: 0,
+            g: 255,
+            b: 0,
+            a: 0,
+        });
+        let frame = renderer.frame(AssTime(1000), &background).unwrap();
+        assert_eq!(pixel(&frame, 32, 30), (255, 0, 0, 255));
+        assert_eq!(pixel(&frame, 32, 5), (0, 255, 0, 255));
+        assert_eq!(pixel(&frame, 2, 2), (0, 0, 0, 255));
+        assert_eq!(pixel(&frame, 8, 8), (0, 255, 0, 255));
+
+        let frame = renderer.frame(AssTime(3000), &background).unwrap();
+        assert_eq!(pixel(&frame, 32, 30), (0, 255, 0, 255));
+        let frame = renderer
+            .frame(AssTime(1000), &Background::Transparent)
+            .unwrap();
+        assert_eq!(pixel(&frame, 32, 5), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_frame_glyphs() {
+        // 'I' in the test font is a bar from 50 to 200 across and up to 700,
+        // so at 40px it covers x 2 to 8 of its 10px advance and 28px above
+        // the baseline, which sits 8px above the bottom
+        let mut script = script(&["{\\pos(10,40)\\an1}I{\\r}I"]);
+        script.styles.entries.push(StyleStrict {
+            name: "Test".to_string(),
+            fontname: "Subass Test".to_string(),
+            fontsize: "40".to_string(),
+            primary_color: "&H000000FF".to_string(),
+            outline: "0".to_string(),
+            shadow: "0".to_string(),
+            ..Default::default()
+        });
+        script.events.entries[0].style = "Test".to_string();
+        let fonts = testing::fonts();
+        let renderer = Renderer::new(&script, &fonts).unwrap();
+        let frame = renderer
+            .frame(AssTime(1000), &Background::Transparent)
+            .unwrap();
+
+        assert_eq!(pixel(&frame, 15, 20), (255, 0, 0, 255));
+        // the bare \r keeps the event's style, so both bars are alike
+        let row: Vec<bool> = (10..30).map(|x| pixel(&frame, x, 20).3 > 0).collect();
+        let bars: Vec<bool> = (10..30).map(|x| (x % 10) >= 2 && (x % 10) < 8).collect();
+        assert_eq!(row, bars);
+        let column: Vec<bool> = (0..48).map(|y| pixel(&frame, 15, y).3 > 0).collect();
+        let bar: Vec<bool> = (0..48).map(|y| (4..32).contains(&y)).collect();
+        assert_eq!(column, bar);
+    }
+}
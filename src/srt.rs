@@ -0,0 +1,163 @@
+use anyhow::Context;
+
+use crate::event::EventStrict;
+use crate::event::EventType;
+use crate::markup;
+use crate::markup::Dialect;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::time::AssTime;
+use crate::AssScript;
+
+/// What to do with override tags when exporting to a format without them
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum TagMode {
+    /// Drop all formatting
+    Strip,
+    /// Keep the formatting the target format can express
+    #[default]
+    Translate,
+}
+
+/// Reads a `SubRip` file into a script with a single style
+pub fn import(input: &str, style: &StyleStrict) -> anyhow::Result<AssScript> {
+    let mut script = AssScript::new();
+    script.styles.entries.push(style.clone());
+    script.events.entries = parse_events(input, &style.name)?;
+    Ok(script)
+}
+
+/// Parses the cues of a `SubRip` file into dialogue events of `style`,
+/// converting `<i>`, `<b>`, `<u>`, `<s>` and `<font color>` to override tags
+pub fn parse_events(input: &str, style: &str) -> anyhow::Result<Vec<EventStrict>> {
+    let input = input.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut events = Vec::new();
+    let mut lines = input.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let Some((start, end)) = line.split_once("-->") else {
+            continue;
+        };
+        let start = AssTime::parse_clock(start).context(format!("invalid cue timing: {line}"))?;
+        // anything after the end time are position coordinates, ignore them
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let end = AssTime::parse_clock(end).context(format!("invalid cue timing: {line}"))?;
+
+        let mut text = Vec::new();
+        while let Some(line) = lines.next_if(|x| !x.trim().is_empty()) {
+            text.push(line.trim_end());
+        }
+        let text = markup::to_ass(&text.join("\n"), Dialect::Srt);
+        events.push(EventStrict::new(start, end, style, &text));
+    }
+
+    Ok(events)
+}
+
+/// Writes the dialogue events of a script as `SubRip`. Comments and drawings
+/// are dropped, and events sharing the same timing are merged into one cue
+/// since `SubRip` has no layers.
+pub fn export(script: &AssScript, tags: TagMode) -> anyhow::Result<String> {
+    let mut cues: Vec<(AssTime, AssTime, i64, String)> = Vec::new();
+    for event in &script.events.entries {
+        if event.event_type != EventType::Dialogue {
+            continue;
+        }
+        let text = match tags {
+            TagMode::Strip => tag::plain_text(&event.text),
+            TagMode::Translate => {
+                let markup = markup::from_ass(&event.text, Dialect::Srt);
+                // the \an extension is understood by most players
+                match markup.alignment {
+                    Some(x) if x != 2 => format!("{{\\an{x}}}{}", markup.text),
+                    _ => markup.text,
+                }
+            }
+        };
+        if tag::plain_text(&event.text).trim().is_empty() {
+            continue;
+        }
+        let layer = event.layer.parse().unwrap_or_default();
+        cues.push((event.start_time()?, event.end_time()?, layer, text));
+    }
+    cues.sort_by_key(|(start, end, layer, _)| (*start, *end, *layer));
+
+    let mut merged: Vec<(AssTime, AssTime, String)> = Vec::new();
+    for (start, end, _, text) in cues {
+        match merged.last_mut() {
+            Some(last) if last.0 == start && last.1 == end => {
+                last.2.push('\n');
+                last.2.push_str(&text);
+            }
+            _ => merged.push((start, end, text)),
+        }
+    }
+
+    let mut out = String::new();
+    for (i, (start, end, text)) in merged.iter().enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n{text}\n\n",
+            i + 1,
+            start.to_clock_millis(','),
+            end.to_clock_millis(',')
+        ));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRT: &str = "1
+00:00:02,940 --> 00:00:06,520
+Hey! <i>You okay</i>, Oliver?!
+<font color=\"#FF0000\">Hang in th--!</font>
+
+2
+00:00:18,990 --> 00:00:23,660 X1:10 X2:20 Y1:30 Y2:40
+That's the last of the pursuers, Levi.
+";
+
+    #[test]
+    fn test_parse_events() {
+        let events = parse_events(SRT, "Default").unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].start, "0:00:02.94");
+        assert_eq!(events[0].end, "0:00:06.52");
+        assert_eq!(events[0].style, "Default");
+        assert_eq!(
+            events[0].text,
+            r"Hey! {\i1}You okay{\i0}, Oliver?!\N{\c&H0000FF&}Hang in th--!{\c}"
+        );
+        assert_eq!(events[1].end, "0:00:23.66");
+    }
+
+    #[test]
+    fn test_export() {
+        let mut script = import(SRT, &StyleStrict::default()).unwrap();
+        let mut simultaneous = script.events.entries[1].clone();
+        simultaneous.text = "Same time".to_string();
+        script.events.entries.push(simultaneous);
+        let mut comment = script.events.entries[0].clone();
+        comment.event_type = EventType::Comment;
+        script.events.entries.push(comment);
+
+        let exported = export(&script, TagMode::Strip).unwrap();
+        assert_eq!(
+            exported,
+            "1
+00:00:02,940 --> 00:00:06,520
+Hey! You okay, Oliver?!
+Hang in th--!
+
+2
+00:00:18,990 --> 00:00:23,660
+That's the last of the pursuers, Levi.
+Same time
+
+"
+        );
+    }
+}
@@ -3,14 +3,26 @@ use std::str::FromStr;
 
 use anyhow::Context;
 
+use crate::common::serde_via_str;
 use crate::common::Boolean;
 
 #[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Styles {
     pub context: StyleContext,
     pub entries: Vec<StyleStrict>,
 }
 
+impl Styles {
+    pub fn find(&self, name: &str) -> Option<&StyleStrict> {
+        self.entries.iter().find(|x| x.name == name)
+    }
+
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut StyleStrict> {
+        self.entries.iter_mut().find(|x| x.name == name)
+    }
+}
+
 /// Known fields in the [V4+ Styles] section
 #[derive(Debug, Clone, PartialEq, strum::Display, strum::EnumString)]
 #[strum(serialize_all = "PascalCase")]
@@ -43,8 +55,14 @@ pub enum StyleField {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StyleContext {
     format: Vec<StyleField>,
+    /// The Format line as read, written back unchanged
+    line: Option<String>,
+    /// Whether the styles were read from an SSA [V4 Styles] section, which
+    /// they are written back as
+    pub(crate) ssa: bool,
 }
 
 impl Default for StyleContext {
@@ -75,18 +93,47 @@ impl Default for StyleContext {
                 StyleField::MarginV,
                 StyleField::Encoding,
             ],
+            line: None,
+            ssa: false,
         }
     }
 }
 
 impl StyleContext {
+    pub fn format_line(&self) -> String {
+        if let Some(line) = &self.line {
+            return line.clone();
+        }
+        let fields: Vec<String> = self.format.iter().map(StyleField::to_string).collect();
+        format!("Format: {}", fields.join(", "))
+    }
+
+    /// Name of the section the styles are written to
+    pub fn section(&self) -> &'static str {
+        if self.ssa {
+            "V4 Styles"
+        } else {
+            "V4+ Styles"
+        }
+    }
+
+    /// Forgets the Format line as read, so it is written with the standard
+    /// `Field, Field` spacing
+    pub(crate) fn normalize_format_line(&mut self) {
+        self.line = None;
+    }
+
     pub fn from_format_line(line: &str) -> anyhow::Result<Self> {
         let (_, fields) = line.split_once(':').context("unable to split on ':'")?;
         let format = fields
             .split(',')
             .map(|x| StyleField::from_str(x.trim()))
             .collect::<Result<_, strum::ParseError>>()?;
-        Ok(Self { format })
+        Ok(Self {
+            format,
+            line: Some(line.trim().to_string()),
+            ssa: false,
+        })
     }
 
     pub fn style_from_line(&self, line: &str) -> anyhow::Result<Style> {
@@ -102,8 +149,8 @@ impl StyleContext {
         if fields.len() != self.format.len() {
             anyhow::bail!(
                 "wrong number of fields, should: {}, actual: {}",
-                fields.len(),
-                self.format.len()
+                self.format.len(),
+                fields.len()
             );
         }
 
@@ -142,7 +189,13 @@ impl StyleContext {
                 }
                 StyleField::Outline => style.outline = Some(field),
                 StyleField::Shadow => style.shadow = Some(field),
-                StyleField::Alignment => style.alignment = Some(Alignment::from_str(&field)?),
+                StyleField::Alignment => {
+                    style.alignment = Some(if self.ssa {
+                        Alignment::from_ssa(&field)
+                    } else {
+                        Alignment::from_str(&field)?
+                    });
+                }
                 StyleField::MarginL => style.margin_l = Some(field),
                 StyleField::MarginR => style.margin_r = Some(field),
                 StyleField::MarginV => style.margin_v = Some(field),
@@ -163,36 +216,10 @@ impl StyleContext {
 
         for field_type in &self.format {
             let s = match field_type {
-                StyleField::Unknown(x) => format!(
-                    "{}",
-                    style
-                        .unknown_fields
-                        .get(x)
-                        .context(format!("unknown fields did not contain field: {x}"))?
-                ),
-                StyleField::Name => format!("{}", style.name),
-                StyleField::Fontname => format!("{}", style.fontname),
-                StyleField::Fontsize => format!("{}", style.fontsize),
-                StyleField::PrimaryColour => format!("{}", style.primary_color),
-                StyleField::SecondaryColour => format!("{}", style.secondary_color),
-                StyleField::OutlineColour => format!("{}", style.outline_color),
-                StyleField::BackColour => format!("{}", style.back_color),
-                StyleField::Bold => format!("{}", style.bold),
-                StyleField::Italic => format!("{}", style.italic),
-                StyleField::Underline => format!("{}", style.underline),
-                StyleField::StrikeOut => format!("{}", style.strike_out),
-                StyleField::ScaleX => format!("{}", style.scale_x),
-                StyleField::ScaleY => format!("{}", style.scale_y),
-                StyleField::Spacing => format!("{}", style.spacing),
-                StyleField::Angle => format!("{}", style.angle),
-                StyleField::BorderStyle => format!("{}", style.border_style),
-                StyleField::Outline => format!("{}", style.outline),
-                StyleField::Shadow => format!("{}", style.shadow),
-                StyleField::Alignment => format!("{}", style.alignment),
-                StyleField::MarginL => format!("{}", style.margin_l),
-                StyleField::MarginR => format!("{}", style.margin_r),
-                StyleField::MarginV => format!("{}", style.margin_v),
-                StyleField::Encoding => format!("{}", style.encoding),
+                StyleField::Alignment if self.ssa => style.alignment.ssa(),
+                _ => style.field(field_type).context(format!(
+                    "unknown fields did not contain field: {field_type}"
+                ))?,
             };
             line.push_str(&s);
             line.push(',');
@@ -233,64 +260,190 @@ pub struct Style {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StyleStrict {
-    unknown_fields: HashMap<String, String>,
-    style_type: StyleType,
-    name: String,
-    fontname: String,
-    fontsize: String,
-    primary_color: String,
-    secondary_color: String,
-    outline_color: String,
-    back_color: String,
-    bold: Boolean,
-    italic: Boolean,
-    underline: Boolean,
-    strike_out: Boolean,
-    scale_x: String,
-    scale_y: String,
-    spacing: String,
-    angle: String,
-    border_style: BorderStyle,
-    outline: String,
-    shadow: String,
-    alignment: Alignment,
-    margin_l: String,
-    margin_r: String,
-    margin_v: String,
-    encoding: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::common::serialize_sorted")
+    )]
+    pub unknown_fields: HashMap<String, String>,
+    pub style_type: StyleType,
+    pub name: String,
+    pub fontname: String,
+    pub fontsize: String,
+    pub primary_color: String,
+    pub secondary_color: String,
+    pub outline_color: String,
+    pub back_color: String,
+    pub bold: Boolean,
+    pub italic: Boolean,
+    pub underline: Boolean,
+    pub strike_out: Boolean,
+    pub scale_x: String,
+    pub scale_y: String,
+    pub spacing: String,
+    pub angle: String,
+    pub border_style: BorderStyle,
+    pub outline: String,
+    pub shadow: String,
+    pub alignment: Alignment,
+    pub margin_l: String,
+    pub margin_r: String,
+    pub margin_v: String,
+    pub encoding: String,
+}
+
+impl StyleStrict {
+    /// Value of a field as written in the Format line, `None` for unknown
+    /// fields the style doesn't have
+    pub fn field(&self, field: &StyleField) -> Option<String> {
+        Some(match field {
+            StyleField::Unknown(x) => return self.unknown_fields.get(x).cloned(),
+            StyleField::Name => self.name.clone(),
+            StyleField::Fontname => self.fontname.clone(),
+            StyleField::Fontsize => self.fontsize.clone(),
+            StyleField::PrimaryColour => self.primary_color.clone(),
+            StyleField::SecondaryColour => self.secondary_color.clone(),
+            StyleField::OutlineColour => self.outline_color.clone(),
+            StyleField::BackColour => self.back_color.clone(),
+            StyleField::Bold => self.bold.to_string(),
+            StyleField::Italic => self.italic.to_string(),
+            StyleField::Underline => self.underline.to_string(),
+            StyleField::StrikeOut => self.strike_out.to_string(),
+            StyleField::ScaleX => self.scale_x.clone(),
+            StyleField::ScaleY => self.scale_y.clone(),
+            StyleField::Spacing => self.spacing.clone(),
+            StyleField::Angle => self.angle.clone(),
+            StyleField::BorderStyle => self.border_style.to_string(),
+            StyleField::Outline => self.outline.clone(),
+            StyleField::Shadow => self.shadow.clone(),
+            StyleField::Alignment => self.alignment.to_string(),
+            StyleField::MarginL => self.margin_l.clone(),
+            StyleField::MarginR => self.margin_r.clone(),
+            StyleField::MarginV => self.margin_v.clone(),
+            StyleField::Encoding => self.encoding.clone(),
+        })
+    }
+
+    /// Every field of the style, the V4+ ones in their usual order followed
+    /// by unknown ones sorted by name
+    pub fn fields(&self) -> Vec<(StyleField, String)> {
+        let mut unknown: Vec<_> = self.unknown_fields.keys().collect();
+        unknown.sort();
+        StyleContext::default()
+            .format
+            .into_iter()
+            .chain(unknown.into_iter().map(|x| StyleField::Unknown(x.clone())))
+            .filter_map(|x| self.field(&x).map(|value| (x, value)))
+            .collect()
+    }
+
+    /// Sets a field by its name in the Format line, such as `Fontsize`.
+    /// Unknown fields can only be set if the style already has them.
+    /// `Alignment` is a numpad position, also for [V4 Styles].
+    pub fn set_field(&mut self, field: &StyleField, value: &str) -> anyhow::Result<()> {
+        let value = value.trim().to_string();
+        match field {
+            StyleField::Unknown(x) => {
+                *self
+                    .unknown_fields
+                    .get_mut(x)
+                    .context(format!("style has no field {x}"))? = value;
+            }
+            StyleField::Name => self.name = value,
+            StyleField::Fontname => self.fontname = value,
+            StyleField::Fontsize => self.fontsize = value,
+            StyleField::PrimaryColour => self.primary_color = value,
+            StyleField::SecondaryColour => self.secondary_color = value,
+            StyleField::OutlineColour => self.outline_color = value,
+            StyleField::BackColour => self.back_color = value,
+            StyleField::Bold => self.bold = Boolean::from_str(&value)?,
+            StyleField::Italic => self.italic = Boolean::from_str(&value)?,
+            StyleField::Underline => self.underline = Boolean::from_str(&value)?,
+            StyleField::StrikeOut => self.strike_out = Boolean::from_str(&value)?,
+            StyleField::ScaleX => self.scale_x = value,
+            StyleField::ScaleY => self.scale_y = value,
+            StyleField::Spacing => self.spacing = value,
+            StyleField::Angle => self.angle = value,
+            StyleField::BorderStyle => self.border_style = BorderStyle::from_str(&value)?,
+            StyleField::Outline => self.outline = value,
+            StyleField::Shadow => self.shadow = value,
+            StyleField::Alignment => self.alignment = Alignment::from_str(&value)?,
+            StyleField::MarginL => self.margin_l = value,
+            StyleField::MarginR => self.margin_r = value,
+            StyleField::MarginV => self.margin_v = value,
+            StyleField::Encoding => self.encoding = value,
+        }
+        Ok(())
+    }
 }
 
+/// The style Aegisub creates for new scripts
+impl Default for StyleStrict {
+    fn default() -> Self {
+        Self {
+            unknown_fields: HashMap::new(),
+            style_type: StyleType::Style,
+            name: "Default".to_string(),
+            fontname: "Arial".to_string(),
+            fontsize: "48".to_string(),
+            primary_color: "&H00FFFFFF".to_string(),
+            secondary_color: "&H000000FF".to_string(),
+            outline_color: "&H00000000".to_string(),
+            back_color: "&H00000000".to_string(),
+            bold: Boolean::False,
+            italic: Boolean::False,
+            underline: Boolean::False,
+            strike_out: Boolean::False,
+            scale_x: "100".to_string(),
+            scale_y: "100".to_string(),
+            spacing: "0".to_string(),
+            angle: "0".to_string(),
+            border_style: BorderStyle::OutlineAndDropShadow,
+            outline: "2".to_string(),
+            shadow: "2".to_string(),
+            alignment: Alignment::BottomCenter,
+            margin_l: "10".to_string(),
+            margin_r: "10".to_string(),
+            margin_v: "10".to_string(),
+            encoding: "1".to_string(),
+        }
+    }
+}
+
+/// Only the name is required, other fields missing from the Format line get
+/// the values of [`StyleStrict::default`]
 impl TryFrom<Style> for StyleStrict {
     type Error = anyhow::Error;
 
     fn try_from(value: Style) -> Result<Self, Self::Error> {
+        let default = Self::default();
         Ok(Self {
             unknown_fields: value.unknown_fields,
             style_type: value.style_type,
             name: value.name.context("Name not found")?,
-            fontname: value.fontname.context("Fontname not found")?,
-            fontsize: value.fontsize.context("Fontsize not found")?,
-            primary_color: value.primary_color.context("PrimaryColor not found")?,
-            secondary_color: value.secondary_color.context("SecondaryColor not found")?,
-            outline_color: value.outline_color.context("OutlineColor not found")?,
-            back_color: value.back_color.context("BackColor not found")?,
-            bold: value.bold.context("Bold not found")?,
-            italic: value.italic.context("Italic not found")?,
-            underline: value.underline.context("Underline not found")?,
-            strike_out: value.strike_out.context("StrikeOut not found")?,
-            scale_x: value.scale_x.context("ScaleX not found")?,
-            scale_y: value.scale_y.context("ScaleY not found")?,
-            spacing: value.spacing.context("Spacing not found")?,
-            angle: value.angle.context("Angle not found")?,
-            border_style: value.border_style.context("BorderStyle not found")?,
-            outline: value.outline.context("Outline not found")?,
-            shadow: value.shadow.context("Shadow not found")?,
-            alignment: value.alignment.context("Alignment not found")?,
-            margin_l: value.margin_l.context("MarginL not found")?,
-            margin_r: value.margin_r.context("MarginR not found")?,
-            margin_v: value.margin_v.context("MarginV not found")?,
-            encoding: value.encoding.context("Encoding not found")?,
+            fontname: value.fontname.unwrap_or(default.fontname),
+            fontsize: value.fontsize.unwrap_or(default.fontsize),
+            primary_color: value.primary_color.unwrap_or(default.primary_color),
+            secondary_color: value.secondary_color.unwrap_or(default.secondary_color),
+            outline_color: value.outline_color.unwrap_or(default.outline_color),
+            back_color: value.back_color.unwrap_or(default.back_color),
+            bold: value.bold.unwrap_or(default.bold),
+            italic: value.italic.unwrap_or(default.italic),
+            underline: value.underline.unwrap_or(default.underline),
+            strike_out: value.strike_out.unwrap_or(default.strike_out),
+            scale_x: value.scale_x.unwrap_or(default.scale_x),
+            scale_y: value.scale_y.unwrap_or(default.scale_y),
+            spacing: value.spacing.unwrap_or(default.spacing),
+            angle: value.angle.unwrap_or(default.angle),
+            border_style: value.border_style.unwrap_or(default.border_style),
+            outline: value.outline.unwrap_or(default.outline),
+            shadow: value.shadow.unwrap_or(default.shadow),
+            alignment: value.alignment.unwrap_or(default.alignment),
+            margin_l: value.margin_l.unwrap_or(default.margin_l),
+            margin_r: value.margin_r.unwrap_or(default.margin_r),
+            margin_v: value.margin_v.unwrap_or(default.margin_v),
+            encoding: value.encoding.unwrap_or(default.encoding),
         })
     }
 }
@@ -338,6 +491,43 @@ pub enum Alignment {
     TopRight,
 }
 
+serde_via_str!(StyleField, StyleType, BorderStyle, Alignment);
+
+impl Alignment {
+    /// Position on a numpad, 1 is bottom left and 9 top right
+    pub fn numpad(&self) -> Option<u8> {
+        match self {
+            Alignment::Unknown(_) => None,
+            x => x.to_string().parse().ok(),
+        }
+    }
+
+    /// Parses the alignment of SSA styles and `\a`, where 1 to 3 are at the
+    /// bottom, 5 to 7 at the top and 9 to 11 in the middle
+    pub fn from_ssa(s: &str) -> Self {
+        let numpad = s.trim().parse::<u8>().ok().and_then(|x| match x {
+            1..=3 => Some(x),
+            5..=7 => Some(x + 2),
+            9..=11 => Some(x - 5),
+            _ => None,
+        });
+        match numpad {
+            Some(x) => Self::from_str(&x.to_string()).unwrap_or(Self::Unknown(s.to_string())),
+            None => Self::Unknown(s.to_string()),
+        }
+    }
+
+    /// The alignment as SSA styles write it
+    pub fn ssa(&self) -> String {
+        match self.numpad() {
+            Some(x @ 1..=3) => x.to_string(),
+            Some(x @ 7..=9) => (x - 2).to_string(),
+            Some(x) => (x + 5).to_string(),
+            None => self.to_string(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -375,10 +565,46 @@ mod tests {
         DEFAULT_STYLE_FORMAT,
         r"Style: zhu2,方正准圆_GBK,33,&H02FFFFFF,&H000000FF,&H00000000,&H00000000,-1,0,0,0,100,100,0,0,1,2,0.1,2,10,10,10,1",
     )]
+    #[case::ssa(
+        "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, TertiaryColour, BackColour, Bold, Italic, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, AlphaLevel, Encoding",
+        r"Style: Default,Arial,20,16777215,65535,65535,-2147483640,-1,0,1,3,0,2,30,30,30,0,0",
+    )]
     fn test_style_lossless(#[case] format: &str, #[case] line_before: &str) {
         let context = StyleContext::from_format_line(format).unwrap();
+        assert_eq!(context.format_line(), format);
         let parsed = context.style_strict_from_line(line_before).unwrap();
         let line_after = context.line_from_style_strict(&parsed).unwrap();
-        assert_eq!(line_after, line_before)
+        assert_eq!(line_after, line_before);
+    }
+
+    #[rstest]
+    #[case("2", Alignment::BottomCenter)]
+    #[case("6", Alignment::TopCenter)]
+    #[case("9", Alignment::MiddleLeft)]
+    #[case("11", Alignment::MiddleRight)]
+    #[case("4", Alignment::Unknown("4".to_string()))]
+    fn test_ssa_alignment(#[case] value: &str, #[case] should: Alignment) {
+        let mut context = StyleContext::from_format_line("Format: Name, Alignment").unwrap();
+        context.ssa = true;
+        let line = format!("Style: Default,{value}");
+        let style = context.style_strict_from_line(&line).unwrap();
+        assert_eq!(style.alignment, should);
+        assert_eq!(context.line_from_style_strict(&style).unwrap(), line);
+    }
+
+    #[test]
+    fn test_set_field() {
+        let mut style = StyleStrict::default();
+        style.set_field(&StyleField::Fontsize, "70").unwrap();
+        style.set_field(&StyleField::Alignment, "8").unwrap();
+        style
+            .set_field(&StyleField::PrimaryColour, "&H0000FFFF")
+            .unwrap();
+        assert_eq!(style.fontsize, "70");
+        assert_eq!(style.alignment, Alignment::TopCenter);
+        assert_eq!(style.primary_color, "&H0000FFFF");
+        assert!(style
+            .set_field(&StyleField::Unknown("AlphaLevel".to_string()), "0")
+            .is_err());
     }
 }
@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+use anyhow::Context;
+
+use crate::style::Styles;
+use crate::tag;
+use crate::AssScript;
+
+/// What happens to the events of a deleted style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrphanEvents {
+    /// Keep them as they are, renderers fall back to `Default`
+    Keep,
+    /// Delete them as well
+    Delete,
+    /// Move them to another style
+    Reassign(String),
+}
+
+/// How the styles of a script are referenced by its events
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StyleUsage {
+    /// Styles no event or `\r` tag uses, in script order
+    pub unused: Vec<String>,
+    /// Styles that are used but not defined, with the indexes of the events
+    /// using them
+    pub missing: BTreeMap<String, Vec<usize>>,
+}
+
+impl Styles {
+    /// Copies the style `name` of `other` as `new_name`, replacing a style
+    /// that already has that name
+    pub fn copy_from(&mut self, other: &Styles, name: &str, new_name: &str) -> anyhow::Result<()> {
+        let mut style = other
+            .find(name)
+            .context(format!("style {name} not found"))?
+            .clone();
+        style.name = new_name.to_string();
+        match self.find_mut(new_name) {
+            Some(x) => *x = style,
+            None => self.entries.push(style),
+        }
+        Ok(())
+    }
+}
+
+/// Rewrites `\r<from>` tags in event text to `\r<to>`
+fn replace_reset(text: &str, from: &str, to: &str) -> String {
+    tag::rewrite_tags(text, &mut |tag| {
+        (tag.name == "r" && tag.args == from).then(|| format!("\\r{to}"))
+    })
+}
+
+/// Styles switched to by `\r` tags in event text
+fn reset_styles(text: &str) -> Vec<&str> {
+    tag::split_text(text)
+        .into_iter()
+        .filter_map(|x| match x {
+            tag::TextPart::Override(block) => Some(tag::parse_tags(block)),
+            tag::TextPart::Text(_) => None,
+        })
+        .flatten()
+        .filter(|x| x.name == "r" && !x.args.is_empty())
+        .map(|x| x.args)
+        .collect()
+}
+
+impl AssScript {
+    /// Cross-references the styles of events and `\r` tags with the defined
+    /// ones. Comments count as uses, so styles kept for reference aren't
+    /// reported as unused.
+    pub fn style_usage(&self) -> StyleUsage {
+        let mut used = HashSet::new();
+        let mut missing: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (i, event) in self.events.entries.iter().enumerate() {
+            let mut names = reset_styles(&event.text);
+            names.push(&event.style);
+            for name in names {
+                used.insert(name);
+                if self.styles.find(name).is_none() {
+                    let events = missing.entry(name.to_string()).or_default();
+                    if events.last() != Some(&i) {
+                        events.push(i);
+                    }
+                }
+            }
+        }
+        StyleUsage {
+            unused: self
+                .styles
+                .entries
+                .iter()
+                .filter(|x| !used.contains(x.name.as_str()))
+                .map(|x| x.name.clone())
+                .collect(),
+            missing,
+        }
+    }
+
+    /// Removes the styles no event uses, returning their names. `Default`
+    /// is kept while events use missing styles, as renderers fall back to it.
+    pub fn prune_styles(&mut self) -> Vec<String> {
+        let usage = self.style_usage();
+        let mut unused = usage.unused;
+        if !usage.missing.is_empty() {
+            unused.retain(|x| x != "Default");
+        }
+        self.styles.entries.retain(|x| !unused.contains(&x.name));
+        unused
+    }
+
+    /// Renames a style, along with the events and `\r` tags using it
+    pub fn rename_style(&mut self, name: &str, new_name: &str) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.styles.find(new_name).is_none(),
+            "style {new_name} already exists"
+        );
+        let style = self
+            .styles
+            .find_mut(name)
+            .context(format!("style {name} not found"))?;
+        style.name = new_name.to_string();
+
+        for event in &mut self.events.entries {
+            if event.style == name {
+                event.style = new_name.to_string();
+            }
+            event.text = replace_reset(&event.text, name, new_name);
+        }
+        Ok(())
+    }
+
+    /// Deletes a style, handling the events and `\r` tags using it as
+    /// `events` says. Unless kept, `\r` tags switching to the style reset to
+    /// the event's own style or switch to the new one instead.
+    pub fn delete_style(&mut self, name: &str, events: &OrphanEvents) -> anyhow::Result<()> {
+        let index = self
+            .styles
+            .entries
+            .iter()
+            .position(|x| x.name == name)
+            .context(format!("style {name} not found"))?;
+        if let OrphanEvents::Reassign(x) = events {
+            anyhow::ensure!(x != name, "can't reassign events to the deleted style");
+            anyhow::ensure!(self.styles.find(x).is_some(), "style {x} not found");
+        }
+        self.styles.entries.remove(index);
+
+        match events {
+            OrphanEvents::Keep => {}
+            OrphanEvents::Delete => {
+                self.events.entries.retain(|x| x.style != name);
+                for event in &mut self.events.entries {
+                    event.text = replace_reset(&event.text, name, "");
+                }
+            }
+            OrphanEvents::Reassign(new_name) => {
+                for event in &mut self.events.entries {
+                    if event.style == name {
+                        event.style.clone_from(new_name);
+                    }
+                    event.text = replace_reset(&event.text, name, new_name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::event::EventStrict;
+    use crate::style::StyleStrict;
+    use crate::time::AssTime;
+
+    fn script() -> AssScript {
+        let mut script = AssScript::new();
+        for name in ["Default", "Sign"] {
+            script.styles.entries.push(StyleStrict {
+                name: name.to_string(),
+                ..Default::default()
+            });
+        }
+        script.events.entries = vec![
+            EventStrict::new(
+                AssTime(0),
+                AssTime(1000),
+                "Default",
+                r"a{\rSign}b{\rSigns}c",
+            ),
+            EventStrict::new(AssTime(0), AssTime(1000), "Sign", "sign"),
+        ];
+        script
+    }
+
+    #[test]
+    fn test_copy_from() {
+        let source = script();
+        let mut target = AssScript::new();
+        target
+            .styles
+            .copy_from(&source.styles, "Default", "DefaultEN")
+            .unwrap();
+        target
+            .styles
+            .copy_from(&source.styles, "Sign", "DefaultEN")
+            .unwrap();
+        assert_eq!(target.styles.entries.len(), 1);
+        assert_eq!(target.styles.entries[0].name, "DefaultEN");
+        assert!(target
+            .styles
+            .copy_from(&source.styles, "Missing", "X")
+            .is_err());
+    }
+
+    #[test]
+    fn test_rename_style() {
+        let mut script = script();
+        script.rename_style("Sign", "Signs2").unwrap();
+        assert_eq!(script.styles.entries[1].name, "Signs2");
+        assert_eq!(script.events.entries[0].text, r"a{\rSigns2}b{\rSigns}c");
+        assert_eq!(script.events.entries[1].style, "Signs2");
+        assert!(script.rename_style("Default", "Signs2").is_err());
+    }
+
+    #[test]
+    fn test_style_usage() {
+        let mut script = script();
+        script.styles.entries.push(StyleStrict {
+            name: "Eyecatch".to_string(),
+            ..Default::default()
+        });
+        script.events.entries.push(EventStrict::new(
+            AssTime(0),
+            AssTime(1000),
+            "Typo",
+            r"{\rSigns}a{\r}",
+        ));
+
+        let usage = script.style_usage();
+        assert_eq!(usage.unused, vec!["Eyecatch"]);
+        assert_eq!(
+            usage.missing,
+            BTreeMap::from([
+                ("Signs".to_string(), vec![0, 2]),
+                ("Typo".to_string(), vec![2])
+            ])
+        );
+
+        assert_eq!(script.prune_styles(), vec!["Eyecatch"]);
+        assert_eq!(script.styles.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_prune_keeps_default() {
+        let mut script = script();
+        script.events.entries[0].style = "Typo".to_string();
+        // events in the missing style are drawn with Default
+        assert!(script.prune_styles().is_empty());
+        script.events.entries[0].style = "Sign".to_string();
+        script.events.entries[0].text = "a".to_string();
+        assert_eq!(script.prune_styles(), vec!["Default"]);
+    }
+
+    #[rstest]
+    #[case(OrphanEvents::Keep, 2, r"a{\rSign}b{\rSigns}c", "Sign")]
+    #[case(OrphanEvents::Delete, 1, r"a{\r}b{\rSigns}c", "")]
+    #[case(OrphanEvents::Reassign("Default".to_string()), 2, r"a{\rDefault}b{\rSigns}c", "Default")]
+    fn test_delete_style(
+        #[case] events: OrphanEvents,
+        #[case] count: usize,
+        #[case] text: &str,
+        #[case] style: &str,
+    ) {
+        let mut script = script();
+        script.delete_style("Sign", &events).unwrap();
+        assert_eq!(script.styles.entries.len(), 1);
+        assert_eq!(script.events.entries.len(), count);
+        assert_eq!(script.events.entries[0].text, text);
+        if count > 1 {
+            assert_eq!(script.events.entries[1].style, style);
+        }
+    }
+}
@@ -0,0 +1,1299 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use anyhow::Context;
+
+use crate::attachment;
+use crate::attachment::Attachment;
+use crate::font::FontDatabase;
+use crate::font::FontFace;
+use crate::font::FontSource;
+use crate::font::FontUsage;
+use crate::tag;
+use crate::AssScript;
+
+/// Name records replaced when a font is renamed: family, unique ID, full
+/// name, PostScript name and typographic family
+const RENAMED_NAME_IDS: [u16; 5] = [1, 3, 4, 6, 16];
+
+/// Tables whose contents are invalidated by subsetting
+const DROPPED_TABLES: [&[u8; 4]; 1] = [b"DSIG"];
+
+/// Subsets a font to the glyphs for `chars`, optionally renaming its family.
+///
+/// Unused glyphs are emptied rather than removed, so glyph IDs stay stable and
+/// tables referencing them (metrics, GSUB, GPOS, ...) remain valid. Glyphs GSUB
+/// can substitute for kept ones are kept too, such as vertical forms and
+/// ligatures, as are the components of composite glyphs. Both TrueType (glyf)
+/// and CFF outlines are supported. A face from a collection is written as a
+/// standalone font.
+pub fn subset_font(
+    data: &[u8],
+    index: u32,
+    chars: &BTreeSet<char>,
+    rename: Option<&str>,
+) -> anyhow::Result<Vec<u8>> {
+    Subset::new(data, index, chars)?.write(rename)
+}
+
+/// The tables of a subset font, before it is renamed and written out
+struct Subset {
+    sfnt_version: u32,
+    tables: BTreeMap<[u8; 4], Vec<u8>>,
+}
+
+impl Subset {
+    fn new(data: &[u8], index: u32, chars: &BTreeSet<char>) -> anyhow::Result<Self> {
+        let face = ttf_parser::Face::parse(data, index)?;
+        let tables = read_tables(data, index)?;
+        let table = |tag: &[u8; 4]| tables.get(tag).copied();
+        let head = table(b"head").context("font has no head table")?;
+        anyhow::ensure!(head.len() >= 54, "unexpected end of font data");
+        let maxp = table(b"maxp").context("font has no maxp table")?;
+        let num_glyphs = read_u16(maxp, 4)?;
+
+        let mut cmap = BTreeMap::new();
+        for c in chars {
+            if let Some(gid) = face.glyph_index(*c) {
+                cmap.insert(*c, gid.0);
+            }
+        }
+        let mut keep: BTreeSet<u16> = cmap.values().copied().collect();
+        keep.insert(0);
+        gsub_closure(&face, &mut keep);
+
+        let mut out: BTreeMap<[u8; 4], Vec<u8>> = BTreeMap::new();
+        for (tag, data) in &tables {
+            if !DROPPED_TABLES.contains(&tag) {
+                out.insert(*tag, data.to_vec());
+            }
+        }
+        let mut new_head = head.to_vec();
+        new_head[8..12].copy_from_slice(&[0; 4]);
+
+        if let (Some(loca), Some(glyf)) = (table(b"loca"), table(b"glyf")) {
+            let long_loca = read_u16(head, 50)? == 1;
+            let (new_glyf, new_loca) = subset_glyf(loca, glyf, long_loca, num_glyphs, &mut keep)?;
+            new_head[50..52].copy_from_slice(&1u16.to_be_bytes());
+            out.insert(*b"loca", new_loca);
+            out.insert(*b"glyf", new_glyf);
+        } else if let Some(cff) = table(b"CFF ") {
+            out.insert(*b"CFF ", subset_cff(cff, num_glyphs, &keep)?);
+        } else if table(b"CFF2").is_some() {
+            anyhow::bail!("CFF2 outlines can't be subset");
+        } else {
+            anyhow::bail!("font has no glyf or CFF outlines");
+        }
+        out.insert(*b"head", new_head);
+        out.insert(*b"cmap", build_cmap(&cmap)?);
+
+        Ok(Self {
+            sfnt_version: read_u32(font_offset_table(data, index)?, 0)?,
+            tables: out,
+        })
+    }
+
+    fn write(mut self, rename: Option<&str>) -> anyhow::Result<Vec<u8>> {
+        if let Some(rename) = rename {
+            if let Some(name) = self.tables.get(b"name") {
+                let name = rename_name_table(name, rename)?;
+                self.tables.insert(*b"name", name);
+            }
+        }
+        write_font(self.sfnt_version, &self.tables)
+    }
+}
+
+/// Adds the glyphs GSUB can substitute for kept glyphs, repeating until no
+/// more are found. Every lookup is followed whatever its feature, script or
+/// context, so this keeps more than shaping can produce but never less.
+/// Contextual lookups only apply other lookups, which are followed anyway.
+fn gsub_closure(face: &ttf_parser::Face, keep: &mut BTreeSet<u16>) {
+    use ttf_parser::gsub::SingleSubstitution;
+    use ttf_parser::gsub::SubstitutionSubtable;
+
+    let Some(gsub) = face.tables().gsub else {
+        return;
+    };
+    loop {
+        let mut found = Vec::new();
+        for lookup in gsub.lookups {
+            for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+                let coverage = subtable.coverage();
+                for &gid in keep.iter() {
+                    let Some(i) = coverage.get(ttf_parser::GlyphId(gid)) else {
+                        continue;
+                    };
+                    match &subtable {
+                        SubstitutionSubtable::Single(SingleSubstitution::Format1 {
+                            delta, ..
+                        }) => found.push(gid.wrapping_add_signed(*delta)),
+                        SubstitutionSubtable::Single(SingleSubstitution::Format2 {
+                            substitutes,
+                            ..
+                        }) => found.extend(substitutes.get(i).map(|x| x.0)),
+                        SubstitutionSubtable::Multiple(x) => {
+                            if let Some(sequence) = x.sequences.get(i) {
+                                found.extend(sequence.substitutes.into_iter().map(|x| x.0));
+                            }
+                        }
+                        SubstitutionSubtable::Alternate(x) => {
+                            if let Some(set) = x.alternate_sets.get(i) {
+                                found.extend(set.alternates.into_iter().map(|x| x.0));
+                            }
+                        }
+                        SubstitutionSubtable::Ligature(x) => {
+                            for ligature in x.ligature_sets.get(i).into_iter().flatten() {
+                                if ligature.components.into_iter().all(|x| keep.contains(&x.0)) {
+                                    found.push(ligature.glyph.0);
+                                }
+                            }
+                        }
+                        SubstitutionSubtable::ReverseChainSingle(x) => {
+                            found.extend(x.substitutes.get(i).map(|x| x.0));
+                        }
+                        SubstitutionSubtable::Context(_)
+                        | SubstitutionSubtable::ChainContext(_) => {}
+                    }
+                }
+            }
+        }
+        let before = keep.len();
+        keep.extend(found);
+        if keep.len() == before {
+            break;
+        }
+    }
+}
+
+/// Empties the glyf entries of glyphs that aren't kept, after adding the
+/// components of kept composite glyphs. Returns the new glyf and long loca.
+fn subset_glyf(
+    loca: &[u8],
+    glyf: &[u8],
+    long_loca: bool,
+    num_glyphs: u16,
+    keep: &mut BTreeSet<u16>,
+) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let glyph_range = |gid: u16| -> anyhow::Result<(usize, usize)> {
+        let gid = usize::from(gid);
+        let (start, end) = if long_loca {
+            (read_u32(loca, gid * 4)?, read_u32(loca, gid * 4 + 4)?)
+        } else {
+            (
+                u32::from(read_u16(loca, gid * 2)?) * 2,
+                u32::from(read_u16(loca, gid * 2 + 2)?) * 2,
+            )
+        };
+        let range = (usize::try_from(start)?, usize::try_from(end)?);
+        anyhow::ensure!(
+            range.0 <= range.1 && range.1 <= glyf.len(),
+            "invalid loca entry for glyph {gid}"
+        );
+        Ok(range)
+    };
+
+    let mut pending: Vec<u16> = keep.iter().copied().collect();
+    while let Some(gid) = pending.pop() {
+        if gid >= num_glyphs {
+            continue;
+        }
+        let (start, end) = glyph_range(gid)?;
+        for component in composite_components(&glyf[start..end])? {
+            if component < num_glyphs && keep.insert(component) {
+                pending.push(component);
+            }
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((usize::from(num_glyphs) + 1) * 4);
+    for gid in 0..num_glyphs {
+        new_loca.extend_from_slice(&u32::try_from(new_glyf.len())?.to_be_bytes());
+        if keep.contains(&gid) {
+            let (start, end) = glyph_range(gid)?;
+            new_glyf.extend_from_slice(&glyf[start..end]);
+            while new_glyf.len() % 4 != 0 {
+                new_glyf.push(0);
+            }
+        }
+    }
+    new_loca.extend_from_slice(&u32::try_from(new_glyf.len())?.to_be_bytes());
+    Ok((new_glyf, new_loca))
+}
+
+/// A subset font ready to be written out or attached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubsetFont {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+/// A face with the family names requests found it by and the characters
+/// they use
+type FaceRequests<'a> = (&'a FontFace, Vec<String>, BTreeSet<char>);
+
+/// Subsets every font used by the script that can be found in the database.
+/// With `rename`, families are given names from [`subset_name`] and the
+/// script's references are rewritten to match. Fonts embedded in the script's
+/// [Fonts] section are replaced there by their subsets, the others are
+/// returned. Fails if any font can't be subset, rather than shipping it whole.
+pub fn subset_script(
+    script: &mut AssScript,
+    db: &FontDatabase,
+    rename: bool,
+) -> anyhow::Result<Vec<SubsetFont>> {
+    let usage = FontUsage::from_script(script);
+
+    // merge the characters of requests resolving to the same face, keeping
+    // every family name they use so all of them are renamed
+    let mut faces: BTreeMap<(String, u32), FaceRequests> = BTreeMap::new();
+    for (request, chars) in &usage.fonts {
+        let Some(face) = db.find(request) else {
+            continue;
+        };
+        let (_, families, merged) = faces
+            .entry((face.source.to_string(), face.index))
+            .or_insert_with(|| (face, Vec::new(), BTreeSet::new()));
+        let family = request.lookup_name().to_string();
+        if !families.contains(&family) {
+            families.push(family);
+        }
+        merged.extend(chars);
+    }
+
+    let mut names: BTreeMap<String, String> = BTreeMap::new();
+    let mut subsets = Vec::new();
+    for (face, families, chars) in faces.values() {
+        let subset = Subset::new(face.data(), face.index, chars)
+            .with_context(|| format!("failed to subset {}", face.source))?;
+        let family = &families[0];
+        if rename {
+            let renamed = names
+                .entry(family.clone())
+                .or_insert_with(|| subset_name(family))
+                .clone();
+            for alias in &families[1..] {
+                names
+                    .entry(alias.clone())
+                    .or_insert_with(|| renamed.clone());
+            }
+        }
+        subsets.push((*face, family, subset));
+    }
+
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    let mut fonts = Vec::new();
+    let mut embedded = Vec::new();
+    for (face, family, subset) in subsets {
+        let renamed = names.get(family).map(String::as_str);
+        let data = subset.write(renamed)?;
+
+        let base: String = renamed
+            .unwrap_or(family)
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == ' ' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let extension = match data.get(..4) {
+            Some(b"OTTO") => "otf",
+            Some(b"ttcf") => "ttc",
+            _ => "ttf",
+        };
+        let count = counts.entry(base.clone()).or_default();
+        let font = SubsetFont {
+            filename: format!("{base}_{count}.{extension}"),
+            data,
+        };
+        *count += 1;
+        match &face.source {
+            FontSource::Embedded(name) => embedded.push((name.clone(), font)),
+            _ => fonts.push(font),
+        }
+    }
+
+    if !embedded.is_empty() {
+        replace_embedded(script, &embedded)?;
+    }
+    rename_fonts(script, &names);
+    Ok(fonts)
+}
+
+/// Swaps fonts in the script's [Fonts] section for their subsets, given by
+/// the name of the file they replace
+fn replace_embedded(script: &mut AssScript, fonts: &[(String, SubsetFont)]) -> anyhow::Result<()> {
+    let lines = script
+        .other_sections
+        .get_mut("Fonts")
+        .context("script has no [Fonts] section")?;
+    let mut attachments = attachment::decode_embedded(lines)?;
+    attachments.retain(|x| !fonts.iter().any(|(name, _)| *name == x.name));
+    attachments.extend(fonts.iter().map(|(_, x)| Attachment {
+        name: x.filename.clone(),
+        data: x.data.clone(),
+    }));
+    *lines = attachments
+        .iter()
+        .flat_map(attachment::encode_embedded)
+        .collect();
+    Ok(())
+}
+
+/// A stable, random looking family name for a font, in the style of the
+/// names `AssFontSubset` generates
+pub fn subset_name(family: &str) -> String {
+    // FNV-1a, so the same family always gets the same name across runs
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in family.to_lowercase().bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    let alphabet = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    (0..8)
+        .map(|i| char::from(alphabet[usize::try_from((hash >> (i * 6)) % 36).unwrap_or(0)]))
+        .collect()
+}
+
+/// Replaces font names in style Fontname fields and `\fn` tags. Keys of
+/// `names` are matched ignoring case, a leading `@` is kept.
+pub fn rename_fonts(script: &mut AssScript, names: &BTreeMap<String, String>) {
+    let lookup = |family: &str| -> Option<String> {
+        let (prefix, name) = match family.strip_prefix('@') {
+            Some(x) => ("@", x),
+            None => ("", family),
+        };
+        names
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| format!("{prefix}{v}"))
+    };
+
+    for style in &mut script.styles.entries {
+        if let Some(x) = lookup(&style.fontname) {
+            style.fontname = x;
+        }
+    }
+    for event in &mut script.events.entries {
+        event.text = tag::rewrite_tags(&event.text, &mut |tag| {
+            if tag.name != "fn" {
+                return None;
+            }
+            lookup(tag.args).map(|x| format!(r"\fn{x}"))
+        });
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .context("unexpected end of font data")?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("unexpected end of font data")?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// The offset table of a face, resolving collections
+fn font_offset_table(data: &[u8], index: u32) -> anyhow::Result<&[u8]> {
+    if data.starts_with(b"ttcf") {
+        let count = read_u32(data, 8)?;
+        anyhow::ensure!(index < count, "face index {index} out of range");
+        let offset = read_u32(data, 12 + usize::try_from(index)? * 4)?;
+        return data
+            .get(usize::try_from(offset)?..)
+            .context("invalid collection offset");
+    }
+    Ok(data)
+}
+
+fn read_tables(data: &[u8], index: u32) -> anyhow::Result<BTreeMap<[u8; 4], &[u8]>> {
+    let header = font_offset_table(data, index)?;
+    let count = read_u16(header, 4)?;
+    let mut tables = BTreeMap::new();
+    for i in 0..usize::from(count) {
+        let record = header
+            .get(12 + i * 16..28 + i * 16)
+            .context("truncated table directory")?;
+        let tag = [record[0], record[1], record[2], record[3]];
+        // offsets are relative to the start of the file, even in collections
+        let offset = usize::try_from(read_u32(record, 8)?)?;
+        let length = usize::try_from(read_u32(record, 12)?)?;
+        let table = data
+            .get(offset..offset + length)
+            .context("table extends past end of font")?;
+        tables.insert(tag, table);
+    }
+    Ok(tables)
+}
+
+fn composite_components(glyph: &[u8]) -> anyhow::Result<Vec<u16>> {
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    // empty glyphs and simple glyphs (non-negative contour count) have none
+    if glyph.len() < 10 || read_u16(glyph, 0)? & 0x8000 == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut components = Vec::new();
+    let mut offset = 10;
+    loop {
+        let flags = read_u16(glyph, offset)?;
+        components.push(read_u16(glyph, offset + 2)?);
+        offset += 4;
+        offset += if flags & ARG_1_AND_2_ARE_WORDS == 0 {
+            2
+        } else {
+            4
+        };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            offset += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            offset += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            offset += 8;
+        }
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    Ok(components)
+}
+
+/// Builds a cmap with a format 4 subtable for the BMP and a format 12
+/// subtable covering every character
+fn build_cmap(cmap: &BTreeMap<char, u16>) -> anyhow::Result<Vec<u8>> {
+    let mut format4 = Vec::new();
+    {
+        // one segment per run of consecutive characters and glyphs
+        let mut segments: Vec<(u16, u16, u16)> = Vec::new();
+        for (c, gid) in cmap {
+            let Ok(c) = u16::try_from(u32::from(*c)) else {
+                continue;
+            };
+            if c == 0xffff {
+                continue;
+            }
+            match segments.last_mut() {
+                Some((start, end, start_gid))
+                    if *end + 1 == c
+                        && u32::from(*start_gid) + u32::from(c - *start) == u32::from(*gid) =>
+                {
+                    *end = c;
+                }
+                _ => segments.push((c, c, *gid)),
+            }
+        }
+        segments.push((0xffff, 0xffff, 0));
+
+        let seg_count = u16::try_from(segments.len()).context("too many cmap segments")?;
+        let entry_selector = seg_count.ilog2();
+        let search_range = 2u16 << entry_selector;
+        let length = 16 + 8 * usize::from(seg_count);
+
+        let mut push = |x: u16| format4.extend_from_slice(&x.to_be_bytes());
+        push(4);
+        push(u16::try_from(length).context("too many cmap segments")?);
+        push(0);
+        push(seg_count * 2);
+        push(search_range);
+        push(u16::try_from(entry_selector)?);
+        push(seg_count * 2 - search_range);
+        for (_, end, _) in &segments {
+            push(*end);
+        }
+        push(0);
+        for (start, _, _) in &segments {
+            push(*start);
+        }
+        for (start, _, gid) in &segments {
+            push(gid.wrapping_sub(*start));
+        }
+        for _ in &segments {
+            push(0);
+        }
+    }
+
+    let mut format12 = Vec::new();
+    {
+        let mut groups: Vec<(u32, u32, u32)> = Vec::new();
+        for (c, gid) in cmap {
+            let (c, gid) = (u32::from(*c), u32::from(*gid));
+            match groups.last_mut() {
+                Some((start, end, start_gid))
+                    if *end + 1 == c && *start_gid + (c - *start) == gid =>
+                {
+                    *end = c;
+                }
+                _ => groups.push((c, c, gid)),
+            }
+        }
+        let length = u32::try_from(16 + 12 * groups.len())?;
+        format12.extend_from_slice(&12u16.to_be_bytes());
+        format12.extend_from_slice(&0u16.to_be_bytes());
+        format12.extend_from_slice(&length.to_be_bytes());
+        format12.extend_from_slice(&0u32.to_be_bytes());
+        format12.extend_from_slice(&u32::try_from(groups.len())?.to_be_bytes());
+        for (start, end, gid) in groups {
+            format12.extend_from_slice(&start.to_be_bytes());
+            format12.extend_from_slice(&end.to_be_bytes());
+            format12.extend_from_slice(&gid.to_be_bytes());
+        }
+    }
+
+    let mut table = Vec::new();
+    table.extend_from_slice(&0u16.to_be_bytes());
+    table.extend_from_slice(&2u16.to_be_bytes());
+    // Windows Unicode BMP and full repertoire encodings
+    let format4_offset = 4 + 2 * 8u32;
+    let format12_offset = format4_offset + u32::try_from(format4.len())?;
+    for (encoding, offset) in [(1u16, format4_offset), (10, format12_offset)] {
+        table.extend_from_slice(&3u16.to_be_bytes());
+        table.extend_from_slice(&encoding.to_be_bytes());
+        table.extend_from_slice(&offset.to_be_bytes());
+    }
+    table.extend_from_slice(&format4);
+    table.extend_from_slice(&format12);
+    Ok(table)
+}
+
+/// Rewrites the family related name records to `rename`. Records in Mac
+/// encodings can only hold ASCII, so they are dropped for other names.
+fn rename_name_table(name: &[u8], rename: &str) -> anyhow::Result<Vec<u8>> {
+    let count = read_u16(name, 2)?;
+    let storage = usize::from(read_u16(name, 4)?);
+
+    let mut records = Vec::new();
+    for i in 0..usize::from(count) {
+        let offset = 6 + i * 12;
+        let platform = read_u16(name, offset)?;
+        let encoding = read_u16(name, offset + 2)?;
+        let language = read_u16(name, offset + 4)?;
+        let name_id = read_u16(name, offset + 6)?;
+        let length = usize::from(read_u16(name, offset + 8)?);
+        let string_offset = storage + usize::from(read_u16(name, offset + 10)?);
+
+        // language tag references of format 1 tables are not carried over
+        if language >= 0x8000 {
+            continue;
+        }
+
+        let mut string = name
+            .get(string_offset..string_offset + length)
+            .context("name record extends past end of table")?
+            .to_vec();
+        if RENAMED_NAME_IDS.contains(&name_id) {
+            // PostScript names may not contain spaces
+            let value = if name_id == 6 {
+                rename.replace(' ', "")
+            } else {
+                rename.to_string()
+            };
+            string = match platform {
+                0 | 3 => value.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+                1 if value.is_ascii() => value.into_bytes(),
+                _ => continue,
+            };
+        }
+        records.push((platform, encoding, language, name_id, string));
+    }
+
+    let count = u16::try_from(records.len())?;
+    let storage = 6 + 12 * count;
+    let mut header = Vec::new();
+    let mut strings = Vec::new();
+    header.extend_from_slice(&0u16.to_be_bytes());
+    header.extend_from_slice(&count.to_be_bytes());
+    header.extend_from_slice(&storage.to_be_bytes());
+    for (platform, encoding, language, name_id, string) in records {
+        for x in [
+            platform,
+            encoding,
+            language,
+            name_id,
+            u16::try_from(string.len())?,
+            u16::try_from(strings.len()).context("name table too large")?,
+        ] {
+            header.extend_from_slice(&x.to_be_bytes());
+        }
+        strings.extend_from_slice(&string);
+    }
+    header.extend_from_slice(&strings);
+    Ok(header)
+}
+
+/// CFF DICT operators whose operands are offsets into the CFF table
+const CFF_CHARSET: u16 = 15;
+const CFF_ENCODING: u16 = 16;
+const CFF_CHARSTRINGS: u16 = 17;
+const CFF_PRIVATE: u16 = 18;
+const CFF_SUBRS: u16 = 19;
+const CFF_FDARRAY: u16 = 0x0c24;
+const CFF_FDSELECT: u16 = 0x0c25;
+
+/// The Type 2 charstring of an empty glyph: `endchar`
+const CFF_EMPTY_GLYPH: &[u8] = &[14];
+
+/// Rebuilds a CFF table with the charstrings of glyphs that aren't kept
+/// replaced by empty ones. Global and local subroutines are kept whole.
+fn subset_cff(cff: &[u8], num_glyphs: u16, keep: &BTreeSet<u16>) -> anyhow::Result<Vec<u8>> {
+    let header_size = usize::from(*cff.get(2).context("truncated CFF header")?);
+    let header = cff.get(..header_size).context("truncated CFF header")?;
+    let (_, name_end) = cff_index(cff, header_size)?;
+    let (top_dicts, top_end) = cff_index(cff, name_end)?;
+    let (_, strings_end) = cff_index(cff, top_end)?;
+    let (_, gsubrs_end) = cff_index(cff, strings_end)?;
+    let [top_dict] = top_dicts[..] else {
+        anyhow::bail!("CFF table has {} fonts, expected one", top_dicts.len());
+    };
+    let top = parse_dict(top_dict)?;
+    let offset = |operator: u16| dict_offset(&top, operator);
+
+    let charstrings_offset = offset(CFF_CHARSTRINGS)?.context("CFF font has no charstrings")?;
+    let (charstrings, _) = cff_index(cff, charstrings_offset)?;
+    anyhow::ensure!(
+        charstrings.len() == usize::from(num_glyphs),
+        "CFF font has {} charstrings for {num_glyphs} glyphs",
+        charstrings.len()
+    );
+    let charstrings: Vec<&[u8]> = (0u16..)
+        .zip(charstrings)
+        .map(|(gid, x)| {
+            if keep.contains(&gid) {
+                x
+            } else {
+                CFF_EMPTY_GLYPH
+            }
+        })
+        .collect();
+    let charstrings = write_cff_index(&charstrings)?;
+
+    let [charset, encoding, fd_select] = copied_blocks(cff, &top, num_glyphs)?;
+
+    // CID fonts keep a private DICT per font DICT, others a single one
+    let mut font_dicts = Vec::new();
+    let mut privates = Vec::new();
+    if let Some(start) = offset(CFF_FDARRAY)? {
+        for dict in cff_index(cff, start)?.0 {
+            let dict = parse_dict(dict)?;
+            privates.push(private_block(cff, &dict)?);
+            font_dicts.push(dict);
+        }
+    } else {
+        privates.push(private_block(cff, &top)?);
+    }
+
+    // offset operands are written in a fixed size, so the layout can be
+    // computed before the offsets are known
+    let layout = |places: &CffPlaces| -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+        let top = write_dict(&top, |operator| match operator {
+            CFF_CHARSET => places.charset.map(|x| vec![x]),
+            CFF_ENCODING => places.encoding.map(|x| vec![x]),
+            CFF_CHARSTRINGS => Some(vec![places.charstrings]),
+            CFF_FDSELECT => places.fd_select.map(|x| vec![x]),
+            CFF_FDARRAY => Some(vec![places.fd_array]),
+            CFF_PRIVATE => places.privates.first().map(|x| vec![x.0, x.1]),
+            _ => None,
+        })?;
+        let fonts = font_dicts
+            .iter()
+            .zip(&places.privates)
+            .map(|(dict, private)| {
+                write_dict(dict, |operator| {
+                    (operator == CFF_PRIVATE).then(|| vec![private.0, private.1])
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let fonts: Vec<&[u8]> = fonts.iter().map(Vec::as_slice).collect();
+        Ok((write_cff_index(&[&top])?, write_cff_index(&fonts)?))
+    };
+    let (top_index, fd_array) = layout(&CffPlaces::default())?;
+
+    let mut end = header.len() + name_end - header_size + top_index.len() + gsubrs_end - top_end;
+    let mut place = |len: usize| -> anyhow::Result<i32> {
+        let at = i32::try_from(end)?;
+        end += len;
+        Ok(at)
+    };
+    let places = CffPlaces {
+        charset: charset.map(|x| place(x.len())).transpose()?,
+        encoding: encoding.map(|x| place(x.len())).transpose()?,
+        fd_select: fd_select.map(|x| place(x.len())).transpose()?,
+        charstrings: place(charstrings.len())?,
+        fd_array: place(fd_array.len())?,
+        privates: privates
+            .iter()
+            .map(|x| Ok((i32::try_from(x.size)?, place(x.data.len())?)))
+            .collect::<anyhow::Result<_>>()?,
+    };
+    let (top_index, fd_array) = layout(&places)?;
+
+    let mut out = Vec::with_capacity(end);
+    out.extend_from_slice(header);
+    out.extend_from_slice(&cff[header_size..name_end]);
+    out.extend_from_slice(&top_index);
+    out.extend_from_slice(&cff[top_end..gsubrs_end]);
+    for block in [charset, encoding, fd_select].into_iter().flatten() {
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&charstrings);
+    if !font_dicts.is_empty() {
+        out.extend_from_slice(&fd_array);
+    }
+    for private in &privates {
+        out.extend_from_slice(private.data);
+    }
+    Ok(out)
+}
+
+/// The blocks of a CFF font copied unchanged: charset, encoding and `FDSelect`
+fn copied_blocks<'a>(
+    cff: &'a [u8],
+    top: &[DictEntry],
+    num_glyphs: u16,
+) -> anyhow::Result<[Option<&'a [u8]>; 3]> {
+    let offset = |operator: u16| dict_offset(top, operator);
+    // the predefined charsets and encodings have offsets 0 to 2 and no data
+    let charset = match offset(CFF_CHARSET)? {
+        Some(start) if start > 2 => {
+            Some(cff_block(cff, start, charset_len(cff, start, num_glyphs)?)?)
+        }
+        _ => None,
+    };
+    let encoding = match offset(CFF_ENCODING)? {
+        Some(start) if start > 1 => Some(cff_block(cff, start, encoding_len(cff, start)?)?),
+        _ => None,
+    };
+    let fd_select = match offset(CFF_FDSELECT)? {
+        Some(start) => Some(cff_block(
+            cff,
+            start,
+            fd_select_len(cff, start, num_glyphs)?,
+        )?),
+        None => None,
+    };
+
+    Ok([charset, encoding, fd_select])
+}
+
+/// Where the blocks of a rebuilt CFF table start, privates also have a size
+#[derive(Default)]
+struct CffPlaces {
+    charset: Option<i32>,
+    encoding: Option<i32>,
+    fd_select: Option<i32>,
+    charstrings: i32,
+    fd_array: i32,
+    privates: Vec<(i32, i32)>,
+}
+
+/// A private DICT together with its local subroutines
+struct PrivateBlock<'a> {
+    size: usize,
+    data: &'a [u8],
+}
+
+/// Finds the private DICT a top or font DICT points to. Local subroutines are
+/// addressed relative to it, so they are copied along in one block.
+fn private_block<'a>(cff: &'a [u8], dict: &[DictEntry]) -> anyhow::Result<PrivateBlock<'a>> {
+    let size = dict_operand(dict, CFF_PRIVATE, 0).context("CFF font has no private DICT")?;
+    let start = dict_operand(dict, CFF_PRIVATE, 1).context("CFF font has no private DICT")?;
+    let (size, start) = (usize::try_from(size)?, usize::try_from(start)?);
+    let private = parse_dict(
+        cff.get(start..start + size)
+            .context("truncated CFF private DICT")?,
+    )?;
+    let mut end = start + size;
+    if let Some(subrs) = dict_operand(&private, CFF_SUBRS, 0) {
+        end = end.max(cff_index(cff, start + usize::try_from(subrs)?)?.1);
+    }
+    Ok(PrivateBlock {
+        size,
+        data: cff_block(cff, start, end - start)?,
+    })
+}
+
+fn cff_block(cff: &[u8], start: usize, len: usize) -> anyhow::Result<&[u8]> {
+    cff.get(start..start + len).context("truncated CFF table")
+}
+
+/// Reads a CFF INDEX, returning its items and where it ends
+fn cff_index(cff: &[u8], start: usize) -> anyhow::Result<(Vec<&[u8]>, usize)> {
+    let count = usize::from(read_u16(cff, start)?);
+    if count == 0 {
+        return Ok((Vec::new(), start + 2));
+    }
+    let size = usize::from(*cff.get(start + 2).context("truncated CFF INDEX")?);
+    anyhow::ensure!(
+        (1..=4).contains(&size),
+        "invalid CFF INDEX offset size {size}"
+    );
+    let offset = |i: usize| -> anyhow::Result<usize> {
+        let at = start + 3 + i * size;
+        let bytes = cff.get(at..at + size).context("truncated CFF INDEX")?;
+        Ok(bytes.iter().fold(0, |x, b| x << 8 | usize::from(*b)))
+    };
+    // offsets count from the byte before the data
+    let base = start + 2 + (count + 1) * size;
+    let mut items = Vec::with_capacity(count);
+    for i in 0..count {
+        let (from, to) = (offset(i)?, offset(i + 1)?);
+        anyhow::ensure!(from >= 1 && from <= to, "invalid CFF INDEX offsets");
+        items.push(
+            cff.get(base + from..base + to)
+                .context("truncated CFF INDEX")?,
+        );
+    }
+    Ok((items, base + offset(count)?))
+}
+
+fn write_cff_index(items: &[&[u8]]) -> anyhow::Result<Vec<u8>> {
+    let count = u16::try_from(items.len()).context("too many items for a CFF INDEX")?;
+    let mut out = count.to_be_bytes().to_vec();
+    if items.is_empty() {
+        return Ok(out);
+    }
+    out.push(4);
+    let mut offset = 1u32;
+    out.extend_from_slice(&offset.to_be_bytes());
+    for item in items {
+        offset += u32::try_from(item.len())?;
+        out.extend_from_slice(&offset.to_be_bytes());
+    }
+    for item in items {
+        out.extend_from_slice(item);
+    }
+    Ok(out)
+}
+
+/// An operator of a CFF DICT with its raw operands. Integer operands are
+/// decoded too, real ones are read as 0 as no offset is a real.
+struct DictEntry<'a> {
+    operator: u16,
+    operands: &'a [u8],
+    numbers: Vec<i32>,
+}
+
+fn dict_operand(dict: &[DictEntry], operator: u16, index: usize) -> Option<i32> {
+    dict.iter()
+        .find(|x| x.operator == operator)
+        .and_then(|x| x.numbers.get(index).copied())
+}
+
+fn dict_offset(dict: &[DictEntry], operator: u16) -> anyhow::Result<Option<usize>> {
+    dict_operand(dict, operator, 0)
+        .map(usize::try_from)
+        .transpose()
+        .context("invalid CFF offset")
+}
+
+fn parse_dict(data: &[u8]) -> anyhow::Result<Vec<DictEntry<'_>>> {
+    let byte = |i: usize| data.get(i).copied().context("truncated CFF DICT");
+    let mut entries = Vec::new();
+    let mut numbers = Vec::new();
+    let (mut start, mut i) = (0, 0);
+    while i < data.len() {
+        let b0 = data[i];
+        match b0 {
+            0..=21 => {
+                let operands = &data[start..i];
+                let operator = if b0 == 12 {
+                    i += 1;
+                    0x0c00 | u16::from(byte(i)?)
+                } else {
+                    u16::from(b0)
+                };
+                i += 1;
+                start = i;
+                entries.push(DictEntry {
+                    operator,
+                    operands,
+                    numbers: std::mem::take(&mut numbers),
+                });
+            }
+            28 => {
+                numbers.push(i32::from(i16::from_be_bytes([byte(i + 1)?, byte(i + 2)?])));
+                i += 3;
+            }
+            29 => {
+                let bytes = [byte(i + 1)?, byte(i + 2)?, byte(i + 3)?, byte(i + 4)?];
+                numbers.push(i32::from_be_bytes(bytes));
+                i += 5;
+            }
+            30 => {
+                i += 1;
+                while byte(i)? & 0x0f != 0x0f && byte(i)? >> 4 != 0x0f {
+                    i += 1;
+                }
+                i += 1;
+                numbers.push(0);
+            }
+            32..=246 => {
+                numbers.push(i32::from(b0) - 139);
+                i += 1;
+            }
+            247..=250 => {
+                numbers.push((i32::from(b0) - 247) * 256 + i32::from(byte(i + 1)?) + 108);
+                i += 2;
+            }
+            251..=254 => {
+                numbers.push(-(i32::from(b0) - 251) * 256 - i32::from(byte(i + 1)?) - 108);
+                i += 2;
+            }
+            _ => anyhow::bail!("invalid CFF DICT operand {b0}"),
+        }
+    }
+    Ok(entries)
+}
+
+/// Writes a DICT back, with the operands `replace` returns written as 5 byte
+/// integers so their size doesn't depend on their values
+fn write_dict(
+    dict: &[DictEntry],
+    replace: impl Fn(u16) -> Option<Vec<i32>>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for entry in dict {
+        if let Some(numbers) = replace(entry.operator) {
+            for number in numbers {
+                out.push(29);
+                out.extend_from_slice(&number.to_be_bytes());
+            }
+        } else {
+            out.extend_from_slice(entry.operands);
+        }
+        if entry.operator > 0xff {
+            out.extend_from_slice(&entry.operator.to_be_bytes());
+        } else {
+            out.push(u8::try_from(entry.operator)?);
+        }
+    }
+    Ok(out)
+}
+
+fn charset_len(cff: &[u8], start: usize, num_glyphs: u16) -> anyhow::Result<usize> {
+    let byte = |i: usize| cff.get(i).copied().context("truncated CFF charset");
+    let format = byte(start)?;
+    if format == 0 {
+        return Ok(1 + 2 * usize::from(num_glyphs.saturating_sub(1)));
+    }
+    anyhow::ensure!(format <= 2, "invalid CFF charset format {format}");
+    // glyph 0 is implicit, each range covers its first glyph and those left
+    let (mut covered, mut i) = (1, start + 1);
+    while covered < usize::from(num_glyphs) {
+        let left = if format == 1 {
+            usize::from(byte(i + 2)?)
+        } else {
+            usize::from(read_u16(cff, i + 2)?)
+        };
+        i += if format == 1 { 3 } else { 4 };
+        covered += left + 1;
+    }
+    Ok(i - start)
+}
+
+fn encoding_len(cff: &[u8], start: usize) -> anyhow::Result<usize> {
+    let byte = |i: usize| {
+        cff.get(i)
+            .copied()
+            .map(usize::from)
+            .context("truncated CFF encoding")
+    };
+    let format = byte(start)?;
+    let mut len = match format & 0x7f {
+        0 => 2 + byte(start + 1)?,
+        1 => 2 + 2 * byte(start + 1)?,
+        format => anyhow::bail!("invalid CFF encoding format {format}"),
+    };
+    // supplements map extra codes to glyph names
+    if format & 0x80 != 0 {
+        len += 1 + 3 * byte(start + len)?;
+    }
+    Ok(len)
+}
+
+fn fd_select_len(cff: &[u8], start: usize, num_glyphs: u16) -> anyhow::Result<usize> {
+    match cff.get(start).context("truncated CFF FDSelect")? {
+        0 => Ok(1 + usize::from(num_glyphs)),
+        3 => Ok(5 + 3 * usize::from(read_u16(cff, start + 1)?)),
+        format => anyhow::bail!("invalid CFF FDSelect format {format}"),
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn write_font(sfnt_version: u32, tables: &BTreeMap<[u8; 4], Vec<u8>>) -> anyhow::Result<Vec<u8>> {
+    let count = u16::try_from(tables.len())?;
+    let entry_selector = count.ilog2();
+    let search_range = 16u16 << entry_selector;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&sfnt_version.to_be_bytes());
+    out.extend_from_slice(&count.to_be_bytes());
+    out.extend_from_slice(&search_range.to_be_bytes());
+    out.extend_from_slice(&u16::try_from(entry_selector)?.to_be_bytes());
+    out.extend_from_slice(&(count * 16 - search_range).to_be_bytes());
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = None;
+    for (tag, data) in tables {
+        if tag == b"head" {
+            head_offset = Some(offset);
+        }
+        out.extend_from_slice(tag);
+        out.extend_from_slice(&checksum(data).to_be_bytes());
+        out.extend_from_slice(&u32::try_from(offset)?.to_be_bytes());
+        out.extend_from_slice(&u32::try_from(data.len())?.to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for data in tables.values() {
+        out.extend_from_slice(data);
+        out.resize(out.len().next_multiple_of(4), 0);
+    }
+
+    let head_offset = head_offset.context("font has no head table")?;
+    let adjustment = 0xb1b0_afbau32.wrapping_sub(checksum(&out));
+    out[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    const TTF: &[u8] = include_bytes!("../testdata/fonts/SubassTest.ttf");
+    const CFF: &[u8] = include_bytes!("../testdata/fonts/SubassTestCFF.otf");
+    const CID: &[u8] = include_bytes!("../testdata/fonts/SubassTestCID.otf");
+
+    /// Glyph IDs of the test fonts
+    const A: u16 = 3;
+    const B: u16 = 4;
+    const O: u16 = 5;
+    const A_VERT: u16 = 6;
+    const DIERESIS: u16 = 7;
+    const I_I: u16 = 9;
+
+    fn has_outline(face: &ttf_parser::Face, gid: u16) -> bool {
+        struct Sink;
+        impl ttf_parser::OutlineBuilder for Sink {
+            fn move_to(&mut self, _: f32, _: f32) {}
+            fn line_to(&mut self, _: f32, _: f32) {}
+            fn quad_to(&mut self, _: f32, _: f32, _: f32, _: f32) {}
+            fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) {}
+            fn close(&mut self) {}
+        }
+        face.outline_glyph(ttf_parser::GlyphId(gid), &mut Sink)
+            .is_some()
+    }
+
+    #[rstest]
+    #[case::ttf(TTF)]
+    #[case::cff(CFF)]
+    #[case::cid(CID)]
+    fn test_subset_font(#[case] font: &[u8]) {
+        let subset = subset_font(font, 0, &BTreeSet::from(['A', 'I']), None).unwrap();
+        let face = ttf_parser::Face::parse(&subset, 0).unwrap();
+        assert_eq!(face.number_of_glyphs(), 10);
+        assert_eq!(face.glyph_index('A'), Some(ttf_parser::GlyphId(A)));
+        assert_eq!(face.glyph_index('B'), None);
+        // vertical form and ligature are only reachable through GSUB
+        let kept = [A, A_VERT, I_I].map(|gid| has_outline(&face, gid));
+        let dropped = [B, O].map(|gid| has_outline(&face, gid));
+        assert_eq!((kept, dropped), ([true; 3], [false; 2]));
+        assert!(subset.len() < font.len() + 64);
+
+        // a ligature needs all of its components
+        let subset = subset_font(font, 0, &BTreeSet::from(['A']), None).unwrap();
+        let face = ttf_parser::Face::parse(&subset, 0).unwrap();
+        assert!(!has_outline(&face, I_I));
+    }
+
+    #[test]
+    fn test_subset_composite() {
+        let subset = subset_font(TTF, 0, &BTreeSet::from(['Ä']), None).unwrap();
+        let face = ttf_parser::Face::parse(&subset, 0).unwrap();
+        let kept = [A, DIERESIS].map(|gid| has_outline(&face, gid));
+        assert_eq!((kept, has_outline(&face, A_VERT)), ([true; 2], false));
+    }
+
+    #[rstest]
+    #[case::ttf(TTF)]
+    #[case::cff(CFF)]
+    fn test_subset_rename(#[case] font: &[u8]) {
+        let subset = subset_font(font, 0, &BTreeSet::from(['A']), Some("ABCD1234")).unwrap();
+        let face = ttf_parser::Face::parse(&subset, 0).unwrap();
+        let families: Vec<String> = face
+            .names()
+            .into_iter()
+            .filter(|x| x.name_id == ttf_parser::name_id::FAMILY)
+            .filter_map(|x| x.to_string())
+            .collect();
+        assert!(!families.is_empty());
+        assert!(families.iter().all(|x| x == "ABCD1234"));
+    }
+
+    #[test]
+    fn test_subset_script() {
+        let mut script = AssScript::try_from_str(
+            r"[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Subass Test,26,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,1.3,0,2,20,20,23,0
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,A{\fnSubass Test CFF}B",
+        )
+        .unwrap();
+        let embedded = Attachment {
+            name: "subasstest.ttf".to_string(),
+            data: TTF.to_vec(),
+        };
+        script
+            .other_sections
+            .insert("Fonts".to_string(), attachment::encode_embedded(&embedded));
+        let mut db = FontDatabase::default();
+        db.load_script(&script).unwrap();
+        db.load_data(CFF.to_vec(), &FontSource::File("cff.otf".into()));
+
+        let fonts = subset_script(&mut script, &db, true).unwrap();
+
+        // the embedded font is replaced in place, the other one returned
+        let (ttf, cff) = (subset_name("Subass Test"), subset_name("Subass Test CFF"));
+        let filenames: Vec<&str> = fonts.iter().map(|x| x.filename.as_str()).collect();
+        assert_eq!(filenames, [format!("{cff}_0.otf")]);
+        let attachments = attachment::decode_embedded(&script.other_sections["Fonts"]).unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].name, format!("{ttf}_0.ttf"));
+        let face = ttf_parser::Face::parse(&attachments[0].data, 0).unwrap();
+        assert_eq!(face.glyph_index('A'), Some(ttf_parser::GlyphId(A)));
+        assert_eq!(face.glyph_index('B'), None);
+
+        assert_eq!(script.styles.entries[0].fontname, ttf);
+        assert_eq!(script.events.entries[0].text, format!(r"A{{\fn{cff}}}B"));
+    }
+
+    #[test]
+    fn test_subset_script_aliases() {
+        // the family and PostScript names of one face
+        let mut script = crate::testing::script(&[(0, 1000, r"A{\fnSubassTest}B")]);
+        script.styles.entries[0].fontname = "Subass Test".to_string();
+        let mut db = FontDatabase::default();
+        db.load_data(TTF.to_vec(), &FontSource::File("ttf.ttf".into()));
+
+        let fonts = subset_script(&mut script, &db, true).unwrap();
+        assert_eq!(fonts.len(), 1);
+        let name = subset_name("Subass Test");
+        assert_eq!(script.styles.entries[0].fontname, name);
+        assert_eq!(script.events.entries[0].text, format!(r"A{{\fn{name}}}B"));
+        let face = ttf_parser::Face::parse(&fonts[0].data, 0).unwrap();
+        assert_eq!(face.glyph_index('B'), Some(ttf_parser::GlyphId(B)));
+    }
+
+    #[test]
+    fn test_subset_truncated_head() {
+        let mut tables = read_tables(TTF, 0).unwrap();
+        let head = tables.get_mut(b"head").unwrap();
+        *head = &head[..10];
+        let tables: BTreeMap<[u8; 4], Vec<u8>> =
+            tables.into_iter().map(|(k, v)| (k, v.to_vec())).collect();
+        let data = write_font(0x0001_0000, &tables).unwrap();
+        assert!(Subset::new(&data, 0, &BTreeSet::from(['A'])).is_err());
+    }
+
+    #[test]
+    fn test_subset_script_error() {
+        let mut script = AssScript::try_from_str(
+            r"[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Subass Test,26,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,1.3,0,2,20,20,23,0
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,A",
+        )
+        .unwrap();
+        // a font whose outlines were stripped can't be subset
+        let mut tables = read_tables(TTF, 0).unwrap();
+        tables.remove(b"glyf");
+        let tables = tables
+            .into_iter()
+            .map(|(tag, x)| (tag, x.to_vec()))
+            .collect();
+        let font = write_font(0x0001_0000, &tables).unwrap();
+        let mut db = FontDatabase::default();
+        db.load_data(font, &FontSource::File("broken.ttf".into()));
+
+        let error = subset_script(&mut script, &db, false).unwrap_err();
+        assert_eq!(error.to_string(), "failed to subset broken.ttf");
+    }
+
+    #[test]
+    fn test_subset_name() {
+        let name = subset_name("Roboto Medium");
+        assert_eq!(name.len(), 8);
+        assert!(name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()));
+        assert_eq!(name, subset_name("roboto medium"));
+        assert_ne!(name, subset_name("Arial"));
+    }
+
+    #[test]
+    fn test_rename_fonts() {
+        let mut script = AssScript::try_from_str(
+            r"[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,@Arial,26,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,1.3,0,2,20,20,23,0
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,{\fnarial}a{\fnOther}b",
+        )
+        .unwrap();
+        let names = BTreeMap::from([("Arial".to_string(), "ABCD1234".to_string())]);
+        rename_fonts(&mut script, &names);
+
+        assert_eq!(script.styles.entries[0].fontname, "@ABCD1234");
+        assert_eq!(script.events.entries[0].text, r"{\fnABCD1234}a{\fnOther}b");
+    }
+
+    #[test]
+    fn test_cmap_roundtrip() {
+        let cmap = BTreeMap::from([('a', 1), ('b', 2), ('d', 7), ('字', 9), ('😀', 12)]);
+        let table = build_cmap(&cmap).unwrap();
+        let parsed = ttf_parser::cmap::Table::parse(&table).unwrap();
+        assert_eq!(parsed.subtables.len(), 2);
+        for subtable in parsed.subtables {
+            let full = matches!(
+                subtable.format,
+                ttf_parser::cmap::Format::SegmentedCoverage(_)
+            );
+            for (c, gid) in &cmap {
+                let expected =
+                    (full || u32::from(*c) <= 0xffff).then_some(ttf_parser::GlyphId(*gid));
+                assert_eq!(subtable.glyph_index(u32::from(*c)), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use crate::event::EventType;
+use crate::event::Events;
+use crate::tag;
+use crate::time::AssTime;
+use crate::timing::Retiming;
+
+/// Limits of the search for the timing difference between two tracks
+#[derive(Debug, Clone, Copy)]
+pub struct SyncOptions {
+    /// Largest offset considered, in either direction
+    pub max_offset: AssTime,
+    /// How far apart the boundaries of matching events may be
+    pub tolerance: AssTime,
+    /// Also estimate a linear drift, for encodes that differ in speed
+    pub drift: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            max_offset: AssTime::from_millis(60_000),
+            tolerance: AssTime::from_millis(120),
+            drift: true,
+        }
+    }
+}
+
+/// How the overlay track has to be retimed to match the reference
+#[derive(Debug, Clone, Copy)]
+pub struct SyncEstimate {
+    pub retiming: Retiming,
+    /// Overlay event boundaries that line up with a reference boundary
+    pub matched: usize,
+    /// Overlay event boundaries considered
+    pub total: usize,
+}
+
+impl SyncEstimate {
+    /// Share of overlay boundaries that match after the correction, from 0
+    /// to 1
+    pub fn confidence(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        // counts are far below the range where the casts lose precision
+        #[allow(clippy::cast_precision_loss)]
+        let confidence = self.matched as f64 / self.total as f64;
+        confidence
+    }
+}
+
+/// Start and end times of the visible dialogue, sorted
+fn boundaries(events: &Events) -> anyhow::Result<Vec<i64>> {
+    let mut times = Vec::new();
+    for event in &events.entries {
+        if event.event_type != EventType::Dialogue || tag::plain_text(&event.text).trim().is_empty()
+        {
+            continue;
+        }
+        times.push(event.start_time()?.millis());
+        times.push(event.end_time()?.millis());
+    }
+    times.sort_unstable();
+    times.dedup();
+    Ok(times)
+}
+
+/// Estimates the offset, and optionally drift, between two tracks timed
+/// independently against the same video. Events rarely share exact times, so
+/// the offset most boundary pairs agree on is found first, then a line is
+/// fitted through the boundaries that match under it.
+pub fn estimate(
+    reference: &Events,
+    overlay: &Events,
+    options: &SyncOptions,
+) -> anyhow::Result<SyncEstimate> {
+    let reference = boundaries(reference)?;
+    let overlay = boundaries(overlay)?;
+    anyhow::ensure!(
+        !reference.is_empty() && !overlay.is_empty(),
+        "no dialogue to align"
+    );
+
+    let max_offset = options.max_offset.millis();
+    let tolerance = options.tolerance.millis();
+
+    let (offset, _) = best_offset(&reference, &overlay, max_offset, tolerance);
+    #[allow(clippy::cast_precision_loss)]
+    let mut retiming = Retiming {
+        scale: 1.0,
+        offset: offset as f64,
+    };
+    if options.drift {
+        // a single offset only fits part of a drifting track, so estimate one
+        // per stretch of about a minute and fit a line through them
+        let mut points = Vec::new();
+        for chunk in overlay.chunks(40) {
+            let (offset, count) = best_offset(&reference, chunk, max_offset, tolerance);
+            if count * 3 >= chunk.len() {
+                points.push((chunk[chunk.len() / 2], offset));
+            }
+        }
+        if let Some(x) = robust_fit(&points) {
+            retiming = x;
+        }
+        // false matches are spread evenly around the line, so least squares
+        // on the matches refines it without bias
+        for _ in 0..2 {
+            let pairs = matches(&reference, &overlay, &retiming, tolerance);
+            match fit(&pairs) {
+                Some(x) => retiming = x,
+                None => break,
+            }
+        }
+    }
+
+    Ok(SyncEstimate {
+        retiming,
+        matched: matches(&reference, &overlay, &retiming, tolerance).len(),
+        total: overlay.len(),
+    })
+}
+
+/// The offset most pairings of boundaries agree on within `tolerance`, and
+/// how many pairings agree
+fn best_offset(
+    reference: &[i64],
+    overlay: &[i64],
+    max_offset: i64,
+    tolerance: i64,
+) -> (i64, usize) {
+    // every pairing within range votes for an offset
+    let mut votes = Vec::new();
+    let mut first = 0;
+    for &o in overlay {
+        while first < reference.len() && reference[first] < o - max_offset {
+            first += 1;
+        }
+        for &r in reference[first..]
+            .iter()
+            .take_while(|&&r| r <= o + max_offset)
+        {
+            votes.push(r - o);
+        }
+    }
+    votes.sort_unstable();
+
+    // the window of offsets that collects the most votes
+    let (mut best, mut best_count) = (0, 0);
+    let mut low = 0;
+    for high in 0..votes.len() {
+        while votes[high] - votes[low] > 2 * tolerance {
+            low += 1;
+        }
+        if high - low + 1 > best_count {
+            best_count = high - low + 1;
+            best = votes[low + (high - low) / 2];
+        }
+    }
+    (best, best_count)
+}
+
+/// Theil-Sen line through `(overlay time, offset)` points, which ignores
+/// stretches whose offset was found wrongly
+#[allow(clippy::cast_precision_loss)]
+fn robust_fit(points: &[(i64, i64)]) -> Option<Retiming> {
+    let mut slopes = Vec::new();
+    for (i, a) in points.iter().enumerate() {
+        for b in &points[i + 1..] {
+            if b.0 != a.0 {
+                slopes.push((b.1 - a.1) as f64 / (b.0 - a.0) as f64);
+            }
+        }
+    }
+    let slope = median(&mut slopes)?;
+    let mut intercepts: Vec<f64> = points
+        .iter()
+        .map(|x| x.1 as f64 - slope * x.0 as f64)
+        .collect();
+    Some(Retiming {
+        scale: 1.0 + slope,
+        offset: median(&mut intercepts)?,
+    })
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_unstable_by(f64::total_cmp);
+    values.get(values.len() / 2).copied()
+}
+
+/// Overlay boundaries paired with the nearest reference boundary within
+/// `tolerance` after retiming
+fn matches(
+    reference: &[i64],
+    overlay: &[i64],
+    retiming: &Retiming,
+    tolerance: i64,
+) -> Vec<(i64, i64)> {
+    let mut pairs = Vec::new();
+    for &o in overlay {
+        let target = retiming.apply(AssTime::from_millis(o)).millis();
+        let i = reference.partition_point(|&r| r < target);
+        let nearest = [i.checked_sub(1), Some(i)]
+            .into_iter()
+            .flatten()
+            .filter_map(|i| reference.get(i))
+            .min_by_key(|&&r| (r - target).abs());
+        if let Some(&r) = nearest.filter(|&&r| (r - target).abs() <= tolerance) {
+            pairs.push((o, r));
+        }
+    }
+    pairs
+}
+
+/// Least squares line through `(overlay, reference)` pairs
+#[allow(clippy::cast_precision_loss)]
+fn fit(pairs: &[(i64, i64)]) -> Option<Retiming> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|x| x.0 as f64).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|x| x.1 as f64).sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for &(x, y) in pairs {
+        covariance += (x as f64 - mean_x) * (y as f64 - mean_y);
+        variance += (x as f64 - mean_x).powi(2);
+    }
+    if variance == 0.0 {
+        return None;
+    }
+    let scale = covariance / variance;
+    Some(Retiming {
+        scale,
+        offset: mean_y - scale * mean_x,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::script;
+
+    /// Roughly evenly spread lines of varying length, like real dialogue
+    fn dialogue(count: i64, map: impl Fn(i64) -> i64) -> Events {
+        let events: Vec<(i64, i64, &str)> = (0..count)
+            .map(|i| {
+                let start = 5000 + i * 3700 + (i * 7919) % 1300;
+                let end = start + 900 + (i * 104_729) % 2100;
+                (map(start), map(end), "line")
+            })
+            .collect();
+        script(&events).events
+    }
+
+    #[test]
+    fn test_estimate_offset() {
+        let reference = dialogue(300, |x| x);
+        // timed separately, so boundaries are off by a few frames
+        let mut overlay = dialogue(300, |x| x - 2500 + (x * 31) % 90 - 45);
+        overlay.entries.truncate(250);
+
+        let estimate = estimate(&reference, &overlay, &SyncOptions::default()).unwrap();
+        assert!(
+            (estimate.retiming.offset - 2500.0).abs() < 60.0,
+            "{estimate:?}"
+        );
+        assert!((estimate.retiming.scale - 1.0).abs() < 1e-4, "{estimate:?}");
+        assert!(estimate.confidence() > 0.9, "{estimate:?}");
+    }
+
+    #[test]
+    fn test_estimate_drift() {
+        let reference = dialogue(300, |x| x);
+        // 1.001 is the ratio between 24 and 23.976 fps
+        let overlay = dialogue(300, |x| (x * 1000 + 500) / 1001 + 800);
+
+        let estimate = estimate(&reference, &overlay, &SyncOptions::default()).unwrap();
+        assert!(
+            (estimate.retiming.scale - 1.001).abs() < 1e-4,
+            "{estimate:?}"
+        );
+        assert!(estimate.confidence() > 0.95, "{estimate:?}");
+
+        let mut corrected = overlay.clone();
+        corrected.retime(&estimate.retiming, |_| true).unwrap();
+        for (got, should) in corrected.entries.iter().zip(&reference.entries) {
+            let difference = got.start_time().unwrap() - should.start_time().unwrap();
+            assert!(difference.millis().abs() <= 20, "{got:?} {should:?}");
+        }
+    }
+
+    #[test]
+    fn test_estimate_unrelated() {
+        let reference = dialogue(100, |x| x);
+        let events: Vec<(i64, i64, &str)> =
+            (0..100).map(|i| (i * 1000, i * 1000 + 10, "x")).collect();
+        let overlay = script(&events).events;
+        let estimate = estimate(&reference, &overlay, &SyncOptions::default()).unwrap();
+        assert!(estimate.confidence() < 0.5, "{estimate:?}");
+    }
+}
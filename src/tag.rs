@@ -0,0 +1,284 @@
+use std::ops::Range;
+
+/// Override tag names, longest first so that prefixes like `\b` don't shadow
+/// `\bord` or `\blur`
+const KNOWN_TAGS: [&str; 52] = [
+    "xbord", "ybord", "xshad", "yshad", "alpha", "iclip", "fscx", "fscy", "bord", "blur", "clip",
+    "shad", "move", "fade", "fsp", "fax", "fay", "frx", "fry", "frz", "pos", "org", "fad", "pbo",
+    "an", "be", "fr", "fn", "fs", "fe", "1c", "2c", "3c", "4c", "1a", "2a", "3a", "4a", "kf", "ko",
+    "a", "b", "c", "i", "u", "s", "k", "K", "q", "r", "t", "p",
+];
+
+/// A piece of event text, either an override block or plain text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextPart<'a> {
+    /// Contents of a `{...}` block, without the braces
+    Override(&'a str),
+    Text(&'a str),
+}
+
+/// A single override tag such as `\fnArial` or `\pos(10,20)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag<'a> {
+    pub name: &'a str,
+    /// Argument with surrounding whitespace and parentheses removed
+    pub args: &'a str,
+    /// Whether the argument was given in parentheses
+    pub parens: bool,
+    /// Byte range of the whole tag, including the backslash, in its block
+    pub range: Range<usize>,
+}
+
+impl<'a> Tag<'a> {
+    /// Whether the tag name is one recognized by common renderers
+    pub fn is_known(&self) -> bool {
+        KNOWN_TAGS.contains(&self.name)
+    }
+
+    /// Comma separated arguments, ignoring commas nested in parentheses
+    pub fn params(&self) -> Vec<&'a str> {
+        let mut params = Vec::new();
+        let mut depth = 0usize;
+        let mut start = 0;
+        for (i, c) in self.args.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => {
+                    params.push(self.args[start..i].trim());
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if !self.args.is_empty() {
+            params.push(self.args[start..].trim());
+        }
+        params
+    }
+}
+
+/// Splits event text into override blocks and plain text. An opening brace
+/// without a closing one is treated as plain text, as renderers do.
+pub fn split_text(text: &str) -> Vec<TextPart<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let Some(open) = rest.find('{') else {
+            parts.push(TextPart::Text(rest));
+            break;
+        };
+        let Some(close) = rest[open..].find('}').map(|x| x + open) else {
+            parts.push(TextPart::Text(rest));
+            break;
+        };
+        if open > 0 {
+            parts.push(TextPart::Text(&rest[..open]));
+        }
+        parts.push(TextPart::Override(&rest[open + 1..close]));
+        rest = &rest[close + 1..];
+    }
+
+    parts
+}
+
+/// Parses the tags in the contents of an override block. Anything that is not
+/// a tag, such as comments, is skipped.
+pub fn parse_tags(block: &str) -> Vec<Tag<'_>> {
+    let mut tags = Vec::new();
+    let mut pos = 0;
+
+    while let Some(offset) = block[pos..].find('\\') {
+        let start = pos + offset;
+        let after = &block[start + 1..];
+
+        let name_len = KNOWN_TAGS
+            .iter()
+            .find(|name| after.starts_with(*name))
+            .map_or_else(
+                || {
+                    after
+                        .find(|c: char| !c.is_ascii_alphanumeric())
+                        .unwrap_or(after.len())
+                },
+                |name| name.len(),
+            );
+        let name = &after[..name_len];
+        let rest = &after[name_len..];
+        let trimmed = rest.trim_start();
+        let skipped = rest.len() - trimmed.len();
+
+        let (args, parens, consumed) = if name != "fn" && trimmed.starts_with('(') {
+            let mut depth = 0usize;
+            let mut close = None;
+            for (i, c) in trimmed.char_indices() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            close = Some(i);
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            // an unterminated argument list runs to the end of the block
+            match close {
+                Some(i) => (&trimmed[1..i], true, skipped + i + 1),
+                None => (&trimmed[1..], true, rest.len()),
+            }
+        } else {
+            let end = rest.find('\\').unwrap_or(rest.len());
+            (&rest[..end], false, end)
+        };
+
+        let end = start + 1 + name_len + consumed;
+        tags.push(Tag {
+            name,
+            args: args.trim(),
+            parens,
+            range: start..end,
+        });
+        pos = end;
+    }
+
+    tags
+}
+
+/// Rewrites every tag in every override block of `text`. The callback returns
+/// the replacement source for a tag (including the backslash), or `None` to
+/// keep it unchanged. Tags nested in `\t` are visited as well.
+pub fn rewrite_tags(text: &str, f: &mut impl FnMut(&Tag) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    for part in split_text(text) {
+        match part {
+            TextPart::Text(x) => out.push_str(x),
+            TextPart::Override(block) => {
+                out.push('{');
+                out.push_str(&rewrite_block(block, f));
+                out.push('}');
+            }
+        }
+    }
+    out
+}
+
+fn rewrite_block(block: &str, f: &mut impl FnMut(&Tag) -> Option<String>) -> String {
+    let mut out = String::with_capacity(block.len());
+    let mut last = 0;
+    for tag in parse_tags(block) {
+        out.push_str(&block[last..tag.range.start]);
+        if let Some(replacement) = f(&tag) {
+            out.push_str(&replacement);
+        } else if tag.name == "t" && tag.parens {
+            // only the nested tags of \t may change, the timing params stay
+            let params = tag.params();
+            let nested = params.last().copied().unwrap_or_default();
+            let source = &block[tag.range.clone()];
+            let offset = source.rfind(nested).unwrap_or(source.len());
+            out.push_str(&source[..offset]);
+            out.push_str(&rewrite_block(nested, f));
+            out.push_str(&source[offset + nested.len()..]);
+        } else {
+            out.push_str(&block[tag.range.clone()]);
+        }
+        last = tag.range.end;
+    }
+    out.push_str(&block[last..]);
+    out
+}
+
+/// Text as it would be displayed: override blocks and drawings removed, hard
+/// and soft line breaks turned into `\n` and hard spaces into U+00A0
+pub fn plain_text(text: &str) -> String {
+    let mut out = String::new();
+    let mut drawing = false;
+    for part in split_text(text) {
+        match part {
+            TextPart::Override(block) => {
+                for tag in parse_tags(block) {
+                    if tag.name == "p" {
+                        drawing = tag.args.parse::<u32>().unwrap_or(0) > 0;
+                    }
+                }
+            }
+            TextPart::Text(x) if !drawing => out.push_str(&unescape(x)),
+            TextPart::Text(_) => {}
+        }
+    }
+    out
+}
+
+/// Replaces the `\N`, `\n` and `\h` escapes in a run of plain text
+pub fn unescape(text: &str) -> String {
+    text.replace("\\N", "\n")
+        .replace("\\n", "\n")
+        .replace("\\h", "\u{a0}")
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[test]
+    fn test_split_text() {
+        let parts = split_text(r"{\i1}Hello{\i0} world{");
+        assert_eq!(
+            parts,
+            vec![
+                TextPart::Override(r"\i1"),
+                TextPart::Text("Hello"),
+                TextPart::Override(r"\i0"),
+                TextPart::Text(" world{"),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(r"\bord2", "bord", "2")]
+    #[case(r"\blur0.5", "blur", "0.5")]
+    #[case(r"\b1", "b", "1")]
+    #[case(r"\fnRoboto Medium", "fn", "Roboto Medium")]
+    #[case(r"\1c&H00FF00&", "1c", "&H00FF00&")]
+    #[case(r"\pos(10,20)", "pos", "10,20")]
+    #[case(r"\foo12", "foo12", "")]
+    fn test_parse_single_tag(#[case] block: &str, #[case] name: &str, #[case] args: &str) {
+        let tags = parse_tags(block);
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, name);
+        assert_eq!(tags[0].args, args);
+    }
+
+    #[test]
+    fn test_parse_nested_transform() {
+        let tags = parse_tags(r"\fad(500,500)\t(0,100,\fscx120\clip(0,0,1,1))\an8");
+        let names: Vec<_> = tags.iter().map(|x| x.name).collect();
+        assert_eq!(names, vec!["fad", "t", "an"]);
+        assert_eq!(
+            tags[1].params(),
+            vec!["0", "100", r"\fscx120\clip(0,0,1,1)"]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_tags() {
+        let text = r"{\fnArial\t(0,50,\fnFoo)}Hi{\i1}";
+        let result = rewrite_tags(text, &mut |tag| {
+            (tag.name == "fn").then(|| format!(r"\fn{}X", tag.args))
+        });
+        assert_eq!(result, r"{\fnArialX\t(0,50,\fnFooX)}Hi{\i1}");
+    }
+
+    #[rstest]
+    #[case(r"{\i1}Hello\Nworld{\i0}", "Hello\nworld")]
+    #[case(r"a\hb", "a\u{a0}b")]
+    #[case(r"{\p1}m 0 0 l 10 10{\p0}text", "text")]
+    fn test_plain_text(#[case] text: &str, #[case] should: &str) {
+        assert_eq!(plain_text(text), should);
+    }
+}
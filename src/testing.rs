@@ -0,0 +1,112 @@
+use crate::event::EventStrict;
+use crate::font::FontDatabase;
+use crate::font::FontSource;
+use crate::style::StyleStrict;
+use crate::time::AssTime;
+use crate::AssScript;
+
+/// A dialogue event given as `(start, end, text)`, `(start, end, style,
+/// text)` or `(start, end, layer, text)`, with times in milliseconds. The
+/// style defaults to `Default` and the layer to 0.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TestEvent<'a> {
+    start: i64,
+    end: i64,
+    style: &'a str,
+    layer: i64,
+    text: &'a str,
+}
+
+impl<'a> From<(i64, i64, &'a str)> for TestEvent<'a> {
+    fn from((start, end, text): (i64, i64, &'a str)) -> Self {
+        Self {
+            start,
+            end,
+            style: "Default",
+            layer: 0,
+            text,
+        }
+    }
+}
+
+impl<'a> From<(i64, i64, &'a str, &'a str)> for TestEvent<'a> {
+    fn from((start, end, style, text): (i64, i64, &'a str, &'a str)) -> Self {
+        Self {
+            style,
+            ..(start, end, text).into()
+        }
+    }
+}
+
+impl<'a> From<(i64, i64, i64, &'a str)> for TestEvent<'a> {
+    fn from((start, end, layer, text): (i64, i64, i64, &'a str)) -> Self {
+        Self {
+            layer,
+            ..(start, end, text).into()
+        }
+    }
+}
+
+/// A new script with the given events, a `Default` style and a default style
+/// for every other name the events use
+pub(crate) fn script<'a, E: Into<TestEvent<'a>> + Copy>(events: &[E]) -> AssScript {
+    let mut script = AssScript::new();
+    script.styles.entries.push(StyleStrict::default());
+    for event in events {
+        let event: TestEvent = (*event).into();
+        if script.styles.find(event.style).is_none() {
+            script.styles.entries.push(StyleStrict {
+                name: event.style.to_string(),
+                ..Default::default()
+            });
+        }
+        let mut entry = EventStrict::new(
+            AssTime(event.start),
+            AssTime(event.end),
+            event.style,
+            event.text,
+        );
+        entry.layer = event.layer.to_string();
+        script.events.entries.push(entry);
+    }
+    script
+}
+
+/// Sets a [Script Info] entry, replacing the line for the key if there is one
+pub(crate) fn set_info(script: &mut AssScript, key: &str, value: &str) {
+    let lines = script
+        .other_sections
+        .entry("Script Info".to_string())
+        .or_default();
+    let line = format!("{key}: {value}");
+    match lines
+        .iter_mut()
+        .find(|x| x.split_once(':').is_some_and(|(k, _)| k.trim() == key))
+    {
+        Some(x) => *x = line,
+        None => lines.push(line),
+    }
+}
+
+/// The fonts of testdata/fonts: `Subass Test` with glyf outlines, `Subass
+/// Test CFF` and the CID-keyed `Subass Test CID`
+pub(crate) fn fonts() -> FontDatabase {
+    let mut fonts = FontDatabase::default();
+    for (file, data) in [
+        (
+            "SubassTest.ttf",
+            &include_bytes!("../testdata/fonts/SubassTest.ttf")[..],
+        ),
+        (
+            "SubassTestCFF.otf",
+            &include_bytes!("../testdata/fonts/SubassTestCFF.otf")[..],
+        ),
+        (
+            "SubassTestCID.otf",
+            &include_bytes!("../testdata/fonts/SubassTestCID.otf")[..],
+        ),
+    ] {
+        fonts.load_data(data.to_vec(), &FontSource::File(file.into()));
+    }
+    fonts
+}
@@ -0,0 +1,301 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Context;
+
+/// A point in time or duration with millisecond precision. ASS itself only
+/// stores centiseconds, other formats need the extra precision. Serialized as
+/// milliseconds, so no precision is lost.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AssTime(pub i64);
+
+impl AssTime {
+    pub const ZERO: Self = Self(0);
+
+    pub fn from_millis(millis: i64) -> Self {
+        Self(millis)
+    }
+
+    pub fn millis(self) -> i64 {
+        self.0
+    }
+
+    /// Parses `H:MM:SS.fff` style timestamps with any number of hour and
+    /// fraction digits, separated by `.` or `,`
+    pub fn parse_clock(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(x) => (true, x),
+            None => (false, s),
+        };
+
+        let (clock, fraction) = match s.find(['.', ',']) {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => (s, ""),
+        };
+
+        let mut parts = clock.rsplit(':');
+        let seconds: i64 = parts.next().context("missing seconds")?.parse()?;
+        let minutes: i64 = parts.next().map_or(Ok(0), str::parse)?;
+        let hours: i64 = parts.next().map_or(Ok(0), str::parse)?;
+        anyhow::ensure!(parts.next().is_none(), "too many components in {s}");
+
+        // scale the fraction to milliseconds regardless of its precision
+        let mut millis = 0;
+        let mut scale = 100;
+        for c in fraction.chars() {
+            let digit = c.to_digit(10).context(format!("invalid fraction in {s}"))?;
+            millis += i64::from(digit) * scale;
+            scale /= 10;
+        }
+
+        let total = ((hours * 60 + minutes) * 60 + seconds) * 1000 + millis;
+        Ok(Self(if negative { -total } else { total }))
+    }
+
+    /// Formats as `HH:MM:SS<sep>mmm`, as used by `SubRip` and `WebVTT`
+    pub fn to_clock_millis(self, separator: char) -> String {
+        let millis = self.0.max(0);
+        format!(
+            "{:02}:{:02}:{:02}{separator}{:03}",
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000
+        )
+    }
+}
+
+impl FromStr for AssTime {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_clock(s)
+    }
+}
+
+/// Formats as `H:MM:SS.cc`, rounding to the nearest centisecond. ASS has no
+/// negative times, so they are clamped to zero.
+impl fmt::Display for AssTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let centis = (self.0.max(0) + 5) / 10;
+        write!(
+            f,
+            "{}:{:02}:{:02}.{:02}",
+            centis / 360_000,
+            centis / 6000 % 60,
+            centis / 100 % 60,
+            centis % 100
+        )
+    }
+}
+
+impl std::ops::Add for AssTime {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl std::ops::Sub for AssTime {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 - rhs.0)
+    }
+}
+
+/// Frames per second as a ratio, so NTSC rates such as 24000/1001 are exact
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRate {
+    pub numerator: i64,
+    pub denominator: i64,
+}
+
+impl FrameRate {
+    /// Highest rate accepted when parsing, well above any video's
+    const MAX_FPS: i64 = 1000;
+    /// Largest denominator accepted when parsing
+    const MAX_DENOMINATOR: i64 = 1_000_000;
+
+    pub const NTSC_FILM: Self = Self {
+        numerator: 24000,
+        denominator: 1001,
+    };
+
+    /// Time frame `frame` starts at, rounded to the millisecond. Frames
+    /// come from files, so the maths is done in `i128` and times that don't
+    /// fit are an error.
+    pub fn frame_to_time(self, frame: i64) -> anyhow::Result<AssTime> {
+        let (numerator, denominator) = (i128::from(self.numerator), i128::from(self.denominator));
+        let scaled = i128::from(frame) * 1000 * denominator;
+        let millis = (2 * scaled + numerator).div_euclid(2 * numerator);
+        Ok(AssTime(
+            i64::try_from(millis).context(format!("frame {frame} is out of range"))?,
+        ))
+    }
+
+    /// Frame shown at `time`, rounded to the nearest frame boundary
+    pub fn time_to_frame(self, time: AssTime) -> anyhow::Result<i64> {
+        let scaled = i128::from(time.0) * i128::from(self.numerator);
+        let divisor = 1000 * i128::from(self.denominator);
+        let frame = (2 * scaled + divisor).div_euclid(2 * divisor);
+        i64::try_from(frame).context(format!("time {}ms is out of range", time.0))
+    }
+}
+
+impl Default for FrameRate {
+    fn default() -> Self {
+        Self::NTSC_FILM
+    }
+}
+
+/// Parses `24000/1001`, `25` or decimal rates. Decimals close to an NTSC
+/// rate such as `23.976` or `29.97` are read as the exact ratio.
+impl FromStr for FrameRate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let rate = if let Some((numerator, denominator)) = s.split_once('/') {
+            Self {
+                numerator: numerator.trim().parse()?,
+                denominator: denominator.trim().parse()?,
+            }
+        } else {
+            let fps: f64 = s.parse().context(format!("invalid frame rate: {s}"))?;
+            #[allow(clippy::cast_precision_loss)]
+            let max = Self::MAX_FPS as f64;
+            anyhow::ensure!(
+                fps.is_finite() && fps > 0.0 && fps <= max,
+                "invalid frame rate: {s}"
+            );
+            // the values are checked to be positive and are small enough for
+            // the casts to be exact
+            #[allow(clippy::cast_possible_truncation)]
+            let ntsc = (fps * 1.001).round() as i64;
+            #[allow(clippy::cast_precision_loss)]
+            let ntsc_fps = ntsc as f64 * 1000.0 / 1001.0;
+            if fps.fract() != 0.0 && (ntsc_fps - fps).abs() < 0.005 {
+                Self {
+                    numerator: ntsc * 1000,
+                    denominator: 1001,
+                }
+            } else {
+                #[allow(clippy::cast_possible_truncation)]
+                let numerator = (fps * 1000.0).round() as i64;
+                Self {
+                    numerator,
+                    denominator: 1000,
+                }
+            }
+        };
+        anyhow::ensure!(
+            (1..=Self::MAX_DENOMINATOR).contains(&rate.denominator)
+                && (1..=Self::MAX_FPS * rate.denominator).contains(&rate.numerator),
+            "invalid frame rate: {s}"
+        );
+        Ok(rate)
+    }
+}
+
+/// Formats as frames per second with up to three decimals, such as `23.976`
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = (self.numerator * 1000 * 2 + self.denominator) / (self.denominator * 2);
+        let formatted = format!("{}.{:03}", millis / 1000, millis % 1000);
+        write!(
+            f,
+            "{}",
+            formatted.trim_end_matches('0').trim_end_matches('.')
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("0:00:02.94", 2940)]
+    #[case("1:02:03.4", 3_723_400)]
+    #[case("00:00:01,001", 1001)]
+    #[case("01:01.500", 61_500)]
+    #[case("-0:00:01.00", -1000)]
+    fn test_time_from_str(#[case] got: &str, #[case] should: i64) {
+        let result = AssTime::from_str(got).unwrap();
+        assert_eq!(result, AssTime(should));
+    }
+
+    #[rstest]
+    #[case(2940, "0:00:02.94")]
+    #[case(3_723_405, "1:02:03.41")]
+    #[case(-10, "0:00:00.00")]
+    fn test_time_to_string(#[case] got: i64, #[case] should: &str) {
+        let result = AssTime(got).to_string();
+        assert_eq!(result, should);
+    }
+
+    #[rstest]
+    #[case("23.976", 24000, 1001)]
+    #[case("29.97", 30000, 1001)]
+    #[case("24000/1001", 24000, 1001)]
+    #[case("25", 25000, 1000)]
+    #[case("12.5", 12500, 1000)]
+    fn test_frame_rate_from_str(
+        #[case] got: &str,
+        #[case] numerator: i64,
+        #[case] denominator: i64,
+    ) {
+        let result = FrameRate::from_str(got).unwrap();
+        assert_eq!(
+            result,
+            FrameRate {
+                numerator,
+                denominator
+            }
+        );
+    }
+
+    #[test]
+    fn test_frame_rate() {
+        let rate = FrameRate::NTSC_FILM;
+        assert_eq!(rate.to_string(), "23.976");
+        assert_eq!(rate.frame_to_time(24).unwrap(), AssTime(1001));
+        assert_eq!(rate.time_to_frame(AssTime(1001)).unwrap(), 24);
+        assert_eq!(rate.time_to_frame(AssTime(1000)).unwrap(), 24);
+        assert!(rate.frame_to_time(9_999_999_999_999).is_ok());
+        assert!(rate.frame_to_time(i64::MAX).is_err());
+        let fast = FrameRate {
+            numerator: i64::MAX,
+            denominator: 1,
+        };
+        assert!(fast.time_to_frame(AssTime(i64::MAX)).is_err());
+        assert_eq!(FrameRate::from_str("25").unwrap().to_string(), "25");
+        assert!(FrameRate::from_str("0").is_err());
+    }
+
+    #[rstest]
+    #[case("inf")]
+    #[case("NaN")]
+    #[case("-25")]
+    #[case("1e300")]
+    #[case("1001")]
+    #[case("9223372036854775807/1")]
+    #[case("24000/0")]
+    #[case("1/9223372036854775807")]
+    fn test_frame_rate_from_str_invalid(#[case] got: &str) {
+        assert!(FrameRate::from_str(got).is_err());
+    }
+
+    #[test]
+    fn test_time_to_clock_millis() {
+        assert_eq!(AssTime(3_723_405).to_clock_millis(','), "01:02:03,405");
+    }
+}
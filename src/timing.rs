@@ -0,0 +1,189 @@
+use crate::event::EventStrict;
+use crate::event::Events;
+use crate::tag;
+use crate::time::AssTime;
+use crate::time::FrameRate;
+
+/// A linear mapping of times, `time * scale + offset`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retiming {
+    pub scale: f64,
+    /// Offset in milliseconds
+    pub offset: f64,
+}
+
+impl Retiming {
+    /// Moves every time by `offset`
+    pub fn shift(offset: AssTime) -> Self {
+        Self {
+            scale: 1.0,
+            // offsets are far below the range where the cast loses precision
+            #[allow(clippy::cast_precision_loss)]
+            offset: offset.millis() as f64,
+        }
+    }
+
+    /// Maps `a.0` to `a.1` and `b.0` to `b.1`, such as the times of the same
+    /// line in two releases
+    pub fn from_points(a: (AssTime, AssTime), b: (AssTime, AssTime)) -> anyhow::Result<Self> {
+        anyhow::ensure!(a.0 != b.0, "reference points must be at different times");
+        #[allow(clippy::cast_precision_loss)]
+        let scale = (b.1 - a.1).millis() as f64 / (b.0 - a.0).millis() as f64;
+        #[allow(clippy::cast_precision_loss)]
+        let offset = a.1.millis() as f64 - a.0.millis() as f64 * scale;
+        Ok(Self { scale, offset })
+    }
+
+    /// Keeps events on the same frames when the video is played at another
+    /// frame rate, such as 25 fps PAL and 23.976 fps releases
+    pub fn from_framerates(from: FrameRate, to: FrameRate) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        let scale =
+            (from.numerator * to.denominator) as f64 / (from.denominator * to.numerator) as f64;
+        Self { scale, offset: 0.0 }
+    }
+
+    pub fn apply(&self, time: AssTime) -> AssTime {
+        // times are far below the range where the casts lose precision
+        #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+        AssTime((time.millis() as f64 * self.scale + self.offset).round() as i64)
+    }
+}
+
+impl Events {
+    /// Shifts the events `select` returns true for by `offset`. See
+    /// [`Events::retime`].
+    pub fn shift(
+        &mut self,
+        offset: AssTime,
+        select: impl FnMut(&EventStrict) -> bool,
+    ) -> anyhow::Result<()> {
+        self.retime(&Retiming::shift(offset), select)
+    }
+
+    /// Retimes the events `select` returns true for. Times before zero are
+    /// clamped, moving the times of `\t`, `\move` and `\fad` that are relative
+    /// to the start so animations stay in place. When stretching, those times
+    /// and karaoke durations are scaled as well.
+    pub fn retime(
+        &mut self,
+        retiming: &Retiming,
+        mut select: impl FnMut(&EventStrict) -> bool,
+    ) -> anyhow::Result<()> {
+        for event in &mut self.entries {
+            if !select(event) {
+                continue;
+            }
+            let start = retiming.apply(event.start_time()?);
+            let end = retiming.apply(event.end_time()?);
+
+            let clipped = (-start.millis()).max(0);
+            #[allow(clippy::float_cmp)]
+            if retiming.scale != 1.0 || clipped > 0 {
+                event.text = retime_tags(&event.text, retiming.scale, clipped);
+            }
+            event.set_start_time(start.max(AssTime::ZERO));
+            event.set_end_time(end.max(AssTime::ZERO));
+        }
+        Ok(())
+    }
+}
+
+/// Scales the times of tags relative to the event start, then moves them
+/// `clipped` milliseconds earlier
+fn retime_tags(text: &str, scale: f64, clipped: i64) -> String {
+    // times are far below the range where the casts lose precision
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    let retime = |value: &str, clip: bool| -> Option<String> {
+        let value: f64 = value.trim().parse().ok()?;
+        let scaled = (value * scale).round() as i64;
+        Some(if clip { scaled - clipped } else { scaled }.to_string())
+    };
+
+    tag::rewrite_tags(text, &mut |tag| {
+        let mut params: Vec<String> = tag.params().iter().map(ToString::to_string).collect();
+        // indexes of the times in the params, and whether they are relative to
+        // the start or durations
+        let times: &[(usize, bool)] = match (tag.name, params.len()) {
+            ("t", 3 | 4) => &[(0, true), (1, true)],
+            ("move", 6) => &[(4, true), (5, true)],
+            ("fade", 7) => &[(3, true), (4, true), (5, true), (6, true)],
+            ("fad", 2) => &[(0, false), (1, false)],
+            ("k" | "K" | "kf" | "ko", 1) => &[(0, false)],
+            _ => return None,
+        };
+        for &(i, clip) in times {
+            params[i] = retime(&params[i], clip)?;
+        }
+        if tag.name == "fad" && clipped > 0 {
+            // the part of the fade in before zero is gone
+            let fade_in: i64 = params[0].parse().ok()?;
+            params[0] = (fade_in - clipped).max(0).to_string();
+        }
+
+        Some(if tag.parens {
+            format!("\\{}({})", tag.name, params.join(","))
+        } else {
+            format!("\\{}{}", tag.name, params.join(","))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::testing::script;
+
+    #[test]
+    fn test_shift() {
+        let mut events = script(&[(1000, 2000, "a"), (5000, 6000, "b")]).events;
+        events.shift(AssTime(-1500), |x| x.text == "a").unwrap();
+        assert_eq!(events.entries[0].start, "0:00:00.00");
+        assert_eq!(events.entries[0].end, "0:00:00.50");
+        assert_eq!(events.entries[1].start, "0:00:05.00");
+    }
+
+    #[test]
+    fn test_shift_keeps_tags() {
+        let mut events = script(&[(1000, 3000, r"{\fad(200,300)\k50}a")]).events;
+        events.shift(AssTime(500), |_| true).unwrap();
+        assert_eq!(events.entries[0].text, r"{\fad(200,300)\k50}a");
+    }
+
+    #[rstest]
+    #[case(r"{\fad(800,300)}a", r"{\fad(300,300)}a")]
+    #[case(r"{\move(0,0,10,10,1000,2000)}a", r"{\move(0,0,10,10,500,1500)}a")]
+    #[case(r"{\t(0,1000,\fs20)}a", r"{\t(-500,500,\fs20)}a")]
+    #[case(r"{\t(\fs20)}a", r"{\t(\fs20)}a")]
+    fn test_clamp_moves_tags(#[case] got: &str, #[case] should: &str) {
+        let mut events = script(&[(500, 3000, got)]).events;
+        events.shift(AssTime(-1000), |_| true).unwrap();
+        assert_eq!(events.entries[0].start, "0:00:00.00");
+        assert_eq!(events.entries[0].text, should);
+    }
+
+    #[test]
+    fn test_from_points() {
+        let retiming = Retiming::from_points(
+            (AssTime(1000), AssTime(2000)),
+            (AssTime(11000), AssTime(22000)),
+        )
+        .unwrap();
+        assert_eq!(retiming.apply(AssTime(6000)), AssTime(12000));
+        assert!(Retiming::from_points((AssTime(0), AssTime(0)), (AssTime(0), AssTime(1))).is_err());
+    }
+
+    #[test]
+    fn test_retime_framerates() {
+        let pal: FrameRate = "25".parse().unwrap();
+        let retiming = Retiming::from_framerates(pal, FrameRate::NTSC_FILM);
+        assert_eq!(retiming.apply(AssTime(24000)), AssTime(25025));
+
+        let mut events = script(&[(24000, 48000, r"{\k100\move(0,0,1,1,0,1000)}a")]).events;
+        events.retime(&retiming, |_| true).unwrap();
+        assert_eq!(events.entries[0].start, "0:00:25.03");
+        assert_eq!(events.entries[0].text, r"{\k104\move(0,0,1,1,0,1043)}a");
+    }
+}
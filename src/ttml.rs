@@ -0,0 +1,289 @@
+use std::str::FromStr;
+
+use crate::common::Boolean;
+use crate::common::Colour;
+use crate::event::EventType;
+use crate::markup;
+use crate::markup::Dialect;
+use crate::srt::TagMode;
+use crate::style::BorderStyle;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::vtt;
+use crate::AssScript;
+
+/// Area of the frame a paragraph is laid out in, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+    /// Numpad alignment of the text inside the region
+    alignment: u8,
+}
+
+impl Region {
+    /// Region for text aligned like `\an`, inside the margins or anchored at
+    /// `\pos` coordinates the same way libass anchors it
+    fn new(
+        alignment: u8,
+        (margin_l, margin_r, margin_v): (f64, f64, f64),
+        position: Option<(f64, f64)>,
+        (res_x, res_y): (f64, f64),
+    ) -> Self {
+        let alignment = alignment.clamp(1, 9);
+        let horizontal = (alignment - 1) % 3;
+        let vertical = (alignment - 1) / 3;
+
+        let ((left, right), (top, bottom)) = match position {
+            Some((x, y)) => (
+                match horizontal {
+                    0 => (x, res_x),
+                    1 => (x - x.min(res_x - x), x + x.min(res_x - x)),
+                    _ => (0.0, x),
+                },
+                match vertical {
+                    0 => (0.0, y),
+                    1 => (y - y.min(res_y - y), y + y.min(res_y - y)),
+                    _ => (y, res_y),
+                },
+            ),
+            None => (
+                (margin_l, res_x - margin_r),
+                match vertical {
+                    1 => (0.0, res_y),
+                    _ => (margin_v, res_y - margin_v),
+                },
+            ),
+        };
+
+        // the values are clamped to the frame, so the casts can't truncate
+        #[allow(clippy::cast_possible_truncation)]
+        let round = |value: f64, max: f64| value.clamp(0.0, max).round() as i64;
+        let (x, y) = (round(left, res_x), round(top, res_y));
+        Self {
+            x,
+            y,
+            width: (round(right, res_x) - x).max(1),
+            height: (round(bottom, res_y) - y).max(1),
+            alignment,
+        }
+    }
+
+    fn element(&self, id: &str) -> String {
+        let display_align = ["after", "center", "before"][usize::from((self.alignment - 1) / 3)];
+        let text_align = ["left", "center", "right"][usize::from((self.alignment - 1) % 3)];
+        format!(
+            "<region xml:id=\"{id}\" tts:origin=\"{}px {}px\" tts:extent=\"{}px {}px\" \
+             tts:displayAlign=\"{display_align}\" tts:textAlign=\"{text_align}\"/>",
+            self.x, self.y, self.width, self.height
+        )
+    }
+}
+
+/// `xml:id` of the TTML style made from a style
+pub fn style_id(name: &str) -> String {
+    format!("s_{}", vtt::style_class(name))
+}
+
+/// Writes the dialogue events of a script as TTML in the IMSC1 text profile.
+/// Styles become `<style>` elements, alignment, margins and `\pos` become
+/// regions and `PlayRes` becomes the pixel extent of the document. Comments
+/// and drawings are dropped.
+pub fn export(script: &AssScript, tags: TagMode) -> anyhow::Result<String> {
+    let (res_x, res_y) = script.play_res();
+    let mut regions: Vec<Region> = Vec::new();
+    let mut paragraphs = Vec::new();
+
+    for event in &script.events.entries {
+        if event.event_type != EventType::Dialogue || tag::plain_text(&event.text).trim().is_empty()
+        {
+            continue;
+        }
+        let style = script.styles.find(&event.style);
+
+        let markup = markup::from_ass(&event.text, Dialect::Ttml);
+        let text = match tags {
+            TagMode::Strip => markup::escape(&tag::plain_text(&event.text)).replace('\n', "<br/>"),
+            TagMode::Translate => markup.text,
+        };
+
+        let alignment = markup
+            .alignment
+            .or_else(|| style.and_then(|x| x.alignment.numpad()))
+            .unwrap_or(2);
+        let region = Region::new(
+            alignment,
+            event.margins(style),
+            markup.position,
+            (res_x, res_y),
+        );
+        let index = regions
+            .iter()
+            .position(|x| *x == region)
+            .unwrap_or_else(|| {
+                regions.push(region);
+                regions.len() - 1
+            });
+
+        let layer: i64 = event.layer.parse().unwrap_or_default();
+        paragraphs.push((
+            event.start_time()?,
+            layer,
+            event.end_time()?,
+            index,
+            event,
+            text,
+        ));
+    }
+    paragraphs.sort_by_key(|(start, layer, ..)| (*start, *layer));
+
+    let lang = script.script_info("Language").unwrap_or_default();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<tt xmlns=\"http://www.w3.org/ns/ttml\" \
+         xmlns:ttp=\"http://www.w3.org/ns/ttml#parameter\" \
+         xmlns:tts=\"http://www.w3.org/ns/ttml#styling\" \
+         ttp:profile=\"http://www.w3.org/ns/ttml/profile/imsc1/text\" \
+         ttp:timeBase=\"media\" tts:extent=\"{res_x}px {res_y}px\" xml:lang=\"{}\">\n",
+        escape_attribute(lang)
+    ));
+    out.push_str("  <head>\n    <styling>\n");
+    for style in &script.styles.entries {
+        out.push_str(&format!("      {}\n", style_element(style)));
+    }
+    out.push_str("    </styling>\n    <layout>\n");
+    for (i, region) in regions.iter().enumerate() {
+        out.push_str(&format!(
+            "      {}\n",
+            region.element(&format!("r{}", i + 1))
+        ));
+    }
+    out.push_str("    </layout>\n  </head>\n  <body>\n    <div>\n");
+
+    for (i, (start, _, end, region, event, text)) in paragraphs.iter().enumerate() {
+        out.push_str(&format!(
+            "      <p xml:id=\"p{}\" begin=\"{}\" end=\"{}\" region=\"r{}\" style=\"{}\">{text}</p>\n",
+            i + 1,
+            start.to_clock_millis('.'),
+            end.to_clock_millis('.'),
+            region + 1,
+            style_id(&event.style)
+        ));
+    }
+    out.push_str("    </div>\n  </body>\n</tt>\n");
+    Ok(out)
+}
+
+fn style_element(style: &StyleStrict) -> String {
+    let mut attributes = vec![format!("xml:id=\"{}\"", style_id(&style.name))];
+    if let Ok(colour) = Colour::from_str(&style.primary_color) {
+        attributes.push(format!("tts:color=\"{}\"", ttml_colour(colour)));
+    }
+    let family = format!("\"{}\"", style.fontname.trim_start_matches('@'));
+    attributes.push(format!("tts:fontFamily=\"{}\"", escape_attribute(&family)));
+    if let Ok(size) = style.fontsize.parse::<f64>() {
+        attributes.push(format!("tts:fontSize=\"{size}px\""));
+    }
+    if style.bold == Boolean::True {
+        attributes.push("tts:fontWeight=\"bold\"".to_string());
+    }
+    if style.italic == Boolean::True {
+        attributes.push("tts:fontStyle=\"italic\"".to_string());
+    }
+    match (&style.underline, &style.strike_out) {
+        (Boolean::True, Boolean::True) => {
+            attributes.push("tts:textDecoration=\"underline lineThrough\"".to_string());
+        }
+        (Boolean::True, _) => attributes.push("tts:textDecoration=\"underline\"".to_string()),
+        (_, Boolean::True) => attributes.push("tts:textDecoration=\"lineThrough\"".to_string()),
+        _ => {}
+    }
+
+    let outline: f64 = style.outline.parse().unwrap_or_default();
+    if let Ok(colour) = Colour::from_str(&style.outline_color) {
+        match style.border_style {
+            // the box is drawn in the outline colour
+            BorderStyle::OpaqueBox => {
+                attributes.push(format!("tts:backgroundColor=\"{}\"", ttml_colour(colour)));
+            }
+            _ if outline > 0.0 => {
+                attributes.push(format!(
+                    "tts:textOutline=\"{} {outline}px\"",
+                    ttml_colour(colour)
+                ));
+            }
+            _ => {}
+        }
+    }
+    format!("<style {}/>", attributes.join(" "))
+}
+
+/// TTML colours are `#RRGGBBAA`, with the alpha as opacity
+fn ttml_colour(colour: Colour) -> String {
+    format!("{}{:02X}", colour.to_html(), 255 - colour.a)
+}
+
+fn escape_attribute(value: &str) -> String {
+    markup::escape(value).replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::event::EventStrict;
+    use crate::time::AssTime;
+
+    #[rstest]
+    #[case(2, None, (10, 10, 1260, 700))]
+    #[case(7, None, (10, 10, 1260, 700))]
+    #[case(5, None, (10, 0, 1260, 720))]
+    #[case(8, Some((320.0, 20.0)), (0, 20, 640, 700))]
+    #[case(3, Some((1000.0, 700.0)), (0, 0, 1000, 700))]
+    fn test_region(
+        #[case] alignment: u8,
+        #[case] position: Option<(f64, f64)>,
+        #[case] should: (i64, i64, i64, i64),
+    ) {
+        let region = Region::new(alignment, (10.0, 10.0, 10.0), position, (1280.0, 720.0));
+        assert_eq!((region.x, region.y, region.width, region.height), should);
+    }
+
+    #[test]
+    fn test_export() {
+        let mut script = AssScript::new();
+        script.styles.entries.push(StyleStrict::default());
+        script.events.entries = vec![
+            EventStrict::new(
+                AssTime(1000),
+                AssTime(2500),
+                "Default",
+                r"{\i1}Hi{\i0}\Nthere & you",
+            ),
+            EventStrict::new(AssTime(0), AssTime(900), "Default", r"{\an8}Top"),
+        ];
+
+        let exported = export(&script, TagMode::Translate).unwrap();
+        assert!(exported.contains("tts:extent=\"1920px 1080px\""));
+        assert!(exported.contains(
+            "<style xml:id=\"s_Default\" tts:color=\"#FFFFFFFF\" \
+             tts:fontFamily=\"&quot;Arial&quot;\" tts:fontSize=\"48px\" \
+             tts:textOutline=\"#000000FF 2px\"/>"
+        ));
+        assert!(exported.contains(
+            "<region xml:id=\"r1\" tts:origin=\"10px 10px\" tts:extent=\"1900px 1060px\" \
+             tts:displayAlign=\"after\" tts:textAlign=\"center\"/>"
+        ));
+        assert!(exported.contains(
+            "<p xml:id=\"p1\" begin=\"00:00:00.000\" end=\"00:00:00.900\" region=\"r2\" \
+             style=\"s_Default\">Top</p>"
+        ));
+        assert!(exported.contains(
+            "<p xml:id=\"p2\" begin=\"00:00:01.000\" end=\"00:00:02.500\" region=\"r1\" \
+             style=\"s_Default\"><span tts:fontStyle=\"italic\">Hi</span><br/>there &amp; you</p>"
+        ));
+    }
+}
@@ -0,0 +1,536 @@
+use std::str::FromStr;
+
+use anyhow::Context;
+
+use crate::common::Boolean;
+use crate::common::Colour;
+use crate::event::EventStrict;
+use crate::event::EventType;
+use crate::markup;
+use crate::markup::Dialect;
+use crate::srt::TagMode;
+use crate::style::StyleStrict;
+use crate::tag;
+use crate::time::AssTime;
+use crate::AssScript;
+
+/// `WebVTT` class name for a style. Class names can't contain whitespace or
+/// dots, or start with a digit.
+pub fn style_class(name: &str) -> String {
+    let class: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if class.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{class}")
+    } else {
+        class
+    }
+}
+
+/// Writes the dialogue events of a script as `WebVTT`. Styles become `::cue`
+/// classes, alignment, margins and `\pos` become cue settings. Comments and
+/// drawings are dropped.
+pub fn export(script: &AssScript, tags: TagMode) -> anyhow::Result<String> {
+    let (res_x, res_y) = script.play_res();
+    let mut colours = Vec::new();
+    let mut cues = Vec::new();
+
+    for event in &script.events.entries {
+        if event.event_type != EventType::Dialogue || tag::plain_text(&event.text).trim().is_empty()
+        {
+            continue;
+        }
+        let style = script.styles.find(&event.style);
+
+        let markup = match tags {
+            TagMode::Strip => markup::Markup {
+                text: markup::escape(&tag::plain_text(&event.text)),
+                ..markup::from_ass(&event.text, Dialect::Vtt)
+            },
+            TagMode::Translate => markup::from_ass(&event.text, Dialect::Vtt),
+        };
+        for colour in &markup.colours {
+            if tags == TagMode::Translate && !colours.contains(colour) {
+                colours.push(*colour);
+            }
+        }
+
+        let alignment = markup
+            .alignment
+            .or_else(|| style.and_then(|x| x.alignment.numpad()))
+            .unwrap_or(2);
+        let margins = event.margins(style);
+        let settings = cue_settings(alignment, margins, markup.position, (res_x, res_y));
+
+        let mut text = format!("<c.{}>{}</c>", style_class(&event.style), markup.text);
+        if !event.name.is_empty() {
+            text = format!("<v {}>{text}", markup::escape(&event.name));
+        }
+        let layer: i64 = event.layer.parse().unwrap_or_default();
+        cues.push((
+            event.start_time()?,
+            layer,
+            event.end_time()?,
+            settings,
+            text,
+        ));
+    }
+    // cues must be ordered by start time
+    cues.sort_by_key(|(start, layer, ..)| (*start, *layer));
+
+    let mut out = String::from("WEBVTT\n\nSTYLE\n");
+    for style in &script.styles.entries {
+        out.push_str(&style_rule(style, res_y));
+    }
+    for colour in colours {
+        out.push_str(&format!(
+            "::cue(.{}) {{ color: {}; }}\n",
+            markup::colour_class(colour),
+            colour.to_html()
+        ));
+    }
+    out.push_str("::cue(.strike) { text-decoration: line-through; }\n\n");
+
+    for (i, (start, _, end, settings, text)) in cues.iter().enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}{settings}\n{text}\n\n",
+            i + 1,
+            start.to_clock_millis('.'),
+            end.to_clock_millis('.')
+        ));
+    }
+    Ok(out)
+}
+
+fn percent(value: f64) -> String {
+    let formatted = format!("{:.2}", value.clamp(0.0, 100.0));
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    format!("{trimmed}%")
+}
+
+fn cue_settings(
+    alignment: u8,
+    (margin_l, margin_r, margin_v): (f64, f64, f64),
+    position: Option<(f64, f64)>,
+    (res_x, res_y): (f64, f64),
+) -> String {
+    let horizontal = (alignment.clamp(1, 9) - 1) % 3;
+    let vertical = (alignment.clamp(1, 9) - 1) / 3;
+    let line_align = ["end", "center", "start"][usize::from(vertical)];
+    let position_align = ["line-left", "center", "line-right"][usize::from(horizontal)];
+    let text_align = ["left", "center", "right"][usize::from(horizontal)];
+
+    let (line, position) = match position {
+        Some((x, y)) => (Some(100.0 * y / res_y), Some(100.0 * x / res_x)),
+        None => (
+            match vertical {
+                0 => Some(100.0 * (res_y - margin_v) / res_y),
+                1 => Some(50.0),
+                _ => Some(100.0 * margin_v / res_y),
+            },
+            match horizontal {
+                0 => Some(100.0 * margin_l / res_x),
+                1 => None,
+                _ => Some(100.0 * (res_x - margin_r) / res_x),
+            },
+        ),
+    };
+
+    let mut settings = String::new();
+    if let Some(x) = line {
+        settings.push_str(&format!(" line:{},{line_align}", percent(x)));
+    }
+    if let Some(x) = position {
+        settings.push_str(&format!(" position:{},{position_align}", percent(x)));
+    }
+    if horizontal != 1 {
+        settings.push_str(&format!(" align:{text_align}"));
+    }
+    settings
+}
+
+fn style_rule(style: &StyleStrict, res_y: f64) -> String {
+    let mut declarations = Vec::new();
+    if let Ok(colour) = Colour::from_str(&style.primary_color) {
+        declarations.push(format!("color: {}", css_colour(colour)));
+    }
+    declarations.push(format!(
+        "font-family: \"{}\"",
+        style.fontname.trim_start_matches('@')
+    ));
+    if let Ok(size) = style.fontsize.parse::<f64>() {
+        declarations.push(format!("font-size: {:.2}vh", 100.0 * size / res_y));
+    }
+    if style.bold == Boolean::True {
+        declarations.push("font-weight: bold".to_string());
+    }
+    if style.italic == Boolean::True {
+        declarations.push("font-style: italic".to_string());
+    }
+    match (&style.underline, &style.strike_out) {
+        (Boolean::True, Boolean::True) => {
+            declarations.push("text-decoration: underline line-through".to_string());
+        }
+        (Boolean::True, _) => declarations.push("text-decoration: underline".to_string()),
+        (_, Boolean::True) => declarations.push("text-decoration: line-through".to_string()),
+        _ => {}
+    }
+    format!(
+        "::cue(.{}) {{ {}; }}\n",
+        style_class(&style.name),
+        declarations.join("; ")
+    )
+}
+
+fn css_colour(colour: Colour) -> String {
+    if colour.a == 0 {
+        return colour.to_html();
+    }
+    let opacity = f64::from(255 - colour.a) / 255.0;
+    format!(
+        "rgba({}, {}, {}, {opacity:.3})",
+        colour.r, colour.g, colour.b
+    )
+}
+
+fn parse_css_colour(value: &str) -> Option<Colour> {
+    let value = value.trim();
+    if value.starts_with('#') {
+        return Colour::from_html(value).ok();
+    }
+    let args = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let parts: Vec<&str> = args.split(',').map(str::trim).collect();
+    let channel = |i: usize| parts.get(i)?.parse::<u8>().ok();
+    let opacity = parts.get(3).map_or(Some(1.0), |x| x.parse::<f64>().ok())?;
+    Some(Colour {
+        r: channel(0)?,
+        g: channel(1)?,
+        b: channel(2)?,
+        // the value is clamped, so the cast can't truncate
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        a: (255.0 - opacity.clamp(0.0, 1.0) * 255.0).round() as u8,
+    })
+}
+
+/// Reads a `WebVTT` file. `::cue` class rules in STYLE blocks become styles
+/// based on `style`, and cues wrapped in such a class use that style.
+pub fn import(input: &str, style: &StyleStrict) -> anyhow::Result<AssScript> {
+    let input = input.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    anyhow::ensure!(input.starts_with("WEBVTT"), "missing WEBVTT header");
+
+    let mut script = AssScript::new();
+    let (_, res_y) = script.play_res();
+    let mut styles: Vec<StyleStrict> = Vec::new();
+    let mut events = Vec::new();
+
+    // blocks are separated by blank lines, the first is the header
+    for block in input.split("\n\n").skip(1) {
+        let block = block.trim_matches('\n');
+        if block.is_empty() || block.starts_with("NOTE") || block.starts_with("REGION") {
+            continue;
+        }
+        if let Some(css) = block.strip_prefix("STYLE") {
+            styles.extend(parse_style_block(css, style, res_y));
+            continue;
+        }
+
+        let mut lines = block.lines();
+        let mut timing = lines.next().unwrap_or_default();
+        if !timing.contains("-->") {
+            // the first line was a cue identifier
+            timing = lines.next().unwrap_or_default();
+        }
+        let Some((start, rest)) = timing.split_once("-->") else {
+            continue;
+        };
+        let mut rest = rest.split_whitespace();
+        let start = AssTime::parse_clock(start).context(format!("invalid cue timing: {timing}"))?;
+        let end = rest.next().unwrap_or_default();
+        let end = AssTime::parse_clock(end).context(format!("invalid cue timing: {timing}"))?;
+        let settings: Vec<&str> = rest.collect();
+
+        let payload = lines.collect::<Vec<_>>().join("\n");
+        events.push(parse_cue(
+            start, end, &settings, &payload, style, &styles, res_y,
+        ));
+    }
+
+    if events.iter().any(|x| x.style == style.name) && !styles.iter().any(|x| x.name == style.name)
+    {
+        styles.insert(0, style.clone());
+    }
+    script.styles.entries = styles;
+    script.events.entries = events;
+    Ok(script)
+}
+
+fn parse_style_block(css: &str, base: &StyleStrict, res_y: f64) -> Vec<StyleStrict> {
+    let mut styles = Vec::new();
+    let mut rest = css;
+
+    while let Some(start) = rest.find("::cue(.") {
+        let after = &rest[start + 7..];
+        let (Some(close), Some(open), Some(end)) =
+            (after.find(')'), after.find('{'), after.find('}'))
+        else {
+            break;
+        };
+        rest = &after[end + 1..];
+
+        let name = &after[..close];
+        if name.starts_with("color_") || name == "strike" || open > end {
+            continue;
+        }
+
+        let mut style = StyleStrict {
+            name: name.to_string(),
+            ..base.clone()
+        };
+        for declaration in after[open + 1..end].split(';') {
+            let Some((property, value)) = declaration.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match property.trim() {
+                "color" => {
+                    if let Some(x) = parse_css_colour(value) {
+                        style.primary_color = x.to_string();
+                    }
+                }
+                "font-family" => {
+                    let family = value.split(',').next().unwrap_or_default();
+                    style.fontname = family.trim().trim_matches(['"', '\'']).to_string();
+                }
+                "font-size" => {
+                    if let Some(x) = value.strip_suffix("vh").and_then(|x| x.parse::<f64>().ok()) {
+                        style.fontsize = format!("{}", (x * res_y / 100.0).round());
+                    }
+                }
+                "font-weight" => {
+                    let bold = value == "bold" || value.parse::<u32>().is_ok_and(|x| x >= 700);
+                    style.bold = if bold { Boolean::True } else { Boolean::False };
+                }
+                "font-style" => {
+                    style.italic = if value == "italic" {
+                        Boolean::True
+                    } else {
+                        Boolean::False
+                    };
+                }
+                "text-decoration" => {
+                    if value.contains("underline") {
+                        style.underline = Boolean::True;
+                    }
+                    if value.contains("line-through") {
+                        style.strike_out = Boolean::True;
+                    }
+                }
+                _ => {}
+            }
+        }
+        styles.push(style);
+    }
+
+    styles
+}
+
+fn parse_cue(
+    start: AssTime,
+    end: AssTime,
+    settings: &[&str],
+    payload: &str,
+    default: &StyleStrict,
+    styles: &[StyleStrict],
+    res_y: f64,
+) -> EventStrict {
+    let mut payload = payload.trim();
+    let mut name = String::new();
+    let mut style = default;
+
+    // a leading voice gives the speaker
+    if let Some(rest) = payload.strip_prefix("<v") {
+        if let Some(close) = rest.find('>') {
+            let voice = rest[..close].trim_start_matches(|c: char| c != ' ');
+            name = voice.trim().to_string();
+            payload = rest[close + 1..].trim_start();
+        }
+    }
+    // a class wrapping the whole cue gives the style
+    if let Some(rest) = payload
+        .strip_prefix("<c.")
+        .filter(|_| wrapped_in_class(payload))
+    {
+        if let (Some(close), Some(inner)) = (rest.find('>'), payload.strip_suffix("</c>")) {
+            let class = rest[..close].split('.').next().unwrap_or_default();
+            if let Some(x) = styles.iter().find(|x| x.name == class) {
+                style = x;
+                payload = &inner[3 + close + 1..];
+            }
+        }
+    }
+
+    let mut line = None;
+    let mut line_align = "start";
+    let mut horizontal = 1;
+    for setting in settings {
+        let Some((key, value)) = setting.split_once(':') else {
+            continue;
+        };
+        let (value, align) = value.split_once(',').unwrap_or((value, ""));
+        match key {
+            "line" => {
+                line = value
+                    .strip_suffix('%')
+                    .and_then(|x| x.parse::<f64>().ok())
+                    .or_else(|| {
+                        // line numbers count from the top, negative ones from the bottom
+                        value
+                            .parse::<i32>()
+                            .ok()
+                            .map(|x| if x < 0 { 100.0 } else { 0.0 })
+                    });
+                if !align.is_empty() {
+                    line_align = if align == "end" { "end" } else { "start" };
+                }
+            }
+            "align" => {
+                horizontal = match value {
+                    "left" | "start" => 0,
+                    "right" | "end" => 2,
+                    _ => 1,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    let (vertical, margin_v) = match line {
+        None => (0, None),
+        Some(x) if x < 100.0 / 3.0 => (2, Some(x)),
+        Some(x) if x > 200.0 / 3.0 => (0, Some(if line_align == "end" { 100.0 - x } else { 0.0 })),
+        Some(_) => (1, None),
+    };
+    let alignment = vertical * 3 + horizontal + 1;
+
+    let mut text = markup::to_ass(payload, Dialect::Vtt);
+    if style.alignment.numpad() != Some(alignment) {
+        text = format!("{{\\an{alignment}}}{text}");
+    }
+
+    let mut event = EventStrict::new(start, end, &style.name, &text);
+    event.name = name;
+    if let Some(x) = margin_v.filter(|x| *x > 0.0) {
+        event.margin_v = format!("{}", (x * res_y / 100.0).round());
+    }
+    event
+}
+
+/// Whether the `<c>` the payload starts with is only closed at its end
+fn wrapped_in_class(payload: &str) -> bool {
+    let mut depth = 0;
+    for (i, _) in payload.match_indices('<') {
+        let rest = &payload[i..];
+        if rest.starts_with("<c.") || rest.starts_with("<c>") {
+            depth += 1;
+        } else if rest.starts_with("</c>") {
+            depth -= 1;
+            if depth == 0 {
+                return i + 4 == payload.len();
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    const SCRIPT: &str = r"[Script Info]
+PlayResX: 640
+PlayResY: 360
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Roboto Medium,36,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,1.3,0,2,20,20,36,0
+Style: On Top,Arial,18,&H800000FF,&H000000FF,&H00000000,&H00000000,-1,0,0,0,100,100,0,0,1,1.3,0,8,20,20,18,0
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:02.94,0:00:06.52,Default,Levi,0,0,0,,Hey! {\i1}You{\i0} <okay>?
+Dialogue: 0,0:00:01.00,0:00:02.00,On Top,,0,0,0,,{\c&H00FF00&}Sign
+Comment: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Note";
+
+    #[test]
+    fn test_export() {
+        let script = AssScript::try_from_str(SCRIPT).unwrap();
+        let exported = export(&script, TagMode::Translate).unwrap();
+        assert_eq!(
+            exported,
+            r#"WEBVTT
+
+STYLE
+::cue(.Default) { color: #FFFFFF; font-family: "Roboto Medium"; font-size: 10.00vh; }
+::cue(.On_Top) { color: rgba(255, 0, 0, 0.498); font-family: "Arial"; font-size: 5.00vh; font-weight: bold; }
+::cue(.color_00FF00) { color: #00FF00; }
+::cue(.strike) { text-decoration: line-through; }
+
+1
+00:00:01.000 --> 00:00:02.000 line:5%,start
+<c.On_Top><c.color_00FF00>Sign</c></c>
+
+2
+00:00:02.940 --> 00:00:06.520 line:90%,end
+<v Levi><c.Default>Hey! <i>You</i> &lt;okay&gt;?</c>
+
+"#
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let script = AssScript::try_from_str(SCRIPT).unwrap();
+        let exported = export(&script, TagMode::Translate).unwrap();
+        let imported = import(&exported, &StyleStrict::default()).unwrap();
+
+        let names: Vec<_> = imported
+            .styles
+            .entries
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Default", "On_Top"]);
+        assert_eq!(imported.styles.entries[1].bold, Boolean::True);
+        assert_eq!(imported.styles.entries[1].primary_color, "&H800000FF");
+
+        let events = &imported.events.entries;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].style, "On_Top");
+        assert_eq!(events[0].text, r"{\an8}{\c&H00FF00&}Sign{\c}");
+        assert_eq!(events[1].style, "Default");
+        assert_eq!(events[1].name, "Levi");
+        assert_eq!(events[1].text, r"Hey! {\i1}You{\i0} <okay>?");
+        assert_eq!(events[1].margin_v, "108");
+        assert_eq!(events[1].start, "0:00:02.94");
+    }
+
+    #[rstest]
+    #[case("Default", "Default")]
+    #[case("On Top", "On_Top")]
+    #[case("1st.sign", "_1st_sign")]
+    fn test_style_class(#[case] got: &str, #[case] should: &str) {
+        assert_eq!(style_class(got), should);
+    }
+}
@@ -0,0 +1,457 @@
+#!/usr/bin/env python3
+"""Generates the fonts used by the tests.
+
+The glyphs are simple shapes drawn for these tests, not letters from an
+existing font. The script and the fonts it writes are dedicated to the public
+domain under CC0 1.0. Run from this directory:
+
+    python3 make_fonts.py
+
+Writes three fonts with the same glyphs, cmap and GSUB:
+
+- SubassTest.ttf: TrueType outlines, with a composite glyph
+- SubassTestCFF.otf: a name-keyed CFF font, one glyph calls a local subr
+- SubassTestCID.otf: a CID-keyed CFF font, as CJK fonts usually are
+
+and SubassTest.mkv, a Matroska file with no tracks that has SubassTest.ttf
+and SubassTestCFF.otf attached.
+"""
+
+import struct
+
+ASCENT, DESCENT = 800, 200
+
+
+def rect(x0, y0, x1, y1, hole=False):
+    points = [(x0, y0), (x0, y1), (x1, y1), (x1, y0)]
+    if hole:
+        points.reverse()
+    return [(x, y, True) for x, y in points]
+
+
+def ellipse(x0, y0, x1, y1, hole=False):
+    cx, cy = (x0 + x1) // 2, (y0 + y1) // 2
+    points = [
+        (cx, y0, True), (x0, y0, False), (x0, cy, True), (x0, y1, False),
+        (cx, y1, True), (x1, y1, False), (x1, cy, True), (x1, y0, False),
+    ]
+    if hole:
+        points = [points[0]] + points[:0:-1]
+    return points
+
+
+# name, advance, contours of (x, y, on curve) or a list of component glyphs
+GLYPHS = [
+    (".notdef", 500, [rect(50, 0, 450, 700), rect(100, 50, 400, 650, hole=True)]),
+    ("space", 250, []),
+    ("I", 250, [rect(50, 0, 200, 700)]),
+    ("A", 520, [
+        [(0, 0, True), (250, 700, True), (500, 0, True)],
+        [(180, 150, True), (320, 150, True), (250, 350, True)],
+    ]),
+    ("B", 520, [[
+        (0, 0, True), (0, 700, True), (300, 700, True), (500, 525, False),
+        (300, 350, True), (500, 175, False), (300, 0, True),
+    ]]),
+    ("O", 520, [ellipse(0, 0, 500, 700), ellipse(120, 120, 380, 580, hole=True)]),
+    # only reachable through the vert feature
+    ("A.vert", 720, [[(0, 0, True), (0, 500, True), (700, 250, True)]]),
+    # only reachable as a component of Adieresis
+    ("dieresis", 500, [rect(100, 750, 200, 800), rect(300, 750, 400, 800)]),
+    ("Adieresis", 520, ["A", "dieresis"]),
+    # only reachable through the liga feature, from I I
+    ("I_I", 500, [rect(50, 0, 200, 700), rect(300, 0, 450, 700)]),
+]
+NAMES = [x[0] for x in GLYPHS]
+GID = {name: i for i, name in enumerate(NAMES)}
+CMAP = {0x20: "space", 0x41: "A", 0x42: "B", 0x49: "I", 0x4F: "O", 0xC4: "Adieresis"}
+
+
+def contours(glyph):
+    _, _, shape = GLYPHS[GID[glyph]]
+    if shape and isinstance(shape[0], str):
+        return [c for component in shape for c in contours(component)]
+    return shape
+
+
+def bbox(glyph):
+    points = [p for c in contours(glyph) for p in c]
+    if not points:
+        return (0, 0, 0, 0)
+    xs, ys = [p[0] for p in points], [p[1] for p in points]
+    return (min(xs), min(ys), max(xs), max(ys))
+
+
+def u16(*x):
+    return struct.pack(f">{len(x)}H", *x)
+
+
+def i16(*x):
+    return struct.pack(f">{len(x)}h", *x)
+
+
+def u32(*x):
+    return struct.pack(f">{len(x)}I", *x)
+
+
+# --- TrueType outlines
+
+
+def glyf_glyph(name):
+    _, _, shape = GLYPHS[GID[name]]
+    if not shape:
+        return b""
+    x0, y0, x1, y1 = bbox(name)
+    if isinstance(shape[0], str):
+        out = i16(-1, x0, y0, x1, y1)
+        for i, component in enumerate(shape):
+            # ARG_1_AND_2_ARE_WORDS | ARGS_ARE_XY_VALUES, MORE_COMPONENTS
+            flags = 0x0003 | (0x0020 if i + 1 < len(shape) else 0)
+            out += u16(flags, GID[component]) + i16(0, 0)
+        return out
+    out = i16(len(shape), x0, y0, x1, y1)
+    end = -1
+    for contour in shape:
+        end += len(contour)
+        out += u16(end)
+    out += u16(0)
+    points = [p for c in shape for p in c]
+    out += bytes(1 if on else 0 for _, _, on in points)
+    x = y = 0
+    xs, ys = b"", b""
+    for px, py, _ in points:
+        xs += i16(px - x)
+        ys += i16(py - y)
+        x, y = px, py
+    return out + xs + ys
+
+
+def glyf_tables():
+    glyf, loca = b"", []
+    for name in NAMES:
+        loca.append(len(glyf))
+        glyf += glyf_glyph(name)
+        glyf += b"\0" * (-len(glyf) % 4)
+    loca.append(len(glyf))
+    points = max(sum(len(c) for c in contours(n)) for n in NAMES)
+    maxp = u32(0x00010000) + u16(len(NAMES), points, 2, points, 3, 2, 0, 0, 0, 0, 0, 0, 2, 1)
+    return {b"glyf": glyf, b"loca": u32(*loca), b"maxp": maxp}
+
+
+# --- CFF outlines
+
+
+def cs_number(v):
+    if -107 <= v <= 107:
+        return bytes([v + 139])
+    if 108 <= v <= 1131:
+        v -= 108
+        return bytes([(v >> 8) + 247, v & 0xFF])
+    if -1131 <= v <= -108:
+        v = -v - 108
+        return bytes([(v >> 8) + 251, v & 0xFF])
+    return b"\x1c" + i16(v)
+
+
+def charstring_path(glyph):
+    """Type 2 operators drawing a glyph, quadratic curves made cubic"""
+    out = b""
+    x = y = 0
+    for contour in contours(glyph):
+        start = contour[0]
+        out += cs_number(start[0] - x) + cs_number(start[1] - y) + b"\x15"  # rmoveto
+        x, y = start[0], start[1]
+        i = 1
+        points = contour[1:] + [start]
+        while i <= len(points):
+            p = points[i - 1]
+            if p[2]:
+                out += cs_number(p[0] - x) + cs_number(p[1] - y) + b"\x05"  # rlineto
+                x, y = p[0], p[1]
+                i += 1
+            else:
+                end = points[i]
+                c1 = (x + 2 * (p[0] - x) / 3, y + 2 * (p[1] - y) / 3)
+                c2 = (end[0] + 2 * (p[0] - end[0]) / 3, end[1] + 2 * (p[1] - end[1]) / 3)
+                c1, c2 = (round(c1[0]), round(c1[1])), (round(c2[0]), round(c2[1]))
+                out += b"".join(cs_number(v) for v in (
+                    c1[0] - x, c1[1] - y, c2[0] - c1[0], c2[1] - c1[1],
+                    end[0] - c2[0], end[1] - c2[1],
+                )) + b"\x08"  # rrcurveto
+                x, y = end[0], end[1]
+                i += 2
+    return out
+
+
+def cff_index(items):
+    if not items:
+        return u16(0)
+    offsets = [1]
+    for item in items:
+        offsets.append(offsets[-1] + len(item))
+    size = 1 if offsets[-1] < 0x100 else 2 if offsets[-1] < 0x10000 else 4
+    out = u16(len(items)) + bytes([size])
+    for offset in offsets:
+        out += offset.to_bytes(size, "big")
+    return out + b"".join(items)
+
+
+def dict_int(v):
+    return b"\x1d" + struct.pack(">i", v)
+
+
+def cff_table(cid):
+    # 'I' is drawn by a local subr, to check subrs survive subsetting
+    subrs = [charstring_path("I") + b"\x0b"]  # return
+    charstrings = []
+    for name in NAMES:
+        if name == "I":
+            charstrings.append(cs_number(-107) + b"\x0a\x0e")  # callsubr endchar
+        else:
+            charstrings.append(charstring_path(name) + b"\x0e")  # endchar
+
+    if cid:
+        strings = [b"Adobe", b"Identity"]
+        charset = b"\x02" + u16(1, len(NAMES) - 2)
+        fdselect = b"\x03" + u16(1) + u16(0) + b"\x00" + u16(len(NAMES))
+    else:
+        strings = [x.encode() for x in NAMES[1:]]
+        charset = b"\x00" + u16(*range(391, 391 + len(NAMES) - 1))
+        fdselect = b""
+
+    private = dict_int(6) + b"\x13"  # Subrs, right after the 6 byte dict
+    local_subrs = cff_index(subrs)
+    name = b"SubassTestCID" if cid else b"SubassTestCFF"
+
+    def top_dict(offsets):
+        out = b""
+        if cid:
+            out += dict_int(391) + dict_int(392) + dict_int(0) + b"\x0c\x1e"  # ROS
+            out += dict_int(len(NAMES)) + b"\x0c\x22"  # CIDCount
+        out += dict_int(offsets["charset"]) + b"\x0f"
+        out += dict_int(offsets["charstrings"]) + b"\x11"
+        if cid:
+            out += dict_int(offsets["fdarray"]) + b"\x0c\x24"
+            out += dict_int(offsets["fdselect"]) + b"\x0c\x25"
+        else:
+            out += dict_int(len(private)) + dict_int(offsets["private"]) + b"\x12"
+        return out
+
+    def font_dict(offsets):
+        return dict_int(len(private)) + dict_int(offsets["private"]) + b"\x12"
+
+    # every offset is written as a 5 byte integer, so sizes don't depend on
+    # the values and the layout can be worked out with zeros first
+    zeros = dict.fromkeys(["charset", "charstrings", "fdarray", "fdselect", "private"], 0)
+    head = b"\x01\x00\x04\x04" + cff_index([name])
+    offset = len(head) + len(cff_index([top_dict(zeros)]))
+    offset += len(cff_index(strings)) + len(cff_index([]))
+    offsets = {"charset": offset}
+    offset += len(charset)
+    offsets["fdselect"] = offset
+    offset += len(fdselect)
+    offsets["charstrings"] = offset
+    offset += len(cff_index(charstrings))
+    offsets["fdarray"] = offset
+    if cid:
+        offset += len(cff_index([font_dict(zeros)]))
+    offsets["private"] = offset
+
+    out = head + cff_index([top_dict(offsets)]) + cff_index(strings) + cff_index([])
+    out += charset + fdselect + cff_index(charstrings)
+    if cid:
+        out += cff_index([font_dict(offsets)])
+    out += private + local_subrs
+    assert len(out) == offsets["private"] + len(private) + len(local_subrs)
+    return {b"CFF ": out, b"maxp": u32(0x00005000) + u16(len(NAMES))}
+
+
+# --- shared tables
+
+
+def coverage(glyphs):
+    return u16(1, len(glyphs), *glyphs)
+
+
+def gsub():
+    # vert: A -> A.vert, single substitution format 2
+    single = u16(2, 8, 1, GID["A.vert"]) + coverage([GID["A"]])
+    # liga: I I -> I_I
+    ligature = u16(GID["I_I"], 2, GID["I"])
+    ligature_set = u16(1, 4) + ligature
+    ligatures = u16(1, 8, 1, 14) + coverage([GID["I"]]) + ligature_set
+    lookups = [u16(1, 0, 1, 8) + single, u16(4, 0, 1, 8) + ligatures]
+    lookup_list = u16(len(lookups))
+    offset = 2 + 2 * len(lookups)
+    for lookup in lookups:
+        lookup_list += u16(offset)
+        offset += len(lookup)
+    lookup_list += b"".join(lookups)
+
+    features = [(b"liga", [1]), (b"vert", [0])]
+    feature_list = u16(len(features))
+    tables = b""
+    offset = 2 + 6 * len(features)
+    for tag, indexes in features:
+        table = u16(0, len(indexes), *indexes)
+        feature_list += tag + u16(offset + len(tables))
+        tables += table
+    feature_list += tables
+
+    lang_sys = u16(0, 0xFFFF, len(features), *range(len(features)))
+    script = u16(4, 0) + lang_sys
+    script_list = u16(1) + b"DFLT" + u16(8) + script
+
+    header = 10
+    return (
+        u32(0x00010000)
+        + u16(header, header + len(script_list), header + len(script_list) + len(feature_list))
+        + script_list
+        + feature_list
+        + lookup_list
+    )
+
+
+def cmap():
+    chars = sorted(CMAP)
+    segments = [(c, c, GID[CMAP[c]]) for c in chars] + [(0xFFFF, 0xFFFF, 0)]
+    n = len(segments)
+    selector = n.bit_length() - 1
+    search = 2 << selector
+    body = u16(4, 16 + 8 * n, 0, 2 * n, search, selector, 2 * n - search)
+    body += u16(*[s[1] for s in segments]) + u16(0) + u16(*[s[0] for s in segments])
+    body += u16(*[(s[2] - s[0]) & 0xFFFF for s in segments]) + u16(*[0] * n)
+    return u16(0, 1, 3, 1) + u32(12) + body
+
+
+def name_table(family, postscript):
+    records = [(1, family), (2, "Regular"), (3, postscript + ";1.000"), (4, family), (6, postscript)]
+    header, strings = u16(0, len(records), 6 + 12 * len(records)), b""
+    for name_id, value in records:
+        data = value.encode("utf-16-be")
+        header += u16(3, 1, 0x409, name_id, len(data), len(strings))
+        strings += data
+    return header + strings
+
+
+def common_tables(family, postscript):
+    boxes = [bbox(n) for n in NAMES]
+    advances = [x[1] for x in GLYPHS]
+    head = (
+        u32(0x00010000, 0x00010000, 0, 0x5F0F3CF5)
+        + u16(3, 1000)
+        + b"\0" * 16
+        + i16(
+            min(b[0] for b in boxes), min(b[1] for b in boxes),
+            max(b[2] for b in boxes), max(b[3] for b in boxes),
+        )
+        + u16(0, 8)
+        + i16(2, 1, 0)
+    )
+    hhea = (
+        u32(0x00010000)
+        + i16(ASCENT, -DESCENT, 0)
+        + u16(max(advances))
+        + i16(0, 0, max(b[2] for b in boxes), 1, 0, 0, 0, 0, 0, 0, 0)
+        + u16(len(NAMES))
+    )
+    hmtx = b"".join(u16(a) + i16(b[0]) for a, b in zip(advances, boxes))
+    os2 = (
+        u16(4)
+        + i16(sum(advances) // len(advances))
+        + u16(400, 5, 0)
+        + i16(*[0] * 10)
+        + i16(0)
+        + b"\0" * 10
+        + u32(1, 0, 0, 0)
+        + b"NONE"
+        + u16(0x40, min(CMAP), max(CMAP))
+        + i16(ASCENT, -DESCENT, 0)
+        + u16(ASCENT, DESCENT)
+        + u32(1, 0)
+        + i16(500, 700)
+        + u16(0, 0x20, 2)
+    )
+    post = u32(0x00030000, 0) + i16(-100, 50) + u32(0, 0, 0, 0, 0)
+    return {
+        b"head": head,
+        b"hhea": hhea,
+        b"hmtx": hmtx,
+        b"OS/2": os2,
+        b"post": post,
+        b"cmap": cmap(),
+        b"name": name_table(family, postscript),
+        b"GSUB": gsub(),
+    }
+
+
+def checksum(data):
+    data += b"\0" * (-len(data) % 4)
+    return sum(struct.unpack(f">{len(data) // 4}I", data)) & 0xFFFFFFFF
+
+
+def write_font(path, version, tables):
+    tags = sorted(tables)
+    n = len(tags)
+    selector = n.bit_length() - 1
+    search = 16 << selector
+    out = u32(version) + u16(n, search, selector, n * 16 - search)
+    offset = 12 + 16 * n
+    head_offset = None
+    for tag in tags:
+        data = tables[tag]
+        if tag == b"head":
+            head_offset = offset
+        out += tag + u32(checksum(data), offset, len(data))
+        offset += len(data) + (-len(data) % 4)
+    for tag in tags:
+        out += tables[tag] + b"\0" * (-len(tables[tag]) % 4)
+    adjustment = (0xB1B0AFBA - checksum(out)) & 0xFFFFFFFF
+    out = out[: head_offset + 8] + u32(adjustment) + out[head_offset + 12 :]
+    with open(path, "wb") as f:
+        f.write(out)
+
+
+def ebml(element_id, payload):
+    n = len(payload)
+    length = next(x for x in range(1, 9) if n < (1 << (7 * x)) - 1)
+    size = (n | (1 << (7 * length))).to_bytes(length, "big")
+    return element_id.to_bytes((element_id.bit_length() + 7) // 8, "big") + size + payload
+
+
+def write_mkv(path, fonts):
+    header = ebml(0x1A45DFA3, ebml(0x4282, b"matroska") + ebml(0x4287, b"\x04"))
+    info = ebml(0x1549A966, ebml(0x2AD7B1, (1000000).to_bytes(3, "big")))
+    files = b""
+    for uid, (name, mime) in enumerate(fonts, 1):
+        with open(name, "rb") as f:
+            data = f.read()
+        files += ebml(0x61A7, ebml(0x466E, name.encode()) + ebml(0x4660, mime.encode())
+                      + ebml(0x465C, data) + ebml(0x46AE, bytes([uid])))
+    # the segment has an unknown size, as files being written live do
+    segment = b"\x18\x53\x80\x67\x01\xff\xff\xff\xff\xff\xff\xff" + info + ebml(0x1941A469, files)
+    with open(path, "wb") as f:
+        f.write(header + segment)
+
+
+def main():
+    write_font(
+        "SubassTest.ttf",
+        0x00010000,
+        {**common_tables("Subass Test", "SubassTest"), **glyf_tables()},
+    )
+    write_font(
+        "SubassTestCFF.otf",
+        0x4F54544F,
+        {**common_tables("Subass Test CFF", "SubassTestCFF"), **cff_table(cid=False)},
+    )
+    write_font(
+        "SubassTestCID.otf",
+        0x4F54544F,
+        {**common_tables("Subass Test CID", "SubassTestCID"), **cff_table(cid=True)},
+    )
+    write_mkv("SubassTest.mkv", [("SubassTest.ttf", "font/ttf"), ("SubassTestCFF.otf", "font/otf")])
+
+
+if __name__ == "__main__":
+    main()